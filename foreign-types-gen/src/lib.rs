@@ -0,0 +1,342 @@
+//! Generates `foreign_type!` declarations from bindgen output.
+//!
+//! Large C libraries often expose hundreds of opaque types, each with a destructor and possibly a
+//! copy function following a naming convention such as `FOO_free` and `FOO_dup`. This crate scans
+//! the `extern` blocks of a bindgen-generated file for such functions and writes out the
+//! corresponding `foreign_type!` declarations, typically from a build script:
+//!
+//! ```no_run
+//! use foreign_types_gen::Generator;
+//! use std::env;
+//! use std::path::Path;
+//!
+//! let out_dir = env::var("OUT_DIR").unwrap();
+//! Generator::new("foo_sys")
+//!     .strip_prefix("foo_")
+//!     .sync_send(true)
+//!     .generate_file(
+//!         Path::new(&out_dir).join("bindings.rs"),
+//!         Path::new(&out_dir).join("types.rs"),
+//!     )
+//!     .unwrap();
+//! ```
+//!
+//! The generated file can then be included with `include!(concat!(env!("OUT_DIR"), "/types.rs"));`.
+//!
+//! A type is generated for every function named `<prefix><free suffix>` taking a single `*mut T`
+//! argument and returning nothing. If a function named `<prefix><dup suffix>` taking a single
+//! pointer to `T` and returning `*mut T` also exists, it is used as the type's `fn clone`.
+//!
+//! Some libraries have several destructors for one type, such as OpenSSL's `BN_free` and
+//! `BN_clear_free`. The one whose prefix also names a constructor, a function named
+//! `<prefix><new suffix>` taking no arguments and returning `*mut T`, is preferred. Any remaining
+//! tie is broken in favor of the shortest name, and then the alphabetically first one. Constructors
+//! are otherwise not used: `foreign_type!` has no constructor item, and whether calling one is safe
+//! cannot be told from its signature. Two C types which would be given the same Rust name are
+//! reported as an error, and can be told apart with `Generator::rename`.
+#![warn(missing_docs)]
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use syn::{FnArg, ForeignItem, Item, ReturnType, Type};
+
+/// An error encountered while generating declarations.
+#[derive(Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.0)
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// A generator of `foreign_type!` declarations.
+pub struct Generator {
+    sys_path: String,
+    foreign_types_path: String,
+    free_suffix: String,
+    dup_suffix: String,
+    new_suffix: String,
+    strip_prefixes: Vec<String>,
+    renames: HashMap<String, String>,
+    sync_send: bool,
+}
+
+struct Decl {
+    ctype: String,
+    free: String,
+    dup: Option<String>,
+}
+
+impl Generator {
+    /// Creates a new generator for bindings which will be referenced through `sys_path`.
+    ///
+    /// `sys_path` is the path at which the bindings are visible from the generated file, such as
+    /// the name of the sys crate or `super::ffi`.
+    pub fn new(sys_path: &str) -> Generator {
+        Generator {
+            sys_path: sys_path.to_string(),
+            foreign_types_path: "foreign_types".to_string(),
+            free_suffix: "_free".to_string(),
+            dup_suffix: "_dup".to_string(),
+            new_suffix: "_new".to_string(),
+            strip_prefixes: vec![],
+            renames: HashMap::new(),
+            sync_send: false,
+        }
+    }
+
+    /// Sets the path to the `foreign-types` crate.
+    ///
+    /// Defaults to `foreign_types`.
+    pub fn foreign_types_path(&mut self, path: &str) -> &mut Generator {
+        self.foreign_types_path = path.to_string();
+        self
+    }
+
+    /// Sets the suffix identifying destructor functions.
+    ///
+    /// Defaults to `_free`.
+    pub fn free_suffix(&mut self, suffix: &str) -> &mut Generator {
+        self.free_suffix = suffix.to_string();
+        self
+    }
+
+    /// Sets the suffix identifying copy functions.
+    ///
+    /// Defaults to `_dup`.
+    pub fn dup_suffix(&mut self, suffix: &str) -> &mut Generator {
+        self.dup_suffix = suffix.to_string();
+        self
+    }
+
+    /// Sets the suffix identifying constructor functions.
+    ///
+    /// Defaults to `_new`.
+    pub fn new_suffix(&mut self, suffix: &str) -> &mut Generator {
+        self.new_suffix = suffix.to_string();
+        self
+    }
+
+    /// Adds a prefix to strip from C type names before converting them to Rust type names.
+    ///
+    /// The first matching prefix is stripped.
+    pub fn strip_prefix(&mut self, prefix: &str) -> &mut Generator {
+        self.strip_prefixes.push(prefix.to_string());
+        self
+    }
+
+    /// Overrides the Rust type name generated for the C type `ctype`.
+    pub fn rename(&mut self, ctype: &str, name: &str) -> &mut Generator {
+        self.renames.insert(ctype.to_string(), name.to_string());
+        self
+    }
+
+    /// Determines if the generated types are declared `Sync + Send`.
+    ///
+    /// Defaults to `false`.
+    pub fn sync_send(&mut self, sync_send: bool) -> &mut Generator {
+        self.sync_send = sync_send;
+        self
+    }
+
+    /// Generates declarations from the source of a bindgen-generated file.
+    pub fn generate(&self, bindings: &str) -> Result<String, Error> {
+        let file = syn::parse_file(bindings)
+            .map_err(|e| Error(format!("error parsing bindings: {}", e)))?;
+
+        let mut fns = BTreeMap::new();
+        let mut ctors = BTreeMap::new();
+        for item in &file.items {
+            let foreign_mod = match item {
+                Item::ForeignMod(foreign_mod) => foreign_mod,
+                _ => continue,
+            };
+            for item in &foreign_mod.items {
+                if let ForeignItem::Fn(f) = item {
+                    let name = f.sig.ident.to_string();
+                    if let Some(ret) = constructor_signature(&f.sig) {
+                        ctors.insert(name, ret);
+                    } else if let Some(sig) = pointer_signature(&f.sig) {
+                        fns.insert(name, sig);
+                    }
+                }
+            }
+        }
+
+        // Destructors keyed by C type, with the preferred destructor of each type sorting first.
+        let mut frees = BTreeMap::new();
+        for (name, (arg, ret)) in &fns {
+            let prefix = match name.strip_suffix(&self.free_suffix) {
+                Some(prefix) => prefix,
+                None => continue,
+            };
+            if ret.is_some() {
+                continue;
+            }
+            let new_name = format!("{}{}", prefix, self.new_suffix);
+            let has_new = ctors.get(&new_name) == Some(arg);
+            let key = (!has_new, name.len(), name);
+            frees
+                .entry(arg)
+                .or_insert_with(BTreeMap::new)
+                .insert(key, prefix);
+        }
+
+        let mut decls = BTreeMap::new();
+        for (arg, candidates) in frees {
+            let (&(_, _, name), prefix) = candidates.iter().next().unwrap();
+            let dup_name = format!("{}{}", prefix, self.dup_suffix);
+            let dup = match fns.get(&dup_name) {
+                Some((dup_arg, Some(dup_ret))) if dup_arg == arg && dup_ret == arg => {
+                    Some(dup_name)
+                }
+                _ => None,
+            };
+            let decl = Decl {
+                ctype: arg.clone(),
+                free: name.clone(),
+                dup,
+            };
+            match decls.entry(self.rust_name(arg)) {
+                Entry::Vacant(entry) => {
+                    entry.insert(decl);
+                }
+                Entry::Occupied(entry) => {
+                    return Err(Error(format!(
+                        "C types `{}` and `{}` would both be named `{}`; use `rename` to tell \
+                         them apart",
+                        entry.get().ctype,
+                        arg,
+                        entry.key()
+                    )));
+                }
+            }
+        }
+
+        let mut out = String::new();
+        out.push_str("// This file was generated by foreign-types-gen. Do not edit.\n\n");
+        out.push_str(&format!("{}::foreign_type! {{\n", self.foreign_types_path));
+        for (i, (name, decl)) in decls.iter().enumerate() {
+            if i != 0 {
+                out.push('\n');
+            }
+            self.write_decl(&mut out, name, decl);
+        }
+        out.push_str("}\n");
+        Ok(out)
+    }
+
+    /// Generates declarations from the bindgen-generated file at `input`, writing them to `output`.
+    pub fn generate_file<P, Q>(&self, input: P, output: Q) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let bindings = fs::read_to_string(input)?;
+        let out = self.generate(&bindings)?;
+        fs::write(output, out)
+    }
+
+    fn write_decl(&self, out: &mut String, name: &str, decl: &Decl) {
+        let oibits = if self.sync_send { ": Sync + Send" } else { "" };
+        out.push_str(&format!("    /// A wrapper over `{}`.\n", decl.ctype));
+        out.push_str(&format!("    pub unsafe type {}{} {{\n", name, oibits));
+        out.push_str(&format!(
+            "        type CType = {}::{};\n",
+            self.sys_path, decl.ctype
+        ));
+        out.push_str(&format!(
+            "        fn drop = {}::{};\n",
+            self.sys_path, decl.free
+        ));
+        if let Some(dup) = &decl.dup {
+            out.push_str(&format!("        fn clone = {}::{};\n", self.sys_path, dup));
+        }
+        out.push_str("    }\n");
+    }
+
+    fn rust_name(&self, ctype: &str) -> String {
+        if let Some(name) = self.renames.get(ctype) {
+            return name.clone();
+        }
+
+        let stripped = self
+            .strip_prefixes
+            .iter()
+            .find_map(|prefix| ctype.strip_prefix(&**prefix))
+            .unwrap_or(ctype);
+        camel_case(stripped)
+    }
+}
+
+/// Returns the pointee type name of the return value of a function, if the function has the shape
+/// of a constructor.
+fn constructor_signature(sig: &syn::Signature) -> Option<String> {
+    if !sig.inputs.is_empty() {
+        return None;
+    }
+    match &sig.output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, ty) => pointee(ty),
+    }
+}
+
+/// Returns the pointee type names of the single argument and the return value of a function, if
+/// the function has the shape of a destructor or copy function.
+fn pointer_signature(sig: &syn::Signature) -> Option<(String, Option<String>)> {
+    if sig.inputs.len() != 1 {
+        return None;
+    }
+    let arg = match &sig.inputs[0] {
+        FnArg::Typed(arg) => pointee(&arg.ty)?,
+        FnArg::Receiver(_) => return None,
+    };
+    let ret = match &sig.output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, ty) => Some(pointee(ty)?),
+    };
+    Some((arg, ret))
+}
+
+fn pointee(ty: &Type) -> Option<String> {
+    let ptr = match ty {
+        Type::Ptr(ptr) => ptr,
+        _ => return None,
+    };
+    match &*ptr.elem {
+        Type::Path(path) if path.qself.is_none() => {
+            path.path.segments.last().map(|s| s.ident.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn camel_case(s: &str) -> String {
+    let mut out = String::new();
+    for part in s.split('_').filter(|p| !p.is_empty()) {
+        let mut chars = part.chars();
+        let first = chars.next().unwrap();
+        out.extend(first.to_uppercase());
+        if part.chars().all(|c| !c.is_lowercase()) {
+            out.extend(chars.flat_map(char::to_lowercase));
+        } else {
+            out.extend(chars);
+        }
+    }
+    out
+}
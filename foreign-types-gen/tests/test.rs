@@ -0,0 +1,107 @@
+use foreign_types_gen::Generator;
+
+const BINDINGS: &str = r#"
+#[repr(C)]
+pub struct foo_thing {
+    _unused: [u8; 0],
+}
+#[repr(C)]
+pub struct SSL_CTX {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn foo_thing_new() -> *mut foo_thing;
+    pub fn foo_thing_free(thing: *mut foo_thing);
+    pub fn foo_thing_dup(thing: *const foo_thing) -> *mut foo_thing;
+    pub fn SSL_CTX_free(ctx: *mut SSL_CTX);
+    pub fn SSL_CTX_get_flags(ctx: *mut SSL_CTX) -> ::std::os::raw::c_int;
+    pub fn foo_string_free(s: *mut ::std::os::raw::c_char, len: usize);
+}
+"#;
+
+#[test]
+fn generate() {
+    let out = Generator::new("sys")
+        .strip_prefix("foo_")
+        .sync_send(true)
+        .generate(BINDINGS)
+        .unwrap();
+
+    assert_eq!(
+        out,
+        "\
+// This file was generated by foreign-types-gen. Do not edit.
+
+foreign_types::foreign_type! {
+    /// A wrapper over `SSL_CTX`.
+    pub unsafe type SslCtx: Sync + Send {
+        type CType = sys::SSL_CTX;
+        fn drop = sys::SSL_CTX_free;
+    }
+
+    /// A wrapper over `foo_thing`.
+    pub unsafe type Thing: Sync + Send {
+        type CType = sys::foo_thing;
+        fn drop = sys::foo_thing_free;
+        fn clone = sys::foo_thing_dup;
+    }
+}
+"
+    );
+}
+
+#[test]
+fn rename() {
+    let out = Generator::new("sys")
+        .rename("SSL_CTX", "SslContext")
+        .generate(BINDINGS)
+        .unwrap();
+
+    assert!(out.contains("pub unsafe type SslContext {"));
+    assert!(out.contains("pub unsafe type FooThing {"));
+}
+
+#[test]
+fn prefers_destructor_with_constructor() {
+    let bindings = r#"
+extern "C" {
+    pub fn BN_clear_free(bn: *mut BIGNUM);
+    pub fn BN_free(bn: *mut BIGNUM);
+    pub fn BN_new() -> *mut BIGNUM;
+    pub fn BN_dup(bn: *const BIGNUM) -> *mut BIGNUM;
+    pub fn X_free(x: *mut X);
+    pub fn X_secure_free(x: *mut X);
+}
+"#;
+
+    let out = Generator::new("sys").generate(bindings).unwrap();
+    assert!(out.contains("fn drop = sys::BN_free;\n        fn clone = sys::BN_dup;\n"));
+    assert!(!out.contains("BN_clear_free"));
+    // Without a constructor, the shortest name is used.
+    assert!(out.contains("fn drop = sys::X_free;\n"));
+    assert!(!out.contains("X_secure_free"));
+}
+
+#[test]
+fn name_collision() {
+    let bindings = r#"
+extern "C" {
+    pub fn foo_thing_free(thing: *mut foo_thing);
+    pub fn FOO_THING_free(thing: *mut FOO_THING);
+}
+"#;
+
+    let err = Generator::new("sys").generate(bindings).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "C types `FOO_THING` and `foo_thing` would both be named `FooThing`; use `rename` to tell \
+         them apart"
+    );
+
+    let out = Generator::new("sys")
+        .rename("FOO_THING", "UpperFooThing")
+        .generate(bindings)
+        .unwrap();
+    assert!(out.contains("pub unsafe type FooThing {"));
+    assert!(out.contains("pub unsafe type UpperFooThing {"));
+}
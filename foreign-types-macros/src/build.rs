@@ -1,11 +1,46 @@
 use proc_macro2::TokenStream;
-use quote::quote;
-use syn::{Ident, Path};
+use quote::{format_ident, quote};
+use syn::punctuated::Punctuated;
+use syn::{GenericParam, Generics, Ident, Path, Token, Type};
 
-use crate::parse::{ForeignType, Input};
+use crate::parse::{ForeignMap, ForeignType, Input, Oibit, RefType, StaticRef};
 
 fn ref_name(input: &ForeignType) -> Ident {
-    Ident::new(&format!("{}Ref", input.name), input.name.span())
+    input.ref_name.clone()
+}
+
+// Values created by `from_ptr_const` cannot be recorded by the `track-ownership`, `testing` and
+// `trace` features, so when any of them is enabled each value carries a flag telling its
+// destructor whether it was.
+fn tracked_field(input: &ForeignType) -> Option<syn::Index> {
+    if cfg!(any(
+        feature = "track-ownership",
+        feature = "testing",
+        feature = "trace"
+    )) {
+        Some(syn::Index::from(1 + input.dtor.is_some() as usize))
+    } else {
+        None
+    }
+}
+
+fn build_tracked(input: &ForeignType, tracked: bool) -> Option<TokenStream> {
+    tracked_field(input).map(|_| quote!(, #tracked))
+}
+
+fn build_if_tracked(input: &ForeignType, value: TokenStream, body: TokenStream) -> TokenStream {
+    match tracked_field(input) {
+        Some(index) if !body.is_empty() => quote! {
+            if #value.#index {
+                #body
+            }
+        },
+        _ => body,
+    }
+}
+
+fn has_impl(input: &ForeignType, impl_: &str) -> bool {
+    input.impls.iter().any(|i| i.is_ident(impl_))
 }
 
 pub fn build(input: Input) -> TokenStream {
@@ -13,32 +48,291 @@ pub fn build(input: Input) -> TokenStream {
         .types
         .iter()
         .map(|t| build_foreign_type(&input.crate_, t));
-    quote! {
+    let refs = input.refs.iter().map(|r| build_ref_type(&input.crate_, r));
+    let statics = input
+        .statics
+        .iter()
+        .map(|s| build_static_ref(&input.crate_, s));
+    let maps = input
+        .maps
+        .iter()
+        .map(|m| build_foreign_map(&input.crate_, m));
+    let items = quote! {
         #(#types)*
+        #(#refs)*
+        #(#statics)*
+        #(#maps)*
+    };
+
+    match &input.module {
+        Some(module) => {
+            let attrs = &module.attrs;
+            let vis = &module.visibility;
+            let name = &module.name;
+            quote! {
+                #(#attrs)*
+                #vis mod #name {
+                    #[allow(unused_imports)]
+                    use super::*;
+
+                    #items
+                }
+            }
+        }
+        None => items,
+    }
+}
+
+fn build_ref_type(crate_: &Path, input: &RefType) -> TokenStream {
+    let attrs = &input.attrs;
+    let vis = &input.visibility;
+    let name = &input.name;
+    let generics = &input.generics;
+    let ctype = &input.ctype;
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+    let phantom_data = build_phantom_type(crate_, &input.phantom_data, &input.oibits)
+        .map(|d| quote!(, #crate_::export::PhantomData<#d>));
+    let oibits = input
+        .oibits
+        .iter()
+        .map(|oibit| build_oibit_impl(crate_, name, generics, ctype, oibit));
+    // The check cannot name the type's generic parameters.
+    let ctype_check = if generics.params.is_empty() {
+        let message = format!("the `CType` of `{}` must not need to be dropped", name);
+        quote! {
+            const _: () = assert!(!#crate_::export::needs_drop::<#ctype>(), #message);
+        }
+    } else {
+        quote!()
+    };
+
+    quote! {
+        #(#attrs)*
+        #vis struct #name #generics(#crate_::Opaque #phantom_data);
+
+        #ctype_check
+
+        #(#oibits)*
+
+        unsafe impl #impl_generics #crate_::ForeignTypeRef for #name #ty_generics {
+            type CType = #ctype;
+        }
+
+        impl #impl_generics #name #ty_generics {
+            /// Constructs a shared instance of this type from its raw type in a `const` context.
+            ///
+            /// This is equivalent to `ForeignTypeRef::from_ptr`.
+            ///
+            /// # Safety
+            ///
+            /// `ptr` must be a valid, immutable, instance of the type for the returned lifetime.
+            #[inline]
+            #vis const unsafe fn from_ptr_const<'__ptr>(ptr: *mut #ctype) -> &'__ptr Self {
+                &*ptr.cast::<Self>()
+            }
+        }
+    }
+}
+
+fn build_foreign_map(crate_: &Path, input: &ForeignMap) -> TokenStream {
+    let attrs = &input.attrs;
+    let vis = &input.visibility;
+    let name = &input.name;
+    let functions = Ident::new(&format!("{}Functions", name), name.span());
+    let key = &input.key;
+    let value = &input.value;
+    let ctype = &input.ctype;
+    let new = &input.new;
+    let drop = &input.drop;
+    let insert_takes = input.insert_takes;
+    let insert = &input.insert;
+    let get = &input.get;
+    let len = &input.len;
+    let for_each = &input.for_each;
+    let doc = format!("The C functions backing [`{}`].", name);
+    let key_ctype = quote!(<#key as #crate_::ForeignType>::CType);
+    let value_ctype = quote!(<#value as #crate_::ForeignType>::CType);
+
+    quote! {
+        #[doc = #doc]
+        #vis enum #functions {}
+
+        unsafe impl #crate_::map::MapFunctions for #functions {
+            type CType = #ctype;
+            type Key = #key;
+            type Value = #value;
+
+            const INSERT_TAKES_OWNERSHIP: bool = #insert_takes;
+
+            #[inline]
+            unsafe fn new() -> *mut #ctype {
+                (#new)()
+            }
+
+            #[inline]
+            unsafe fn free(map: *mut #ctype) {
+                (#drop)(map)
+            }
+
+            #[inline]
+            unsafe fn insert(map: *mut #ctype, key: *mut #key_ctype, value: *mut #value_ctype) {
+                (#insert)(map, key, value)
+            }
+
+            #[inline]
+            unsafe fn get(map: *mut #ctype, key: *mut #key_ctype) -> *mut #value_ctype {
+                (#get)(map, key)
+            }
+
+            #[inline]
+            #[allow(clippy::useless_conversion)]
+            unsafe fn len(map: *mut #ctype) -> usize {
+                // A negative length is treated as an empty map rather than wrapping around.
+                <usize as #crate_::export::TryFrom<_>>::try_from((#len)(map)).unwrap_or(0)
+            }
+
+            #[inline]
+            unsafe fn for_each(
+                map: *mut #ctype,
+                f: &mut dyn FnMut(*mut #key_ctype, *mut #value_ctype),
+            ) {
+                (#for_each)(map, f)
+            }
+        }
+
+        #(#attrs)*
+        #vis type #name = #crate_::map::ForeignMap<#functions>;
+    }
+}
+
+fn build_static_ref(crate_: &Path, input: &StaticRef) -> TokenStream {
+    let attrs = &input.attrs;
+    let vis = &input.visibility;
+    let name = &input.name;
+    let ty = &input.ty;
+    let init = &input.init;
+    let ctype = quote!(<#ty as #crate_::ForeignTypeRef>::CType);
+    let message = format!("`{}` is null", name);
+
+    quote! {
+        #(#attrs)*
+        #[allow(non_camel_case_types)]
+        #vis struct #name {
+            __private: (),
+        }
+
+        #[doc(hidden)]
+        #vis static #name: #name = #name { __private: () };
+
+        impl #crate_::export::Deref for #name {
+            type Target = #ty;
+
+            #[inline]
+            fn deref(&self) -> &'static #ty {
+                static PTR: #crate_::export::AtomicPtr<#ctype> =
+                    #crate_::export::AtomicPtr::new(#crate_::export::null_mut());
+
+                let mut ptr = PTR.load(#crate_::export::Ordering::Acquire);
+                if ptr.is_null() {
+                    ptr = #init;
+                    assert!(!ptr.is_null(), #message);
+                    PTR.store(ptr, #crate_::export::Ordering::Release);
+                }
+                unsafe { #crate_::ForeignTypeRef::from_ptr(ptr) }
+            }
+        }
     }
 }
 
 fn build_foreign_type(crate_: &Path, input: &ForeignType) -> TokenStream {
     let decls = build_decls(crate_, input);
+    let ctype_check = build_ctype_check(crate_, input);
     let oibits = build_oibits(crate_, input);
     let foreign_impls = build_foreign_impls(crate_, input);
     let drop_impl = build_drop_impl(crate_, input);
+    let drop_many_impl = build_drop_many_impl(crate_, input);
+    let close_impls = build_close_impls(crate_, input);
     let deref_impls = build_deref_impls(crate_, input);
     let borrow_impls = build_borrow_impls(crate_, input);
     let as_ref_impls = build_as_ref_impls(crate_, input);
+    let raw_impls = build_raw_impls(crate_, input);
     let clone_impl = build_clone_impl(crate_, input);
     let to_owned_impl = build_to_owned_impl(crate_, input);
+    let immutable_impls = build_immutable_impls(crate_, input);
+    let testing_impl = build_testing_impl(crate_, input);
+    let const_impls = build_const_impls(crate_, input);
+    let toll_free_bridged_impls = build_toll_free_bridged_impls(crate_, input);
+    let core_foundation_impls = build_core_foundation_impls(crate_, input);
+    let glib_impls = build_glib_impls(crate_, input);
+    let defmt_impls = build_defmt_impls(crate_, input);
+    let py_capsule_impls = build_py_capsule_impls(crate_, input);
+    let debug_impls = build_debug_impls(crate_, input);
+    let handle_impls = build_handle_impls(crate_, input);
+    let dynamic_impls = build_dynamic_impls(crate_, input);
+    let dtor_impls = build_dtor_impls(crate_, input);
+    let array_impls = build_array_impls(crate_, input);
+    let pool_impls = build_pool_impls(crate_, input);
+    let ctype_const_impls = build_ctype_const_impls(crate_, input);
+    let getter_impls = build_getter_impls(crate_, input);
+    let setter_impls = build_setter_impls(crate_, input);
+    let ref_counted_impl = build_ref_counted_impl(crate_, input);
+    let last_error_impls = build_last_error_impls(crate_, input);
+    let from_bytes_impls = build_from_bytes_impls(crate_, input);
+    let from_str_impls = build_from_str_impls(crate_, input);
+    let cmp_impls = build_cmp_impls(crate_, input);
+    let io_impls = build_io_impls(crate_, input);
+    let error_object_impls = build_error_object_impls(crate_, input);
+    let pop_impls = build_pop_impls(crate_, input);
+    let bytes_impls = build_bytes_impls(crate_, input);
+    let edit_impls = build_edit_impls(crate_, input);
+    let compat_impls = input
+        .impls
+        .iter()
+        .filter(|i| i.segments.len() > 1)
+        .map(|i| build_compat_impls(crate_, input, i));
 
     quote! {
         #decls
+        #ctype_check
         #oibits
         #foreign_impls
         #drop_impl
+        #drop_many_impl
+        #close_impls
         #deref_impls
         #borrow_impls
         #as_ref_impls
+        #raw_impls
         #clone_impl
         #to_owned_impl
+        #immutable_impls
+        #testing_impl
+        #const_impls
+        #toll_free_bridged_impls
+        #core_foundation_impls
+        #glib_impls
+        #defmt_impls
+        #py_capsule_impls
+        #debug_impls
+        #handle_impls
+        #dynamic_impls
+        #dtor_impls
+        #array_impls
+        #pool_impls
+        #ctype_const_impls
+        #getter_impls
+        #setter_impls
+        #ref_counted_impl
+        #last_error_impls
+        #from_bytes_impls
+        #from_str_impls
+        #cmp_impls
+        #io_impls
+        #error_object_impls
+        #pop_impls
+        #bytes_impls
+        #edit_impls
+        #(#compat_impls)*
     }
 }
 
@@ -48,26 +342,109 @@ fn build_decls(crate_: &Path, input: &ForeignType) -> TokenStream {
     let name = &input.name;
     let generics = &input.generics;
     let ctype = &input.ctype;
-    let phantom_data = input
-        .phantom_data
-        .as_ref()
+    let phantom_data = build_phantom_type(crate_, &input.phantom_data, &input.oibits)
         .map(|d| quote!(, #crate_::export::PhantomData<#d>));
     let ref_name = ref_name(input);
     let ref_docs = format!(
-        "A borrowed reference to a [`{name}`](struct.{}.html).",
+        "A borrowed reference to a [`{name}`](struct.{name}.html).",
         name = name
     );
+    let (repr, dtor) = match &input.dtor {
+        Some(dtor) => (quote!(), quote!(, #dtor)),
+        None if tracked_field(input).is_some() => (quote!(), quote!()),
+        None => (quote!(#[repr(transparent)]), quote!()),
+    };
+    let tracked = tracked_field(input).map(|_| quote!(, bool));
+    let must_use = if input.must_use && !attrs.iter().any(|a| a.path.is_ident("must_use")) {
+        quote!(#[must_use])
+    } else {
+        quote!()
+    };
 
     quote! {
         #(#attrs)*
-        #[repr(transparent)]
-        #vis struct #name #generics(#crate_::export::NonNull<#ctype> #phantom_data);
+        #must_use
+        #repr
+        #vis struct #name #generics(
+            #crate_::export::NonNull<#ctype> #dtor #tracked #phantom_data
+        );
 
         #[doc = #ref_docs]
         #vis struct #ref_name #generics(#crate_::Opaque #phantom_data);
     }
 }
 
+fn build_ctype_check(crate_: &Path, input: &ForeignType) -> TokenStream {
+    // The check cannot name the type's generic parameters.
+    if !input.generics.params.is_empty() {
+        return quote!();
+    }
+    let ctype = &input.ctype;
+    let message = format!(
+        "the `CType` of `{}` must not need to be dropped",
+        input.name
+    );
+
+    quote! {
+        const _: () = assert!(!#crate_::export::needs_drop::<#ctype>(), #message);
+    }
+}
+
+fn build_const_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = &input.name;
+    let vis = &input.visibility;
+    let ctype = &input.ctype;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let phantom_data = build_phantom_type(crate_, &input.phantom_data, &input.oibits)
+        .map(|_| quote!(, #crate_::export::PhantomData));
+    let dtor = input.dtor.as_ref().map(|_| {
+        let drop = &input.drop;
+        quote!(, #drop)
+    });
+    let tracked = build_tracked(input, false);
+
+    quote! {
+        impl #impl_generics #name #ty_generics {
+            /// Constructs an instance of this type from its raw type in a `const` context.
+            ///
+            /// This is equivalent to `ForeignType::from_ptr`, except that the value is not recorded
+            /// by the `track-ownership`, `testing` and `trace` Cargo features, which also ignore it
+            /// when it is dropped.
+            ///
+            /// # Safety
+            ///
+            /// `ptr` must be a valid, owned instance of the native type.
+            #[inline]
+            #vis const unsafe fn from_ptr_const(ptr: *mut #ctype) -> #name #ty_generics {
+                #name(#crate_::export::NonNull::new_unchecked(ptr) #dtor #tracked #phantom_data)
+            }
+
+            /// Returns a raw pointer to the wrapped value in a `const` context.
+            ///
+            /// This is equivalent to `ForeignType::as_ptr`.
+            #[inline]
+            #vis const fn as_ptr_const(&self) -> *mut #ctype {
+                self.0.as_ptr()
+            }
+        }
+
+        impl #impl_generics #ref_name #ty_generics {
+            /// Constructs a shared instance of this type from its raw type in a `const` context.
+            ///
+            /// This is equivalent to `ForeignTypeRef::from_ptr`.
+            ///
+            /// # Safety
+            ///
+            /// `ptr` must be a valid, immutable, instance of the type for the returned lifetime.
+            #[inline]
+            #vis const unsafe fn from_ptr_const<'__ptr>(ptr: *mut #ctype) -> &'__ptr Self {
+                &*ptr.cast::<Self>()
+            }
+        }
+    }
+}
+
 fn build_oibits(crate_: &Path, input: &ForeignType) -> TokenStream {
     let oibits = input.oibits.iter().map(|t| build_oibit(crate_, input, t));
 
@@ -76,14 +453,77 @@ fn build_oibits(crate_: &Path, input: &ForeignType) -> TokenStream {
     }
 }
 
-fn build_oibit(crate_: &Path, input: &ForeignType, oibit: &Ident) -> TokenStream {
-    let name = &input.name;
+fn build_oibit(crate_: &Path, input: &ForeignType, oibit: &Oibit) -> TokenStream {
     let ref_name = ref_name(input);
-    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let owned = build_oibit_impl(crate_, &input.name, &input.generics, &input.ctype, oibit);
+    let borrowed = build_oibit_impl(crate_, &ref_name, &input.generics, &input.ctype, oibit);
+
+    quote! {
+        #owned
+        #borrowed
+    }
+}
 
+fn build_oibit_impl(
+    crate_: &Path,
+    name: &Ident,
+    generics: &Generics,
+    ctype: &Type,
+    oibit: &Oibit,
+) -> TokenStream {
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+    let trait_ = &oibit.name;
+
+    // Suppressed traits are handled by `build_phantom_type`.
+    if oibit.negative.is_some() {
+        return quote!();
+    }
+    if oibit.is("UnwindSafe") || oibit.is("RefUnwindSafe") {
+        return quote! {
+            impl #impl_generics #crate_::export::#trait_ for #name #ty_generics {}
+        };
+    }
+    if !oibit.is("auto") {
+        return quote! {
+            unsafe impl #impl_generics #crate_::export::#trait_ for #name #ty_generics {}
+        };
+    }
+
+    // A bound on a concrete type is rejected unless it holds, so the `CType` is wrapped in a type
+    // which is generic over a lifetime to defer the check until the impl is used.
     quote! {
-        unsafe impl #impl_generics #crate_::export::#oibit for #name #ty_generics {}
-        unsafe impl #impl_generics #crate_::export::#oibit for #ref_name #ty_generics {}
+        unsafe impl #impl_generics #crate_::export::Send for #name #ty_generics
+        where
+            for<'__ctype> #crate_::export::AutoTraits<'__ctype, #ctype>: #crate_::export::Send,
+        {
+        }
+        unsafe impl #impl_generics #crate_::export::Sync for #name #ty_generics
+        where
+            for<'__ctype> #crate_::export::AutoTraits<'__ctype, #ctype>: #crate_::export::Sync,
+        {
+        }
+    }
+}
+
+/// Returns the type of the `PhantomData` field, which includes markers for suppressed traits.
+fn build_phantom_type(
+    crate_: &Path,
+    phantom_data: &Option<Type>,
+    oibits: &Punctuated<Oibit, Token![+]>,
+) -> Option<TokenStream> {
+    let mut types = phantom_data.iter().map(|d| quote!(#d)).collect::<Vec<_>>();
+    for oibit in oibits.iter().filter(|o| o.negative.is_some()) {
+        if oibit.name == "UnwindSafe" {
+            types.push(quote!(&'static mut ()));
+        } else {
+            types.push(quote!(#crate_::export::UnsafeCell<()>));
+        }
+    }
+
+    match types.len() {
+        0 => None,
+        1 => types.pop(),
+        _ => Some(quote!((#(#types),*))),
     }
 }
 
@@ -92,138 +532,469 @@ fn build_foreign_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
     let ctype = &input.ctype;
     let ref_name = ref_name(input);
     let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
-    let phantom_data = input
-        .phantom_data
-        .as_ref()
+    let phantom_data = build_phantom_type(crate_, &input.phantom_data, &input.oibits)
         .map(|_| quote!(, #crate_::export::PhantomData));
+    let tracked = build_tracked(input, true);
+    let from_ptr = match &input.dtor {
+        Some(_) => {
+            let drop = &input.drop;
+            quote!(Self::from_ptr_with_dtor(ptr, #drop))
+        }
+        None => {
+            let track_acquire = build_track_acquire(crate_, input);
+            let testing_acquire = build_testing_acquire();
+            let trace_acquire = build_trace(
+                crate_,
+                input,
+                "FromPtr",
+                quote!(ptr, #crate_::export::null_mut()),
+            );
+            quote! {
+                debug_assert!(!ptr.is_null());
+                #track_acquire
+                #testing_acquire
+                #trace_acquire
+                #name(<#crate_::export::NonNull<_>>::new_unchecked(ptr) #tracked #phantom_data)
+            }
+        }
+    };
+    let into_ptr = build_into_ptr(crate_, input);
+    let track_ref = build_track_ref(crate_, input);
+    let clone_const = input.clone.as_ref().map(|_| {
+        quote! {
+            const CLONE: #crate_::export::Option<unsafe fn(*mut #ctype) -> *mut #ctype> =
+                #crate_::export::Option::Some(#crate_::export::clone_ptr::<Self>);
+        }
+    });
 
     quote! {
         unsafe impl #impl_generics #crate_::ForeignType for #name #ty_generics {
             type CType = #ctype;
             type Ref = #ref_name #ty_generics;
 
+            #clone_const
+
             #[inline]
             unsafe fn from_ptr(ptr: *mut #ctype) -> #name #ty_generics {
-                debug_assert!(!ptr.is_null());
-                #name(<#crate_::export::NonNull<_>>::new_unchecked(ptr) #phantom_data)
+                #from_ptr
             }
 
             #[inline]
             fn as_ptr(&self) -> *mut #ctype {
                 <#crate_::export::NonNull<_>>::as_ptr(self.0)
             }
+
+            #into_ptr
         }
 
         unsafe impl #impl_generics #crate_::ForeignTypeRef for #ref_name #ty_generics {
             type CType = #ctype;
+
+            #track_ref
         }
     }
 }
 
-fn build_drop_impl(crate_: &Path, input: &ForeignType) -> TokenStream {
-    let name = &input.name;
-    let drop = &input.drop;
-    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+fn build_into_ptr(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let track_forget = build_track_forget(crate_, input);
+    let testing_release = build_testing_release();
+    if track_forget.is_empty() && testing_release.is_empty() {
+        return quote!();
+    }
+    let ctype = &input.ctype;
+    let release = build_if_tracked(
+        input,
+        quote!(self),
+        quote! {
+            #track_forget
+            #testing_release
+        },
+    );
 
     quote! {
-        impl #impl_generics #crate_::export::Drop for #name #ty_generics {
-            #[inline]
-            fn drop(&mut self) {
-                unsafe {
-                    (#drop)(#crate_::ForeignType::as_ptr(self));
-                }
-            }
+        #[inline]
+        fn into_ptr(self) -> *mut #ctype {
+            let ptr = #crate_::ForeignType::as_ptr(&self);
+            #release
+            #crate_::export::forget(self);
+            ptr
         }
     }
 }
 
-fn build_deref_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
-    let name = &input.name;
-    let ref_name = ref_name(input);
-    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+#[cfg(feature = "track-ownership")]
+fn type_name(input: &ForeignType) -> String {
+    input.name.to_string()
+}
+
+#[cfg(feature = "track-ownership")]
+fn build_track_acquire(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = type_name(input);
+    let f = if input.clone.is_some() {
+        quote!(share)
+    } else {
+        quote!(acquire)
+    };
 
     quote! {
-        impl #impl_generics #crate_::export::Deref for #name #ty_generics {
-            type Target = #ref_name #ty_generics;
+        #crate_::track::#f(ptr, #name);
+    }
+}
 
-            #[inline]
-            fn deref(&self) -> &#ref_name #ty_generics {
-                unsafe {
-                    #crate_::ForeignTypeRef::from_ptr(#crate_::ForeignType::as_ptr(self))
-                }
-            }
-        }
+#[cfg(not(feature = "track-ownership"))]
+fn build_track_acquire(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
 
-        impl #impl_generics #crate_::export::DerefMut for #name #ty_generics {
-            #[inline]
-            fn deref_mut(&mut self) -> &mut #ref_name #ty_generics {
-                unsafe {
-                    #crate_::ForeignTypeRef::from_ptr_mut(#crate_::ForeignType::as_ptr(self))
-                }
-            }
-        }
+#[cfg(feature = "track-ownership")]
+fn build_track_forget(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = type_name(input);
+
+    quote! {
+        #crate_::track::forget(ptr, #name);
     }
 }
 
-fn build_borrow_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
-    let name = &input.name;
-    let ref_name = ref_name(input);
-    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+#[cfg(not(feature = "track-ownership"))]
+fn build_track_forget(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
+
+#[cfg(feature = "track-ownership")]
+fn build_track_ref(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = input.ref_name.to_string();
+    let ctype = &input.ctype;
 
     quote! {
-        impl #impl_generics #crate_::export::Borrow<#ref_name #ty_generics> for #name #ty_generics {
-            #[inline]
-            fn borrow(&self) -> &#ref_name #ty_generics {
-                &**self
-            }
+        #[inline]
+        unsafe fn from_ptr<'__ptr>(ptr: *mut #ctype) -> &'__ptr Self {
+            debug_assert!(!ptr.is_null());
+            #crate_::track::check(ptr, #name);
+            &*ptr.cast::<Self>()
         }
 
-        impl #impl_generics #crate_::export::BorrowMut<#ref_name #ty_generics> for #name #ty_generics {
-            #[inline]
-            fn borrow_mut(&mut self) -> &mut #ref_name #ty_generics {
-                &mut **self
-            }
+        #[inline]
+        unsafe fn from_ptr_mut<'__ptr>(ptr: *mut #ctype) -> &'__ptr mut Self {
+            debug_assert!(!ptr.is_null());
+            #crate_::track::check(ptr, #name);
+            &mut *ptr.cast::<Self>()
         }
     }
 }
 
-fn build_as_ref_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
-    let name = &input.name;
-    let ref_name = ref_name(input);
-    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+#[cfg(not(feature = "track-ownership"))]
+fn build_track_ref(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
+
+#[cfg(feature = "track-ownership")]
+fn build_track_release(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = type_name(input);
 
     quote! {
-        impl #impl_generics #crate_::export::AsRef<#ref_name #ty_generics> for #name #ty_generics {
-            #[inline]
-            fn as_ref(&self) -> &#ref_name #ty_generics {
-                &**self
-            }
+        #crate_::track::release(#crate_::ForeignType::as_ptr(self), #name);
+    }
+}
+
+#[cfg(not(feature = "track-ownership"))]
+fn build_track_release(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
+
+fn build_drop_impl(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = &input.name;
+    let drop = match &input.dtor {
+        Some(_) => quote!((self.1)),
+        None => {
+            let drop = &input.drop;
+            quote!((#drop))
         }
+    };
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let track_release = build_track_release(crate_, input);
+    let testing_release = build_testing_release();
+    let trace_release = build_trace(
+        crate_,
+        input,
+        if input.close.is_some() {
+            "DropUnclosed"
+        } else {
+            "Drop"
+        },
+        quote!(#crate_::ForeignType::as_ptr(self), #crate_::export::null_mut()),
+    );
+    let ctype = &input.ctype;
+    let free = match &input.drop_queue {
+        Some(queue) => quote! {
+            (#queue).push(
+                #crate_::ForeignType::as_ptr(self).cast(),
+                |ptr| #drop(ptr.cast::<#ctype>()),
+            );
+        },
+        None => quote!(#drop(#crate_::ForeignType::as_ptr(self));),
+    };
+    let free = build_abort_on_panic(crate_, input, "drop", free);
+    let warn_unclosed = build_warn_unclosed(crate_, input);
+    let release = build_if_tracked(
+        input,
+        quote!(self),
+        quote! {
+            #track_release
+            #testing_release
+            #trace_release
+            #warn_unclosed
+        },
+    );
 
-        impl #impl_generics #crate_::export::AsMut<#ref_name #ty_generics> for #name #ty_generics {
+    quote! {
+        impl #impl_generics #crate_::export::Drop for #name #ty_generics {
             #[inline]
-            fn as_mut(&mut self) -> &mut #ref_name #ty_generics {
-                &mut **self
+            fn drop(&mut self) {
+                #release
+                unsafe {
+                    #free
+                }
             }
         }
     }
 }
 
-fn build_clone_impl(crate_: &Path, input: &ForeignType) -> TokenStream {
+#[cfg(feature = "std")]
+fn build_warn_unclosed(crate_: &Path, input: &ForeignType) -> TokenStream {
+    if input.close.is_none() {
+        return quote!();
+    }
+    let name = input.name.to_string();
+
+    quote! {
+        #[cfg(debug_assertions)]
+        #crate_::export::warn_unclosed(#name);
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn build_warn_unclosed(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
+
+#[cfg(feature = "std")]
+fn build_drop_many_impl(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let drop_many = match &input.drop_many {
+        Some(drop_many) => drop_many,
+        None => return quote!(),
+    };
+    let name = &input.name;
+    let vis = &input.visibility;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let trace_release = build_trace(
+        crate_,
+        input,
+        "Drop",
+        quote!(#crate_::ForeignType::as_ptr(&value), #crate_::export::null_mut()),
+    );
+    let trace_release = build_if_tracked(input, quote!(value), trace_release);
+    let free = build_abort_on_panic(
+        crate_,
+        input,
+        "drop_many",
+        quote!((#drop_many)(ptrs.as_mut_ptr(), ptrs.len() as _)),
+    );
+
+    quote! {
+        impl #impl_generics #name #ty_generics {
+            /// Frees all of the values with a single call.
+            #[allow(clippy::unnecessary_cast)]
+            #vis fn drop_many(values: #crate_::export::Vec<Self>) {
+                if values.is_empty() {
+                    return;
+                }
+                let mut ptrs = #crate_::export::Vec::with_capacity(values.len());
+                for value in values {
+                    #trace_release
+                    ptrs.push(#crate_::ForeignType::into_ptr(value));
+                }
+                unsafe {
+                    #free;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn build_drop_many_impl(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
+
+fn build_close_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let close = match &input.close {
+        Some(close) => close,
+        None => return quote!(),
+    };
+    let name = &input.name;
+    let vis = &input.visibility;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let trace_release = build_trace(
+        crate_,
+        input,
+        "Drop",
+        quote!(#crate_::ForeignType::as_ptr(&self), #crate_::export::null_mut()),
+    );
+    let trace_release = build_if_tracked(input, quote!(self), trace_release);
+
+    quote! {
+        impl #impl_generics #name #ty_generics {
+            /// Frees the value without blocking.
+            ///
+            /// Dropping the value instead frees it synchronously.
+            #vis async fn close(self) {
+                #trace_release
+                let ptr = #crate_::ForeignType::into_ptr(self);
+                unsafe { (#close)(ptr) }.await
+            }
+        }
+    }
+}
+
+fn build_deref_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = &input.name;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #crate_::export::Deref for #name #ty_generics {
+            type Target = #ref_name #ty_generics;
+
+            #[inline]
+            fn deref(&self) -> &#ref_name #ty_generics {
+                unsafe {
+                    #crate_::ForeignTypeRef::from_ptr(#crate_::ForeignType::as_ptr(self))
+                }
+            }
+        }
+
+        impl #impl_generics #crate_::export::DerefMut for #name #ty_generics {
+            #[inline]
+            fn deref_mut(&mut self) -> &mut #ref_name #ty_generics {
+                unsafe {
+                    #crate_::ForeignTypeRef::from_ptr_mut(#crate_::ForeignType::as_ptr(self))
+                }
+            }
+        }
+    }
+}
+
+fn build_borrow_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = &input.name;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #crate_::export::Borrow<#ref_name #ty_generics> for #name #ty_generics {
+            #[inline]
+            fn borrow(&self) -> &#ref_name #ty_generics {
+                &**self
+            }
+        }
+
+        impl #impl_generics #crate_::export::BorrowMut<#ref_name #ty_generics> for #name #ty_generics {
+            #[inline]
+            fn borrow_mut(&mut self) -> &mut #ref_name #ty_generics {
+                &mut **self
+            }
+        }
+    }
+}
+
+fn build_as_ref_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = &input.name;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #crate_::export::AsRef<#ref_name #ty_generics> for #name #ty_generics {
+            #[inline]
+            fn as_ref(&self) -> &#ref_name #ty_generics {
+                &**self
+            }
+        }
+
+        impl #impl_generics #crate_::export::AsMut<#ref_name #ty_generics> for #name #ty_generics {
+            #[inline]
+            fn as_mut(&mut self) -> &mut #ref_name #ty_generics {
+                &mut **self
+            }
+        }
+    }
+}
+
+fn build_raw_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = &input.name;
+    let ctype = &input.ctype;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #crate_::export::From<#name #ty_generics>
+            for #crate_::export::NonNull<#ctype>
+        {
+            #[inline]
+            fn from(value: #name #ty_generics) -> #crate_::export::NonNull<#ctype> {
+                unsafe {
+                    #crate_::export::NonNull::new_unchecked(#crate_::ForeignType::into_ptr(value))
+                }
+            }
+        }
+
+        impl #impl_generics #crate_::export::TryFrom<#crate_::raw::OwnedPtr<#ctype>>
+            for #name #ty_generics
+        {
+            type Error = #crate_::raw::NullPtrError;
+
+            #[inline]
+            fn try_from(
+                ptr: #crate_::raw::OwnedPtr<#ctype>,
+            ) -> #crate_::export::Result<#name #ty_generics, #crate_::raw::NullPtrError> {
+                match unsafe { #crate_::ForeignType::from_ptr_opt(ptr.into_ptr()) } {
+                    #crate_::export::Option::Some(value) => #crate_::export::Ok(value),
+                    #crate_::export::Option::None => #crate_::export::Err(#crate_::raw::NullPtrError),
+                }
+            }
+        }
+    }
+}
+
+fn build_clone_impl(crate_: &Path, input: &ForeignType) -> TokenStream {
     let clone = match &input.clone {
         Some(clone) => clone,
         None => return quote!(),
     };
     let name = &input.name;
     let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let from_ptr = match &input.dtor {
+        Some(_) => quote!(Self::from_ptr_with_dtor(ptr, #crate_::export::Clone::clone(&self.1))),
+        None => quote!(#crate_::ForeignType::from_ptr(ptr)),
+    };
+    let trace_clone = build_trace(
+        crate_,
+        input,
+        "Clone",
+        quote!(#crate_::ForeignType::as_ptr(self), ptr),
+    );
+    let clone = build_abort_on_panic(
+        crate_,
+        input,
+        "clone",
+        quote!((#clone)(#crate_::ForeignType::as_ptr(self))),
+    );
 
     quote! {
         impl #impl_generics #crate_::export::Clone for #name #ty_generics {
             #[inline]
             fn clone(&self) -> #name #ty_generics {
                 unsafe {
-                    let ptr = (#clone)(#crate_::ForeignType::as_ptr(self));
-                    #crate_::ForeignType::from_ptr(ptr)
+                    let ptr = #clone;
+                    #trace_clone
+                    #from_ptr
                 }
             }
         }
@@ -239,6 +1010,18 @@ fn build_to_owned_impl(crate_: &Path, input: &ForeignType) -> TokenStream {
     let name = &input.name;
     let ref_name = ref_name(input);
     let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let trace_clone = build_trace(
+        crate_,
+        input,
+        "Clone",
+        quote!(#crate_::ForeignTypeRef::as_ptr(self), ptr),
+    );
+    let clone = build_abort_on_panic(
+        crate_,
+        input,
+        "to_owned",
+        quote!((#clone)(#crate_::ForeignTypeRef::as_ptr(self))),
+    );
 
     quote! {
         impl #impl_generics #crate_::export::ToOwned for #ref_name #ty_generics {
@@ -247,7 +1030,8 @@ fn build_to_owned_impl(crate_: &Path, input: &ForeignType) -> TokenStream {
             #[inline]
             fn to_owned(&self) -> #name #ty_generics {
                 unsafe {
-                    let ptr = (#clone)(#crate_::ForeignTypeRef::as_ptr(self));
+                    let ptr = #clone;
+                    #trace_clone
                     #crate_::ForeignType::from_ptr(ptr)
                 }
             }
@@ -259,3 +1043,1547 @@ fn build_to_owned_impl(crate_: &Path, input: &ForeignType) -> TokenStream {
 fn build_to_owned_impl(_: &Path, _: &ForeignType) -> TokenStream {
     quote!()
 }
+
+fn build_immutable_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let immutable = match &input.immutable {
+        Some(immutable) => immutable,
+        None => return quote!(),
+    };
+    let name = &input.name;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let immutable_ref = quote!(<#immutable as #crate_::ForeignType>::Ref);
+
+    quote! {
+        impl #impl_generics #crate_::export::Deref for #ref_name #ty_generics {
+            type Target = #immutable_ref;
+
+            #[inline]
+            fn deref(&self) -> &#immutable_ref {
+                unsafe {
+                    #crate_::ForeignTypeRef::from_ptr(#crate_::ForeignTypeRef::as_ptr(self))
+                }
+            }
+        }
+
+        impl #impl_generics #crate_::export::DerefMut for #ref_name #ty_generics {
+            #[inline]
+            fn deref_mut(&mut self) -> &mut #immutable_ref {
+                unsafe {
+                    #crate_::ForeignTypeRef::from_ptr_mut(#crate_::ForeignTypeRef::as_ptr(self))
+                }
+            }
+        }
+
+        impl #impl_generics #crate_::export::AsRef<#immutable_ref> for #name #ty_generics {
+            #[inline]
+            fn as_ref(&self) -> &#immutable_ref {
+                &***self
+            }
+        }
+
+        impl #impl_generics #crate_::export::AsRef<#immutable_ref> for #ref_name #ty_generics {
+            #[inline]
+            fn as_ref(&self) -> &#immutable_ref {
+                &**self
+            }
+        }
+
+        impl #impl_generics #crate_::export::From<#name #ty_generics> for #immutable {
+            #[inline]
+            fn from(value: #name #ty_generics) -> #immutable {
+                unsafe {
+                    #crate_::ForeignType::from_ptr(#crate_::ForeignType::into_ptr(value))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "objc2")]
+fn build_toll_free_bridged_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let bridged = match &input.toll_free_bridged {
+        Some(bridged) => bridged,
+        None => return quote!(),
+    };
+    let name = &input.name;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let impl_params = &input.generics.params;
+
+    quote! {
+        #[cfg(target_vendor = "apple")]
+        impl #impl_generics #crate_::export::From<#name #ty_generics> for #crate_::export::objc2::rc::Retained<#bridged> {
+            #[inline]
+            fn from(value: #name #ty_generics) -> #crate_::export::objc2::rc::Retained<#bridged> {
+                unsafe {
+                    let ptr = #crate_::ForeignType::into_ptr(value);
+                    #crate_::export::objc2::rc::Retained::from_raw(ptr.cast::<#bridged>()).unwrap_unchecked()
+                }
+            }
+        }
+
+        #[cfg(target_vendor = "apple")]
+        impl #impl_generics #crate_::export::From<#crate_::export::objc2::rc::Retained<#bridged>> for #name #ty_generics {
+            #[inline]
+            fn from(value: #crate_::export::objc2::rc::Retained<#bridged>) -> #name #ty_generics {
+                unsafe {
+                    let ptr = #crate_::export::objc2::rc::Retained::into_raw(value);
+                    #crate_::ForeignType::from_ptr(ptr.cast())
+                }
+            }
+        }
+
+        #[cfg(target_vendor = "apple")]
+        impl #impl_generics #crate_::export::AsRef<#bridged> for #ref_name #ty_generics {
+            #[inline]
+            fn as_ref(&self) -> &#bridged {
+                unsafe { &*#crate_::ForeignTypeRef::as_ptr(self).cast::<#bridged>() }
+            }
+        }
+
+        #[cfg(target_vendor = "apple")]
+        impl<'__ref, #impl_params> #crate_::export::From<&'__ref #bridged> for &'__ref #ref_name #ty_generics {
+            #[inline]
+            fn from(value: &'__ref #bridged) -> &'__ref #ref_name #ty_generics {
+                let ptr = value as *const #bridged as *mut #bridged;
+                unsafe { #crate_::ForeignTypeRef::from_ptr(ptr.cast()) }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "objc2"))]
+fn build_toll_free_bridged_impls(_: &Path, input: &ForeignType) -> TokenStream {
+    match &input.toll_free_bridged {
+        Some(bridged) => syn::Error::new_spanned(
+            bridged,
+            "`type TollFreeBridged` requires the `objc2` feature of foreign-types",
+        )
+        .to_compile_error(),
+        None => quote!(),
+    }
+}
+
+#[cfg(feature = "core-foundation")]
+fn build_core_foundation_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let cf = match &input.core_foundation {
+        Some(cf) => cf,
+        None => return quote!(),
+    };
+    let name = &input.name;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let impl_params = &input.generics.params;
+
+    quote! {
+        #[cfg(target_vendor = "apple")]
+        impl #impl_generics #crate_::export::From<#name #ty_generics> for #cf {
+            #[inline]
+            fn from(value: #name #ty_generics) -> #cf {
+                unsafe {
+                    let ptr = #crate_::ForeignType::into_ptr(value);
+                    #crate_::export::core_foundation::base::TCFType::wrap_under_create_rule(ptr as _)
+                }
+            }
+        }
+
+        #[cfg(target_vendor = "apple")]
+        impl #impl_generics #crate_::export::From<#cf> for #name #ty_generics {
+            #[inline]
+            fn from(value: #cf) -> #name #ty_generics {
+                let ptr = #crate_::export::core_foundation::base::TCFType::as_concrete_TypeRef(&value);
+                #crate_::export::forget(value);
+                unsafe { #crate_::ForeignType::from_ptr(ptr as _) }
+            }
+        }
+
+        #[cfg(target_vendor = "apple")]
+        impl<'__ref, #impl_params> #crate_::export::From<&'__ref #ref_name #ty_generics> for #cf {
+            #[inline]
+            fn from(value: &'__ref #ref_name #ty_generics) -> #cf {
+                unsafe {
+                    let ptr = #crate_::ForeignTypeRef::as_ptr(value);
+                    #crate_::export::core_foundation::base::TCFType::wrap_under_get_rule(ptr as _)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "core-foundation"))]
+fn build_core_foundation_impls(_: &Path, input: &ForeignType) -> TokenStream {
+    match &input.core_foundation {
+        Some(cf) => syn::Error::new_spanned(
+            cf,
+            "`type CoreFoundation` requires the `core-foundation` feature of foreign-types",
+        )
+        .to_compile_error(),
+        None => quote!(),
+    }
+}
+
+#[cfg(feature = "pyo3")]
+fn build_py_capsule_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let capsule_name = match &input.py_capsule_name {
+        Some(capsule_name) => capsule_name,
+        None => return quote!(),
+    };
+    let vis = &input.visibility;
+    let name = &input.name;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let c_name = syn::LitByteStr::new(
+        format!("{}\0", capsule_name.value()).as_bytes(),
+        capsule_name.span(),
+    );
+    let pyo3 = quote!(#crate_::export::pyo3);
+
+    quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Moves this value into a Python capsule holding its raw pointer, which frees it when
+            /// the capsule is destroyed.
+            #vis fn into_py_capsule<'__py>(
+                self,
+                py: #pyo3::Python<'__py>,
+            ) -> #pyo3::PyResult<#pyo3::Bound<'__py, #pyo3::types::PyCapsule>>
+            where
+                Self: #crate_::export::Send + 'static,
+            {
+                let ptr = #crate_::ForeignType::into_ptr(self);
+                let capsule = unsafe {
+                    #crate_::export::py_capsule_new(
+                        py,
+                        ptr.cast(),
+                        #c_name,
+                        Self::__py_capsule_destructor,
+                    )
+                };
+                if capsule.is_err() {
+                    #crate_::export::drop(unsafe { <Self as #crate_::ForeignType>::from_ptr(ptr) });
+                }
+                capsule
+            }
+
+            /// Takes ownership of the value stored in a Python capsule.
+            ///
+            /// The capsule's name is cleared, so that later calls to this function or
+            /// `from_py_capsule` on the reference type fail. Returns an error if the capsule's
+            /// name does not match.
+            ///
+            /// # Safety
+            ///
+            /// A capsule with the expected name must hold a valid, owned instance of the native
+            /// type, such as one created by `into_py_capsule`.
+            #vis unsafe fn from_py_capsule(
+                capsule: &#pyo3::Bound<'_, #pyo3::types::PyCapsule>,
+            ) -> #pyo3::PyResult<Self> {
+                let ptr = #crate_::export::py_capsule_take(capsule, #c_name)?;
+                #crate_::export::Ok(<Self as #crate_::ForeignType>::from_ptr(ptr.cast()))
+            }
+
+            #[doc(hidden)]
+            unsafe extern "C" fn __py_capsule_destructor(capsule: *mut #pyo3::ffi::PyObject) {
+                #crate_::panic::abort_on_panic("PyCapsule destructor", || {
+                    let ptr = unsafe { #crate_::export::py_capsule_destructor_pointer(capsule, #c_name) };
+                    if !ptr.is_null() {
+                        #crate_::export::drop(unsafe {
+                            <Self as #crate_::ForeignType>::from_ptr(ptr.cast())
+                        });
+                    }
+                })
+            }
+        }
+
+        impl #impl_generics #ref_name #ty_generics #where_clause {
+            /// Borrows the value stored in a Python capsule.
+            ///
+            /// Returns an error if the capsule's name does not match.
+            ///
+            /// # Safety
+            ///
+            /// A capsule with the expected name must hold a valid instance of the native type for
+            /// the lifetime of the borrow, such as one created by `into_py_capsule`.
+            #vis unsafe fn from_py_capsule<'__a>(
+                capsule: &'__a #pyo3::Bound<'_, #pyo3::types::PyCapsule>,
+            ) -> #pyo3::PyResult<&'__a Self> {
+                let ptr = #crate_::export::py_capsule_pointer(capsule, #c_name)?;
+                #crate_::export::Ok(<Self as #crate_::ForeignTypeRef>::from_ptr(ptr.cast()))
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "pyo3"))]
+fn build_py_capsule_impls(_: &Path, input: &ForeignType) -> TokenStream {
+    match &input.py_capsule_name {
+        Some(capsule_name) => syn::Error::new_spanned(
+            capsule_name,
+            "`const PY_CAPSULE_NAME` requires the `pyo3` feature of foreign-types",
+        )
+        .to_compile_error(),
+        None => quote!(),
+    }
+}
+
+fn build_array_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let free_array = match &input.free_array {
+        Some(free_array) => free_array,
+        None => return quote!(),
+    };
+    let name = &input.name;
+    let vis = &input.visibility;
+    let ctype = &input.ctype;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #name #ty_generics {
+            /// Takes ownership of a null-terminated array of pointers to this type and of each of
+            /// its elements.
+            ///
+            /// # Safety
+            ///
+            /// See `NullTerminated::from_ptr_owned`.
+            #vis unsafe fn from_null_terminated(
+                ptr: *mut *mut #ctype,
+            ) -> #crate_::array::NullTerminated<#name #ty_generics> {
+                #crate_::array::NullTerminated::from_ptr_owned(ptr, |ptr| (#free_array)(ptr))
+            }
+        }
+    }
+}
+
+fn build_pool_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let (lifetime, pool) = match &input.pool {
+        Some(pool) => pool,
+        None => return quote!(),
+    };
+    let name = &input.name;
+    let vis = &input.visibility;
+    let ctype = &input.ctype;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #name #ty_generics {
+            /// Constructs an instance of this type from a pointer to a value allocated from `pool`.
+            ///
+            /// The value is not freed when it is dropped, but cannot outlive the borrow of the pool.
+            ///
+            /// # Safety
+            ///
+            /// `ptr` must be a valid pointer to a value allocated from `pool`.
+            #[inline]
+            #vis unsafe fn from_pool_ptr(
+                _pool: &#lifetime <#pool as #crate_::ForeignType>::Ref,
+                ptr: *mut #ctype,
+            ) -> #name #ty_generics {
+                <Self as #crate_::ForeignType>::from_ptr(ptr)
+            }
+        }
+    }
+}
+
+fn build_from_bytes_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let from_bytes = match &input.from_bytes {
+        Some(from_bytes) => from_bytes,
+        None => return quote!(),
+    };
+    let name = &input.name;
+    let vis = &input.visibility;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let arbitrary_impl = build_arbitrary_impl(crate_, input);
+
+    quote! {
+        impl #impl_generics #name #ty_generics {
+            /// Parses an instance of this type from bytes, returning `None` on failure.
+            #[inline]
+            #[must_use]
+            #vis fn from_bytes(data: &[u8]) -> #crate_::export::Option<#name #ty_generics> {
+                unsafe {
+                    let ptr = (#from_bytes)(data.as_ptr() as _, data.len() as _);
+                    #crate_::ForeignType::from_ptr_opt(ptr)
+                }
+            }
+        }
+
+        #arbitrary_impl
+    }
+}
+
+#[cfg(feature = "std")]
+fn build_from_str_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let from_str = match &input.from_str {
+        Some(from_str) => from_str,
+        None => return quote!(),
+    };
+    let vis = &input.visibility;
+    let name = &input.name;
+    let error_name = Ident::new(&format!("Parse{}Error", name), name.span());
+    let error_docs = format!(
+        "An error returned when parsing a [`{name}`](struct.{name}.html) from a string fails.",
+        name = name
+    );
+    let message = format!("invalid {} syntax", name);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        #[doc = #error_docs]
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        // `true` if the string contained an interior nul byte.
+        #vis struct #error_name(bool);
+
+        impl #crate_::export::fmt::Display for #error_name {
+            fn fmt(&self, fmt: &mut #crate_::export::fmt::Formatter) -> #crate_::export::fmt::Result {
+                if self.0 {
+                    fmt.write_str("string contains an interior nul byte")
+                } else {
+                    fmt.write_str(#message)
+                }
+            }
+        }
+
+        impl #crate_::export::Error for #error_name {}
+
+        impl #impl_generics #crate_::export::FromStr for #name #ty_generics {
+            type Err = #error_name;
+
+            fn from_str(s: &str) -> #crate_::export::Result<#name #ty_generics, #error_name> {
+                let s = #crate_::export::CString::new(s).map_err(|_| #error_name(true))?;
+                unsafe {
+                    let ptr = (#from_str)(s.as_ptr() as _);
+                    #crate_::ForeignType::from_ptr_opt(ptr).ok_or(#error_name(false))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn build_from_str_impls(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
+
+fn build_cmp_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let cmp = match &input.cmp {
+        Some(cmp) => cmp,
+        None => return quote!(),
+    };
+    let name = &input.name;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #crate_::export::Ord for #ref_name #ty_generics {
+            #[inline]
+            fn cmp(&self, other: &Self) -> #crate_::export::cmp::Ordering {
+                let ret = unsafe {
+                    (#cmp)(
+                        #crate_::ForeignTypeRef::as_ptr(self),
+                        #crate_::ForeignTypeRef::as_ptr(other),
+                    )
+                };
+                #crate_::export::Ord::cmp(&ret, &0)
+            }
+        }
+
+        impl #impl_generics #crate_::export::PartialOrd for #ref_name #ty_generics {
+            #[inline]
+            fn partial_cmp(&self, other: &Self) -> #crate_::export::Option<#crate_::export::cmp::Ordering> {
+                #crate_::export::Option::Some(#crate_::export::Ord::cmp(self, other))
+            }
+        }
+
+        impl #impl_generics #crate_::export::PartialEq for #ref_name #ty_generics {
+            #[inline]
+            fn eq(&self, other: &Self) -> bool {
+                #crate_::export::Ord::cmp(self, other) == #crate_::export::cmp::Ordering::Equal
+            }
+        }
+
+        impl #impl_generics #crate_::export::Eq for #ref_name #ty_generics {}
+
+        impl #impl_generics #crate_::export::Ord for #name #ty_generics {
+            #[inline]
+            fn cmp(&self, other: &Self) -> #crate_::export::cmp::Ordering {
+                #crate_::export::Ord::cmp(&**self, &**other)
+            }
+        }
+
+        impl #impl_generics #crate_::export::PartialOrd for #name #ty_generics {
+            #[inline]
+            fn partial_cmp(&self, other: &Self) -> #crate_::export::Option<#crate_::export::cmp::Ordering> {
+                #crate_::export::Option::Some(#crate_::export::Ord::cmp(&**self, &**other))
+            }
+        }
+
+        impl #impl_generics #crate_::export::PartialEq for #name #ty_generics {
+            #[inline]
+            fn eq(&self, other: &Self) -> bool {
+                **self == **other
+            }
+        }
+
+        impl #impl_generics #crate_::export::Eq for #name #ty_generics {}
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+fn build_arbitrary_impl(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = &input.name;
+    let impl_params = &input.generics.params;
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl<'__arbitrary, #impl_params> #crate_::export::arbitrary::Arbitrary<'__arbitrary> for #name #ty_generics {
+            fn arbitrary(
+                u: &mut #crate_::export::arbitrary::Unstructured<'__arbitrary>,
+            ) -> #crate_::export::arbitrary::Result<Self> {
+                let data = <&[u8] as #crate_::export::arbitrary::Arbitrary<'__arbitrary>>::arbitrary(u)?;
+                Self::from_bytes(data).ok_or(#crate_::export::arbitrary::Error::IncorrectFormat)
+            }
+
+            fn arbitrary_take_rest(
+                u: #crate_::export::arbitrary::Unstructured<'__arbitrary>,
+            ) -> #crate_::export::arbitrary::Result<Self> {
+                let data = <&[u8] as #crate_::export::arbitrary::Arbitrary<'__arbitrary>>::arbitrary_take_rest(u)?;
+                Self::from_bytes(data).ok_or(#crate_::export::arbitrary::Error::IncorrectFormat)
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "arbitrary"))]
+fn build_arbitrary_impl(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
+
+#[cfg(feature = "std")]
+fn build_io_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let error = build_io_error(crate_, input);
+    let invalid_len = format!("{} I/O returned more bytes than requested", input.name);
+
+    let read_impl = input.read.as_ref().map(|read| {
+        quote! {
+            impl #impl_generics #crate_::export::io::Read for #ref_name #ty_generics {
+                fn read(&mut self, buf: &mut [u8]) -> #crate_::export::io::Result<usize> {
+                    let len = if buf.len() > i32::MAX as usize {
+                        i32::MAX as usize
+                    } else {
+                        buf.len()
+                    };
+                    let ret = unsafe {
+                        (#read)(
+                            #crate_::ForeignTypeRef::as_ptr(self),
+                            buf.as_mut_ptr() as _,
+                            len as _,
+                        )
+                    } as isize;
+                    if ret < 0 {
+                        #crate_::export::Err(#error)
+                    } else if ret as usize > len {
+                        #crate_::export::Err(#crate_::export::io::Error::new(
+                            #crate_::export::io::ErrorKind::InvalidData,
+                            #invalid_len,
+                        ))
+                    } else {
+                        #crate_::export::Ok(ret as usize)
+                    }
+                }
+            }
+        }
+    });
+
+    let flush = match &input.flush {
+        Some(flush) => quote! {
+            let ret = unsafe { (#flush)(#crate_::ForeignTypeRef::as_ptr(self)) } as isize;
+            if ret < 0 {
+                #crate_::export::Err(#error)
+            } else {
+                #crate_::export::Ok(())
+            }
+        },
+        None => quote!(#crate_::export::Ok(())),
+    };
+
+    let write_impl = input.write.as_ref().map(|write| {
+        quote! {
+            impl #impl_generics #crate_::export::io::Write for #ref_name #ty_generics {
+                fn write(&mut self, buf: &[u8]) -> #crate_::export::io::Result<usize> {
+                    let len = if buf.len() > i32::MAX as usize {
+                        i32::MAX as usize
+                    } else {
+                        buf.len()
+                    };
+                    let ret = unsafe {
+                        (#write)(
+                            #crate_::ForeignTypeRef::as_ptr(self),
+                            buf.as_ptr() as _,
+                            len as _,
+                        )
+                    } as isize;
+                    if ret < 0 {
+                        #crate_::export::Err(#error)
+                    } else if ret as usize > len {
+                        #crate_::export::Err(#crate_::export::io::Error::new(
+                            #crate_::export::io::ErrorKind::InvalidData,
+                            #invalid_len,
+                        ))
+                    } else {
+                        #crate_::export::Ok(ret as usize)
+                    }
+                }
+
+                fn flush(&mut self) -> #crate_::export::io::Result<()> {
+                    #flush
+                }
+            }
+        }
+    });
+
+    quote! {
+        #read_impl
+        #write_impl
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn build_io_impls(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
+
+/// Builds the `io::Error` returned when a read or write fails, wrapping the type's last error if
+/// it has one.
+#[cfg(feature = "std")]
+fn build_io_error(crate_: &Path, input: &ForeignType) -> TokenStream {
+    match &input.last_error {
+        Some((_, last_error)) => {
+            let error_name = Ident::new(&format!("{}Error", input.name), input.name.span());
+            quote! {
+                #crate_::export::io::Error::new(
+                    #crate_::export::io::ErrorKind::Other,
+                    #error_name(unsafe { (#last_error)() }),
+                )
+            }
+        }
+        None => {
+            let message = format!("{} I/O failed", input.name);
+            quote! {
+                #crate_::export::io::Error::new(#crate_::export::io::ErrorKind::Other, #message)
+            }
+        }
+    }
+}
+
+fn build_pop_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let (ty, pop) = match &input.pop {
+        Some(pop) => pop,
+        None => return quote!(),
+    };
+    let name = &input.name;
+    let ctype = &input.ctype;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #crate_::export::IntoIterator for #name #ty_generics {
+            type Item = #ty;
+            type IntoIter = #crate_::pop::IntoIter<Self, #ty>;
+
+            #[inline]
+            fn into_iter(self) -> #crate_::pop::IntoIter<Self, #ty> {
+                let pop: unsafe fn(*mut #ctype) -> *mut <#ty as #crate_::ForeignType>::CType =
+                    |ptr| unsafe { (#pop)(ptr) };
+                unsafe { #crate_::pop::IntoIter::new(self, pop) }
+            }
+        }
+    }
+}
+
+fn build_bytes_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let (len, data) = match &input.bytes {
+        Some(bytes) => bytes,
+        None => return quote!(),
+    };
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let vis = &input.visibility;
+    // A negative length is treated as an empty buffer rather than wrapping around.
+    let len = quote! {
+        #[allow(clippy::useless_conversion)]
+        let len = <usize as #crate_::export::TryFrom<_>>::try_from(unsafe {
+            (#len)(#crate_::ForeignTypeRef::as_ptr(self))
+        })
+        .unwrap_or(0);
+    };
+
+    quote! {
+        impl #impl_generics #ref_name #ty_generics {
+            /// Returns the contents of the buffer.
+            #[inline]
+            #vis fn as_bytes(&self) -> &[u8] {
+                #len
+                if len == 0 {
+                    return &[];
+                }
+                unsafe {
+                    let data = (#data)(#crate_::ForeignTypeRef::as_ptr(self));
+                    #crate_::export::slice::from_raw_parts(data.cast::<u8>(), len)
+                }
+            }
+
+            /// Returns the contents of the buffer mutably.
+            #[inline]
+            #vis fn as_bytes_mut(&mut self) -> &mut [u8] {
+                #len
+                if len == 0 {
+                    return &mut [];
+                }
+                unsafe {
+                    let data = (#data)(#crate_::ForeignTypeRef::as_ptr(self));
+                    #crate_::export::slice::from_raw_parts_mut(data as *mut u8, len)
+                }
+            }
+        }
+    }
+}
+
+fn build_edit_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let (begin, commit, rollback) = match &input.edit {
+        Some(edit) => edit,
+        None => return quote!(),
+    };
+    let ref_name = ref_name(input);
+    let ctype = &input.ctype;
+    let vis = &input.visibility;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let guard = format_ident!("{}EditGuard", input.name);
+    let guard_doc = format!("A guard for an edit of a `{}`.", ref_name);
+    // Type aliases ignore bounds, so only the names of the parameters are used.
+    let params = input.generics.params.iter().map(|param| match param {
+        GenericParam::Type(param) => {
+            let ident = &param.ident;
+            quote!(#ident)
+        }
+        GenericParam::Lifetime(param) => {
+            let lifetime = &param.lifetime;
+            quote!(#lifetime)
+        }
+        GenericParam::Const(param) => {
+            let ident = &param.ident;
+            let ty = &param.ty;
+            quote!(const #ident: #ty)
+        }
+    });
+    let rollback = match rollback {
+        Some(rollback) => quote! {{
+            let rollback: unsafe fn(*mut #ctype) = |ptr| unsafe { (#rollback)(ptr) };
+            #crate_::export::Option::Some(rollback)
+        }},
+        None => quote!(#crate_::export::Option::None),
+    };
+
+    quote! {
+        #[doc = #guard_doc]
+        #vis type #guard<'__edit, #(#params),*> =
+            #crate_::edit::EditGuard<'__edit, #ref_name #ty_generics>;
+
+        impl #impl_generics #ref_name #ty_generics {
+            /// Begins an edit, which is committed or rolled back when the returned guard is finished
+            /// or dropped.
+            #[inline]
+            #vis fn edit(&mut self) -> #crate_::edit::EditGuard<'_, Self> {
+                let commit: unsafe fn(*mut #ctype) = |ptr| unsafe { (#commit)(ptr) };
+                let rollback = #rollback;
+                unsafe {
+                    (#begin)(#crate_::ForeignTypeRef::as_ptr(self));
+                    #crate_::edit::EditGuard::new(self, commit, rollback)
+                }
+            }
+        }
+    }
+}
+
+fn build_error_object_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let vis = &input.visibility;
+
+    let code_impl = input.code.as_ref().map(|(ty, code)| {
+        quote! {
+            impl #impl_generics #ref_name #ty_generics {
+                /// Returns the error code.
+                #[inline]
+                #vis fn code(&self) -> #ty {
+                    unsafe { (#code)(#crate_::ForeignTypeRef::as_ptr(self)) }
+                }
+            }
+        }
+    });
+    let error_impls = build_error_impls(crate_, input);
+
+    quote! {
+        #code_impl
+        #error_impls
+    }
+}
+
+#[cfg(feature = "std")]
+fn build_error_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let message = match &input.message {
+        Some(message) => message,
+        None => return quote!(),
+    };
+    let name = &input.name;
+    let name_str = name.to_string();
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let code_field = input
+        .code
+        .as_ref()
+        .map(|_| quote!(debug.field("code", &self.code());));
+
+    quote! {
+        impl #impl_generics #crate_::export::fmt::Display for #ref_name #ty_generics {
+            fn fmt(&self, fmt: &mut #crate_::export::fmt::Formatter) -> #crate_::export::fmt::Result {
+                let message = unsafe { (#message)(#crate_::ForeignTypeRef::as_ptr(self)) };
+                if message.is_null() {
+                    return fmt.write_str("unknown error");
+                }
+                let message = unsafe { #crate_::export::CStr::from_ptr(message) };
+                fmt.write_str(&message.to_string_lossy())
+            }
+        }
+
+        impl #impl_generics #crate_::export::fmt::Debug for #ref_name #ty_generics {
+            fn fmt(&self, fmt: &mut #crate_::export::fmt::Formatter) -> #crate_::export::fmt::Result {
+                let mut debug = fmt.debug_struct(#name_str);
+                #code_field
+                debug.field("message", &#crate_::export::format_args!("{}", self));
+                debug.finish()
+            }
+        }
+
+        impl #impl_generics #crate_::export::Error for #ref_name #ty_generics {}
+
+        impl #impl_generics #crate_::export::fmt::Display for #name #ty_generics {
+            fn fmt(&self, fmt: &mut #crate_::export::fmt::Formatter) -> #crate_::export::fmt::Result {
+                #crate_::export::fmt::Display::fmt(&**self, fmt)
+            }
+        }
+
+        impl #impl_generics #crate_::export::fmt::Debug for #name #ty_generics {
+            fn fmt(&self, fmt: &mut #crate_::export::fmt::Formatter) -> #crate_::export::fmt::Result {
+                #crate_::export::fmt::Debug::fmt(&**self, fmt)
+            }
+        }
+
+        impl #impl_generics #crate_::export::Error for #name #ty_generics {}
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn build_error_impls(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
+
+fn build_last_error_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let (ty, last_error) = match &input.last_error {
+        Some(last_error) => last_error,
+        None => return quote!(),
+    };
+    let vis = &input.visibility;
+    let name = &input.name;
+    let ctype = &input.ctype;
+    let error_name = Ident::new(&format!("{}Error", name), name.span());
+    let error_docs = format!(
+        "An error returned by a function creating a [`{name}`](struct.{name}.html).",
+        name = name
+    );
+    let message = format!("error creating {}: {{}}", name);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let std_error_impl = build_std_error_impl(crate_, &error_name);
+
+    quote! {
+        #[doc = #error_docs]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #vis struct #error_name(#ty);
+
+        impl #error_name {
+            /// Returns the error reported by the C library.
+            #[inline]
+            #vis fn code(&self) -> #ty {
+                self.0
+            }
+        }
+
+        impl #crate_::export::fmt::Display for #error_name {
+            fn fmt(&self, fmt: &mut #crate_::export::fmt::Formatter) -> #crate_::export::fmt::Result {
+                #crate_::export::write!(fmt, #message, self.0)
+            }
+        }
+
+        #std_error_impl
+
+        impl #impl_generics #name #ty_generics {
+            /// Constructs an instance of this type from the return value of a C function which
+            /// returns null on failure, in which case the library's last error is returned.
+            ///
+            /// # Safety
+            ///
+            /// If `ptr` is not null, it must be a valid, owned instance of the native type.
+            #[inline]
+            #vis unsafe fn from_ptr_checked(
+                ptr: *mut #ctype,
+            ) -> #crate_::export::Result<#name #ty_generics, #error_name> {
+                if ptr.is_null() {
+                    #crate_::export::Err(#error_name((#last_error)()))
+                } else {
+                    #crate_::export::Ok(#crate_::ForeignType::from_ptr(ptr))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn build_std_error_impl(crate_: &Path, error_name: &Ident) -> TokenStream {
+    quote! {
+        impl #crate_::export::Error for #error_name {}
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn build_std_error_impl(_: &Path, _: &Ident) -> TokenStream {
+    quote!()
+}
+
+fn build_ctype_const_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let ctype_const = match &input.ctype_const {
+        Some(ctype_const) => ctype_const,
+        None => return quote!(),
+    };
+    let vis = &input.visibility;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #ref_name #ty_generics {
+            /// Constructs a shared instance of this type from its raw const type.
+            ///
+            /// # Safety
+            ///
+            /// `ptr` must be a valid, immutable, instance of the type for the returned lifetime.
+            #[inline]
+            #vis unsafe fn from_const_ptr<'__ptr>(ptr: *const #ctype_const) -> &'__ptr Self {
+                #crate_::ForeignTypeRef::from_ptr(ptr as *mut _)
+            }
+
+            /// Returns a raw pointer to the wrapped value as its const type.
+            #[inline]
+            #vis fn as_const_ptr(&self) -> *const #ctype_const {
+                #crate_::ForeignTypeRef::as_ptr(self).cast::<#ctype_const>()
+            }
+        }
+    }
+}
+
+fn build_getter_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    if input.getters.is_empty() {
+        return quote!();
+    }
+    let vis = &input.visibility;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let getters = input.getters.iter().map(|getter| {
+        let name = &getter.name;
+        let name_mut = Ident::new(&format!("{}_mut", name), name.span());
+        let ty = &getter.ty;
+        let f = &getter.getter;
+        let null = format!("`{}` returned a null pointer", name);
+        let (attrs, attrs_mut) = if getter.attrs.is_empty() {
+            let doc = format!("Returns a reference to the `{}` of this value.", name);
+            let doc_mut = format!(
+                "Returns a mutable reference to the `{}` of this value.",
+                name
+            );
+            (quote!(#[doc = #doc]), quote!(#[doc = #doc_mut]))
+        } else {
+            let attrs = &getter.attrs;
+            (quote!(#(#attrs)*), quote!(#(#attrs)*))
+        };
+
+        quote! {
+            #attrs
+            #[inline]
+            #vis fn #name(&self) -> &#ty {
+                unsafe {
+                    let ptr = (#f)(#crate_::ForeignTypeRef::as_ptr(self));
+                    assert!(!ptr.is_null(), #null);
+                    #crate_::ForeignTypeRef::from_ptr_of(self, ptr)
+                }
+            }
+
+            #attrs_mut
+            #[inline]
+            #vis fn #name_mut(&mut self) -> &mut #ty {
+                unsafe {
+                    let ptr = (#f)(#crate_::ForeignTypeRef::as_ptr(self));
+                    assert!(!ptr.is_null(), #null);
+                    #crate_::ForeignTypeRef::from_ptr_mut_of(self, ptr)
+                }
+            }
+        }
+    });
+
+    quote! {
+        impl #impl_generics #ref_name #ty_generics {
+            #(#getters)*
+        }
+    }
+}
+
+fn build_setter_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    if input.setters.is_empty() {
+        return quote!();
+    }
+    let vis = &input.visibility;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let setters = input.setters.iter().map(|setter| {
+        let name = &setter.name;
+        let set_name = Ident::new(&format!("set_{}", name), name.span());
+        let ty = &setter.ty;
+        let f = &setter.setter;
+        let attrs = if setter.attrs.is_empty() {
+            let doc = if setter.takes {
+                format!("Sets the `{}` of this value, taking ownership of it.", name)
+            } else {
+                format!("Sets the `{}` of this value to a copy of `{}`.", name, name)
+            };
+            quote!(#[doc = #doc])
+        } else {
+            let attrs = &setter.attrs;
+            quote!(#(#attrs)*)
+        };
+        let (arg_ty, arg) = if setter.takes {
+            (quote!(#ty), quote!(#crate_::ForeignType::into_ptr(#name)))
+        } else {
+            (quote!(&#ty), quote!(#crate_::ForeignTypeRef::as_ptr(#name)))
+        };
+
+        let status = match &setter.status {
+            Some(status) => quote!(#status),
+            None => quote!(()),
+        };
+
+        quote! {
+            #attrs
+            #[inline]
+            #vis fn #set_name(&mut self, #name: #arg_ty) -> #status {
+                unsafe { (#f)(#crate_::ForeignTypeRef::as_ptr(self), #arg) }
+            }
+        }
+    });
+
+    quote! {
+        impl #impl_generics #ref_name #ty_generics {
+            #(#setters)*
+        }
+    }
+}
+
+fn build_compat_impls(crate_: &Path, input: &ForeignType, trait_: &Path) -> TokenStream {
+    let mut trait_ref = trait_.clone();
+    let last = trait_ref.segments.last_mut().unwrap();
+    last.ident = Ident::new("ForeignTypeRef", last.ident.span());
+    let name = &input.name;
+    let ctype = &input.ctype;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #trait_ for #name #ty_generics {
+            type CType = #ctype;
+            type Ref = #ref_name #ty_generics;
+
+            #[inline]
+            unsafe fn from_ptr(ptr: *mut #ctype) -> #name #ty_generics {
+                <Self as #crate_::ForeignType>::from_ptr(ptr)
+            }
+
+            #[inline]
+            fn as_ptr(&self) -> *mut #ctype {
+                <Self as #crate_::ForeignType>::as_ptr(self)
+            }
+        }
+
+        impl #impl_generics #trait_ref for #ref_name #ty_generics {
+            type CType = #ctype;
+        }
+    }
+}
+
+fn build_dtor_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let dtor = match &input.dtor {
+        Some(dtor) => dtor,
+        None => return quote!(),
+    };
+    let name = &input.name;
+    let vis = &input.visibility;
+    let ctype = &input.ctype;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let phantom_data = build_phantom_type(crate_, &input.phantom_data, &input.oibits)
+        .map(|_| quote!(, #crate_::export::PhantomData));
+    let track_acquire = build_track_acquire(crate_, input);
+    let testing_acquire = build_testing_acquire();
+    let tracked = build_tracked(input, true);
+    let trace_acquire = build_trace(
+        crate_,
+        input,
+        "FromPtr",
+        quote!(ptr, #crate_::export::null_mut()),
+    );
+
+    let shallow = input.drop_shallow.as_ref().map(|drop_shallow| {
+        let drop = &input.drop;
+        quote! {
+            /// Constructs an instance of this type from its raw type, freeing it and everything it
+            /// owns when dropped.
+            ///
+            /// This is equivalent to `ForeignType::from_ptr`.
+            ///
+            /// # Safety
+            ///
+            /// `ptr` must be a valid, owned instance of the native type which owns its contents.
+            #[inline]
+            #vis unsafe fn from_ptr_deep(ptr: *mut #ctype) -> #name #ty_generics {
+                Self::from_ptr_with_dtor(ptr, #drop)
+            }
+
+            /// Constructs an instance of this type from its raw type, freeing only the value itself
+            /// and not its contents when dropped.
+            ///
+            /// # Safety
+            ///
+            /// `ptr` must be a valid, owned instance of the native type whose contents are owned
+            /// elsewhere.
+            #[inline]
+            #vis unsafe fn from_ptr_shallow(ptr: *mut #ctype) -> #name #ty_generics {
+                Self::from_ptr_with_dtor(ptr, #drop_shallow)
+            }
+        }
+    });
+
+    quote! {
+        impl #impl_generics #name #ty_generics {
+            /// Constructs an instance of this type from its raw type, freeing it with `dtor`.
+            ///
+            /// # Safety
+            ///
+            /// `ptr` must be a valid, owned instance of the native type which can be freed with
+            /// `dtor`.
+            #[inline]
+            #vis unsafe fn from_ptr_with_dtor(ptr: *mut #ctype, dtor: #dtor) -> #name #ty_generics {
+                debug_assert!(!ptr.is_null());
+                #track_acquire
+                #testing_acquire
+                #trace_acquire
+                #name(<#crate_::export::NonNull<_>>::new_unchecked(ptr), dtor #tracked #phantom_data)
+            }
+
+            /// Returns the function which will be used to free this value.
+            #[inline]
+            #vis fn dtor(&self) -> &#dtor {
+                &self.1
+            }
+
+            #shallow
+        }
+    }
+}
+
+fn build_dynamic_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let vis = &input.visibility;
+    let name = &input.name;
+    let ctype = &input.ctype;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    let drop = if input.dynamic_drop {
+        quote! {
+            /// Sets the function used to free instances of this type.
+            ///
+            /// This must be called before any instance of this type is dropped, typically right
+            /// after the library defining the function has been loaded. Dropping an instance
+            /// before then panics.
+            ///
+            /// # Safety
+            ///
+            /// `f` must free the instances of this type it is passed, and must remain callable
+            /// for as long as instances may be dropped.
+            #vis unsafe fn set_drop_fn(f: unsafe extern "C" fn(*mut #ctype)) {
+                Self::__dynamic_drop().set(f);
+            }
+
+            #[doc(hidden)]
+            pub fn __dynamic_drop() -> &'static #crate_::dynamic::DropFn<#ctype> {
+                static DROP: #crate_::dynamic::DropFn<#ctype> = #crate_::dynamic::DropFn::new();
+                &DROP
+            }
+        }
+    } else {
+        quote!()
+    };
+
+    let clone = if input.dynamic_clone {
+        quote! {
+            /// Sets the function used to clone instances of this type.
+            ///
+            /// This must be called before any instance of this type is cloned, typically right
+            /// after the library defining the function has been loaded. Cloning an instance
+            /// before then panics.
+            ///
+            /// # Safety
+            ///
+            /// `f` must return a new, owned copy of the instance of this type it is passed, and
+            /// must remain callable for as long as instances may be cloned.
+            #vis unsafe fn set_clone_fn(f: unsafe extern "C" fn(*mut #ctype) -> *mut #ctype) {
+                Self::__dynamic_clone().set(f);
+            }
+
+            #[doc(hidden)]
+            pub fn __dynamic_clone() -> &'static #crate_::dynamic::CloneFn<#ctype> {
+                static CLONE: #crate_::dynamic::CloneFn<#ctype> = #crate_::dynamic::CloneFn::new();
+                &CLONE
+            }
+        }
+    } else {
+        quote!()
+    };
+
+    if drop.is_empty() && clone.is_empty() {
+        return quote!();
+    }
+
+    quote! {
+        impl #impl_generics #name #ty_generics {
+            #drop
+            #clone
+        }
+    }
+}
+
+fn build_debug_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    if !has_impl(input, "Debug") {
+        return quote!();
+    }
+    let name = &input.name;
+    let name_str = name.to_string();
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #crate_::export::fmt::Debug for #ref_name #ty_generics {
+            fn fmt(&self, fmt: &mut #crate_::export::fmt::Formatter) -> #crate_::export::fmt::Result {
+                fmt.debug_tuple(#name_str)
+                    .field(&#crate_::ForeignTypeRef::as_ptr(self))
+                    .finish()
+            }
+        }
+
+        impl #impl_generics #crate_::export::fmt::Debug for #name #ty_generics {
+            fn fmt(&self, fmt: &mut #crate_::export::fmt::Formatter) -> #crate_::export::fmt::Result {
+                #crate_::export::fmt::Debug::fmt(&**self, fmt)
+            }
+        }
+    }
+}
+
+fn build_handle_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    if !cfg!(feature = "std") || !has_impl(input, "AsHandle") {
+        return quote!();
+    }
+    let name = &input.name;
+    let vis = &input.visibility;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        #[cfg(windows)]
+        impl #impl_generics ::std::os::windows::io::AsRawHandle for #ref_name #ty_generics {
+            #[inline]
+            fn as_raw_handle(&self) -> ::std::os::windows::io::RawHandle {
+                #crate_::ForeignTypeRef::as_ptr(self).cast()
+            }
+        }
+
+        #[cfg(windows)]
+        impl #impl_generics ::std::os::windows::io::AsHandle for #ref_name #ty_generics {
+            #[inline]
+            fn as_handle(&self) -> ::std::os::windows::io::BorrowedHandle<'_> {
+                unsafe {
+                    ::std::os::windows::io::BorrowedHandle::borrow_raw(
+                        ::std::os::windows::io::AsRawHandle::as_raw_handle(self),
+                    )
+                }
+            }
+        }
+
+        #[cfg(windows)]
+        impl #impl_generics ::std::os::windows::io::AsRawHandle for #name #ty_generics {
+            #[inline]
+            fn as_raw_handle(&self) -> ::std::os::windows::io::RawHandle {
+                ::std::os::windows::io::AsRawHandle::as_raw_handle(&**self)
+            }
+        }
+
+        #[cfg(windows)]
+        impl #impl_generics ::std::os::windows::io::AsHandle for #name #ty_generics {
+            #[inline]
+            fn as_handle(&self) -> ::std::os::windows::io::BorrowedHandle<'_> {
+                ::std::os::windows::io::AsHandle::as_handle(&**self)
+            }
+        }
+
+        #[cfg(windows)]
+        impl #impl_generics ::std::os::windows::io::IntoRawHandle for #name #ty_generics {
+            #[inline]
+            fn into_raw_handle(self) -> ::std::os::windows::io::RawHandle {
+                #crate_::ForeignType::into_ptr(self).cast()
+            }
+        }
+
+        #[cfg(windows)]
+        impl #impl_generics #crate_::export::From<#name #ty_generics> for ::std::os::windows::io::OwnedHandle {
+            #[inline]
+            fn from(value: #name #ty_generics) -> ::std::os::windows::io::OwnedHandle {
+                unsafe {
+                    ::std::os::windows::io::FromRawHandle::from_raw_handle(
+                        ::std::os::windows::io::IntoRawHandle::into_raw_handle(value),
+                    )
+                }
+            }
+        }
+
+        #[cfg(windows)]
+        impl #impl_generics #crate_::export::From<::std::os::windows::io::OwnedHandle> for #name #ty_generics {
+            #[inline]
+            fn from(value: ::std::os::windows::io::OwnedHandle) -> #name #ty_generics {
+                unsafe {
+                    let handle = ::std::os::windows::io::IntoRawHandle::into_raw_handle(value);
+                    #crate_::ForeignType::from_ptr(handle.cast())
+                }
+            }
+        }
+
+        #[cfg(windows)]
+        impl #impl_generics #name #ty_generics {
+            /// Constructs an instance of this type from a raw handle, returning `None` if the handle
+            /// is null or `INVALID_HANDLE_VALUE`.
+            ///
+            /// # Safety
+            ///
+            /// If not null or `INVALID_HANDLE_VALUE`, `handle` must be a valid, owned handle.
+            #[inline]
+            #vis unsafe fn from_raw_handle_checked(
+                handle: ::std::os::windows::io::RawHandle,
+            ) -> #crate_::export::Option<#name #ty_generics> {
+                if handle.is_null() || #crate_::export::ptr_addr(handle) == usize::MAX {
+                    #crate_::export::Option::None
+                } else {
+                    #crate_::export::Option::Some(#crate_::ForeignType::from_ptr(handle.cast()))
+                }
+            }
+        }
+    }
+}
+
+fn build_ref_counted_impl(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let ref_ = match &input.clone {
+        Some(ref_) if input.refcounted => ref_,
+        _ => return quote!(),
+    };
+    let name = &input.name;
+    let ctype = &input.ctype;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        unsafe impl #impl_generics #crate_::RefCounted for #name #ty_generics {
+            #[inline]
+            unsafe fn retain(ptr: *mut #ctype) {
+                (#ref_)(ptr);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "glib")]
+fn build_glib_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let ref_ = match &input.clone {
+        Some(ref_) if input.refcounted => ref_,
+        _ => return quote!(),
+    };
+    let name = &input.name;
+    let ctype = &input.ctype;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let impl_params = &input.generics.params;
+
+    quote! {
+        impl #impl_generics #crate_::export::glib::translate::GlibPtrDefault for #name #ty_generics {
+            type GlibType = *mut #ctype;
+        }
+
+        impl #impl_generics #crate_::export::glib::translate::FromGlibPtrFull<*mut #ctype> for #name #ty_generics {
+            #[inline]
+            unsafe fn from_glib_full(ptr: *mut #ctype) -> #name #ty_generics {
+                #crate_::ForeignType::from_ptr(ptr)
+            }
+        }
+
+        impl #impl_generics #crate_::export::glib::translate::FromGlibPtrNone<*mut #ctype> for #name #ty_generics {
+            #[inline]
+            unsafe fn from_glib_none(ptr: *mut #ctype) -> #name #ty_generics {
+                #crate_::ForeignType::from_ptr((#ref_)(ptr))
+            }
+        }
+
+        impl<'__glib, #impl_params> #crate_::export::glib::translate::ToGlibPtr<'__glib, *mut #ctype> for #name #ty_generics {
+            type Storage = #crate_::export::PhantomData<&'__glib Self>;
+
+            #[inline]
+            fn to_glib_none(&'__glib self) -> #crate_::export::glib::translate::Stash<'__glib, *mut #ctype, Self> {
+                #crate_::export::glib::translate::Stash(#crate_::ForeignType::as_ptr(self), #crate_::export::PhantomData)
+            }
+
+            #[inline]
+            fn to_glib_full(&self) -> *mut #ctype {
+                unsafe { (#ref_)(#crate_::ForeignType::as_ptr(self)) }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "glib"))]
+fn build_glib_impls(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
+
+#[cfg(feature = "defmt")]
+fn build_defmt_impls(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = &input.name;
+    let ref_name = ref_name(input);
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+    let name_str = name.to_string();
+    let ref_name_str = ref_name.to_string();
+
+    quote! {
+        impl #impl_generics #crate_::export::defmt::Format for #name #ty_generics {
+            fn format(&self, fmt: #crate_::export::defmt::Formatter) {
+                #crate_::export::defmt_format_ptr(fmt, #name_str, #crate_::ForeignType::addr(self))
+            }
+        }
+
+        impl #impl_generics #crate_::export::defmt::Format for #ref_name #ty_generics {
+            fn format(&self, fmt: #crate_::export::defmt::Formatter) {
+                #crate_::export::defmt_format_ptr(
+                    fmt,
+                    #ref_name_str,
+                    #crate_::ForeignTypeRef::addr(self),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "defmt"))]
+fn build_defmt_impls(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
+
+#[cfg(feature = "testing")]
+fn build_testing_impl(crate_: &Path, input: &ForeignType) -> TokenStream {
+    let name = &input.name;
+    let vis = &input.visibility;
+    let (impl_generics, ty_generics, _) = input.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #name #ty_generics {
+            /// Returns the number of live owned instances of this type.
+            ///
+            /// The count is shared between all instantiations of the type's generic parameters.
+            #vis fn live_count() -> usize {
+                Self::__live_counter().get()
+            }
+
+            #[doc(hidden)]
+            pub fn __live_counter() -> &'static #crate_::testing::LiveCounter {
+                static COUNTER: #crate_::testing::LiveCounter = #crate_::testing::LiveCounter::new();
+                &COUNTER
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "testing"))]
+fn build_testing_impl(_: &Path, _: &ForeignType) -> TokenStream {
+    quote!()
+}
+
+/// Wraps a call to a user-supplied function so that a panic aborts instead of unwinding.
+#[cfg(feature = "abort-on-panic")]
+fn build_abort_on_panic(
+    crate_: &Path,
+    input: &ForeignType,
+    method: &str,
+    call: TokenStream,
+) -> TokenStream {
+    let context = format!("{}::{}", input.name, method);
+
+    quote! {
+        #crate_::panic::abort_on_panic(#context, || { #call })
+    }
+}
+
+#[cfg(not(feature = "abort-on-panic"))]
+fn build_abort_on_panic(_: &Path, _: &ForeignType, _: &str, call: TokenStream) -> TokenStream {
+    call
+}
+
+#[cfg(feature = "trace")]
+fn build_trace(crate_: &Path, input: &ForeignType, kind: &str, ptrs: TokenStream) -> TokenStream {
+    let name = input.name.to_string();
+    let kind = Ident::new(kind, input.name.span());
+
+    quote! {
+        #crate_::trace::emit(#name, #crate_::trace::EventKind::#kind, #ptrs);
+    }
+}
+
+#[cfg(not(feature = "trace"))]
+fn build_trace(_: &Path, _: &ForeignType, _: &str, _: TokenStream) -> TokenStream {
+    quote!()
+}
+
+#[cfg(feature = "testing")]
+fn build_testing_acquire() -> TokenStream {
+    quote! {
+        Self::__live_counter().increment();
+    }
+}
+
+#[cfg(not(feature = "testing"))]
+fn build_testing_acquire() -> TokenStream {
+    quote!()
+}
+
+#[cfg(feature = "testing")]
+fn build_testing_release() -> TokenStream {
+    quote! {
+        Self::__live_counter().decrement();
+    }
+}
+
+#[cfg(not(feature = "testing"))]
+fn build_testing_release() -> TokenStream {
+    quote!()
+}
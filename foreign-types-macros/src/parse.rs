@@ -1,31 +1,373 @@
+use proc_macro2::TokenTree;
+use quote::ToTokens;
+use syn::ext::IdentExt;
 use syn::parse::{self, Parse, ParseStream};
 use syn::punctuated::Punctuated;
 use syn::token;
-use syn::{braced, Attribute, Expr, Generics, Ident, Path, Token, Type, Visibility};
+use syn::{
+    braced, parenthesized, parse_quote, Attribute, Expr, Generics, Ident, Lifetime, LitBool,
+    LitStr, Pat, PatType, Path, Token, Type, Visibility,
+};
 
 mod kw {
     syn::custom_keyword!(Sync);
     syn::custom_keyword!(Send);
+    syn::custom_keyword!(UnwindSafe);
+    syn::custom_keyword!(RefUnwindSafe);
     syn::custom_keyword!(PhantomData);
     syn::custom_keyword!(CType);
+    syn::custom_keyword!(CTypeConst);
+    syn::custom_keyword!(Immutable);
+    syn::custom_keyword!(TollFreeBridged);
+    syn::custom_keyword!(CoreFoundation);
+    syn::custom_keyword!(Dtor);
+    syn::custom_keyword!(Pool);
+    syn::custom_keyword!(PY_CAPSULE_NAME);
+    syn::custom_keyword!(MUST_USE);
+    syn::custom_keyword!(STRIP_PREFIX);
+    syn::custom_keyword!(REF_SUFFIX);
+    syn::custom_keyword!(DROP_QUEUE);
     syn::custom_keyword!(drop);
+    syn::custom_keyword!(drop_shallow);
+    syn::custom_keyword!(drop_many);
     syn::custom_keyword!(clone);
+    syn::custom_keyword!(close);
+    syn::custom_keyword!(unref);
+    syn::custom_keyword!(free_array);
+    syn::custom_keyword!(get);
+    syn::custom_keyword!(set);
+    syn::custom_keyword!(takes);
+    syn::custom_keyword!(copies);
+    syn::custom_keyword!(from_bytes);
+    syn::custom_keyword!(from_str);
+    syn::custom_keyword!(cmp);
+    syn::custom_keyword!(last_error);
+    syn::custom_keyword!(read);
+    syn::custom_keyword!(message);
+    syn::custom_keyword!(code);
+    syn::custom_keyword!(pop);
+    syn::custom_keyword!(write);
+    syn::custom_keyword!(flush);
+    syn::custom_keyword!(map);
+    syn::custom_keyword!(new);
+    syn::custom_keyword!(insert);
+    syn::custom_keyword!(len);
+    syn::custom_keyword!(for_each);
+    syn::custom_keyword!(auto);
+    syn::custom_keyword!(data);
+    syn::custom_keyword!(begin_edit);
+    syn::custom_keyword!(commit_edit);
+    syn::custom_keyword!(rollback_edit);
 }
 
 pub struct Input {
     pub crate_: Path,
+    pub module: Option<Module>,
     pub types: Vec<ForeignType>,
+    pub statics: Vec<StaticRef>,
+    pub maps: Vec<ForeignMap>,
+    pub refs: Vec<RefType>,
 }
 
 impl Parse for Input {
     fn parse(input: ParseStream) -> parse::Result<Input> {
         let crate_ = input.parse()?;
+        let module = input.call(parse_module)?;
+        let naming = input.parse()?;
         let mut types = vec![];
+        let mut statics = vec![];
+        let mut maps = vec![];
+        let mut refs = vec![];
         while !input.is_empty() {
-            types.push(input.parse()?);
+            let fork = input.fork();
+            fork.call(Attribute::parse_outer)?;
+            fork.parse::<Visibility>()?;
+            if fork.peek(Token![static]) {
+                statics.push(input.parse()?);
+                continue;
+            }
+
+            fork.parse::<Option<Token![unsafe]>>()?;
+            fork.parse::<Option<Token![type]>>()?;
+            if fork.peek(Token![ref]) {
+                refs.push(RefType::parse(input, &naming)?);
+            } else if fork.peek(kw::map) && fork.peek2(syn::Ident) {
+                maps.push(input.parse()?);
+            } else {
+                types.push(ForeignType::parse(input, &naming)?);
+            }
+        }
+
+        Ok(Input {
+            crate_,
+            module,
+            types,
+            statics,
+            maps,
+            refs,
+        })
+    }
+}
+
+/// A module containing all generated items, declared with `mod name;`.
+pub struct Module {
+    pub attrs: Vec<Attribute>,
+    pub visibility: Visibility,
+    pub name: Ident,
+}
+
+fn parse_module(input: ParseStream) -> parse::Result<Option<Module>> {
+    let fork = input.fork();
+    fork.call(Attribute::parse_outer)?;
+    fork.parse::<Visibility>()?;
+    if !fork.peek(Token![mod]) {
+        return Ok(None);
+    }
+
+    let attrs = input.call(Attribute::parse_outer)?;
+    let visibility = input.parse()?;
+    input.parse::<Token![mod]>()?;
+    let name = input.parse()?;
+    input.parse::<Token![;]>()?;
+    Ok(Some(Module {
+        attrs,
+        visibility,
+        name,
+    }))
+}
+
+/// The naming scheme applied to every type, declared with `const STRIP_PREFIX` and
+/// `const REF_SUFFIX`.
+pub struct Naming {
+    strip_prefix: Option<LitStr>,
+    ref_suffix: String,
+}
+
+impl Parse for Naming {
+    fn parse(input: ParseStream) -> parse::Result<Naming> {
+        let strip_prefix = if input.peek(Token![const]) && input.peek2(kw::STRIP_PREFIX) {
+            Some(input.call(parse_const::<kw::STRIP_PREFIX, LitStr>)?)
+        } else {
+            None
+        };
+        let ref_suffix = if input.peek(Token![const]) && input.peek2(kw::REF_SUFFIX) {
+            input.call(parse_const::<kw::REF_SUFFIX, LitStr>)?.value()
+        } else {
+            "Ref".to_string()
+        };
+
+        Ok(Naming {
+            strip_prefix,
+            ref_suffix,
+        })
+    }
+}
+
+impl Naming {
+    /// Returns the name of a type declared as `name`, and the name of its reference type.
+    fn apply(&self, name: Ident) -> parse::Result<(Ident, Ident)> {
+        let name = self.strip(name)?;
+        let ref_name = syn::parse_str::<Ident>(&format!("{}{}", name, self.ref_suffix))
+            .map(|ref_name| Ident::new(&ref_name.to_string(), name.span()))
+            .map_err(|_| {
+                parse::Error::new(
+                    name.span(),
+                    format!("`{}{}` is not a valid name", name, self.ref_suffix),
+                )
+            })?;
+        Ok((name, ref_name))
+    }
+
+    /// Returns the name of a type declared as `name` with the prefix stripped.
+    fn strip(&self, name: Ident) -> parse::Result<Ident> {
+        let name = match &self.strip_prefix {
+            Some(prefix) => match name.to_string().strip_prefix(&prefix.value()) {
+                Some(stripped) => syn::parse_str::<Ident>(stripped)
+                    .map(|stripped| Ident::new(&stripped.to_string(), name.span()))
+                    .map_err(|_| {
+                        parse::Error::new(
+                            name.span(),
+                            format!("`{}` is not a valid name after stripping the prefix", name),
+                        )
+                    })?,
+                None => name,
+            },
+            None => name,
+        };
+        Ok(name)
+    }
+}
+
+pub struct StaticRef {
+    pub attrs: Vec<Attribute>,
+    pub visibility: Visibility,
+    pub name: Ident,
+    pub ty: Type,
+    pub init: Expr,
+}
+
+impl Parse for StaticRef {
+    fn parse(input: ParseStream) -> parse::Result<StaticRef> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let visibility = input.parse()?;
+        input.parse::<Token![static]>()?;
+        input.parse::<Token![ref]>()?;
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let ty = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let init = input.parse()?;
+        input.parse::<Token![;]>()?;
+
+        Ok(StaticRef {
+            attrs,
+            visibility,
+            name,
+            ty,
+            init,
+        })
+    }
+}
+
+/// A type which is only ever borrowed, declared with `unsafe type ref Name { ... }`.
+pub struct RefType {
+    pub attrs: Vec<Attribute>,
+    pub visibility: Visibility,
+    pub name: Ident,
+    pub generics: Generics,
+    pub oibits: Punctuated<Oibit, Token![+]>,
+    pub ctype: Type,
+    pub phantom_data: Option<Type>,
+}
+
+impl RefType {
+    fn parse(input: ParseStream, naming: &Naming) -> parse::Result<RefType> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let visibility = input.parse()?;
+        input.parse::<Token![unsafe]>()?;
+        input.parse::<Token![type]>()?;
+        input.parse::<Token![ref]>()?;
+        let name = naming.strip(input.parse()?)?;
+        let generics = input.parse()?;
+        let oibits = input.call(parse_oibits)?;
+        let inner;
+        let brace = braced!(inner in input);
+        let mut ctype = None;
+        let mut phantom_data = None;
+        while !inner.is_empty() {
+            let (name, key) = peek_item_key(&inner.fork(), REF_KEYS)?;
+            match &*key {
+                "type CType" => set_once(&mut ctype, inner.call(parse_type::<kw::CType>)?),
+                "type PhantomData" => set_once(
+                    &mut phantom_data,
+                    inner.call(parse_type::<kw::PhantomData>)?,
+                ),
+                _ => return Err(unknown_item(&name, &key, REF_KEYS)),
+            }
+            .map_err(|()| duplicate_item(&name, &key))?;
+        }
+
+        let ctype = match ctype {
+            Some(ctype) => ctype,
+            None => return Err(parse::Error::new(brace.span, "missing `type CType`")),
+        };
+        if let Type::Ptr(_) | Type::Reference(_) = ctype {
+            return Err(parse::Error::new_spanned(
+                ctype,
+                "`CType` must be the type pointed to, not a pointer or reference",
+            ));
+        }
+
+        Ok(RefType {
+            attrs,
+            visibility,
+            name,
+            generics,
+            oibits,
+            ctype,
+            phantom_data,
+        })
+    }
+}
+
+/// A map type, declared with `unsafe type map Name<Key => Value> { ... }`.
+pub struct ForeignMap {
+    pub attrs: Vec<Attribute>,
+    pub visibility: Visibility,
+    pub name: Ident,
+    pub key: Type,
+    pub value: Type,
+    pub ctype: Type,
+    pub new: Expr,
+    pub drop: Expr,
+    pub insert_takes: bool,
+    pub insert: Expr,
+    pub get: Expr,
+    pub len: Expr,
+    pub for_each: Expr,
+}
+
+impl Parse for ForeignMap {
+    fn parse(input: ParseStream) -> parse::Result<ForeignMap> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let visibility = input.parse()?;
+        input.parse::<Token![unsafe]>()?;
+        input.parse::<Token![type]>()?;
+        input.parse::<kw::map>()?;
+        let name = input.parse()?;
+        input.parse::<Token![<]>()?;
+        let key = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        let value = input.parse()?;
+        input.parse::<Token![>]>()?;
+        let content;
+        let brace = braced!(content in input);
+        let mut ctype = None;
+        let mut new = None;
+        let mut drop = None;
+        let mut insert = None;
+        let mut get = None;
+        let mut len = None;
+        let mut for_each = None;
+        while !content.is_empty() {
+            let (name, key) = peek_item_key(&content.fork(), MAP_KEYS)?;
+            match &*key {
+                "type CType" => set_once(&mut ctype, content.call(parse_type::<kw::CType>)?),
+                "fn new" => set_once(&mut new, content.call(parse_fn::<kw::new>)?),
+                "fn drop" => set_once(&mut drop, content.call(parse_fn::<kw::drop>)?),
+                "fn insert" => set_once(&mut insert, content.call(parse_insert)?),
+                "fn get" => set_once(&mut get, content.call(parse_fn::<kw::get>)?),
+                "fn len" => set_once(&mut len, content.call(parse_fn::<kw::len>)?),
+                "fn for_each" => set_once(&mut for_each, content.call(parse_fn::<kw::for_each>)?),
+                _ => return Err(unknown_item(&name, &key, MAP_KEYS)),
+            }
+            .map_err(|()| duplicate_item(&name, &key))?;
         }
 
-        Ok(Input { crate_, types })
+        let missing = |key: &str| parse::Error::new(brace.span, format!("missing `{}`", key));
+        let ctype = ctype.ok_or_else(|| missing("type CType"))?;
+        let new = new.ok_or_else(|| missing("fn new"))?;
+        let drop = drop.ok_or_else(|| missing("fn drop"))?;
+        let (insert_takes, insert) = insert.ok_or_else(|| missing("fn insert"))?;
+        let get = get.ok_or_else(|| missing("fn get"))?;
+        let len = len.ok_or_else(|| missing("fn len"))?;
+        let for_each = for_each.ok_or_else(|| missing("fn for_each"))?;
+
+        Ok(ForeignMap {
+            attrs,
+            visibility,
+            name,
+            key,
+            value,
+            ctype,
+            new,
+            drop,
+            insert_takes,
+            insert,
+            get,
+            len,
+            for_each,
+        })
     }
 }
 
@@ -33,55 +375,663 @@ pub struct ForeignType {
     pub attrs: Vec<Attribute>,
     pub visibility: Visibility,
     pub name: Ident,
+    pub ref_name: Ident,
     pub generics: Generics,
-    pub oibits: Punctuated<Ident, Token![+]>,
+    pub oibits: Punctuated<Oibit, Token![+]>,
     pub phantom_data: Option<Type>,
     pub ctype: Type,
+    pub ctype_const: Option<Type>,
+    pub immutable: Option<Type>,
+    pub toll_free_bridged: Option<Type>,
+    pub core_foundation: Option<Type>,
+    pub dtor: Option<Type>,
+    pub pool: Option<(Lifetime, Type)>,
+    pub py_capsule_name: Option<LitStr>,
+    pub must_use: bool,
+    pub drop_queue: Option<Expr>,
     pub drop: Expr,
+    pub drop_shallow: Option<Expr>,
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub drop_many: Option<Expr>,
     pub clone: Option<Expr>,
+    pub close: Option<Expr>,
+    pub free_array: Option<Expr>,
+    pub from_bytes: Option<Expr>,
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub from_str: Option<Expr>,
+    pub cmp: Option<Expr>,
+    pub last_error: Option<(Type, Expr)>,
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub read: Option<Expr>,
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub write: Option<Expr>,
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub flush: Option<Expr>,
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub message: Option<Expr>,
+    pub code: Option<(Type, Expr)>,
+    pub pop: Option<(Type, Expr)>,
+    pub bytes: Option<(Expr, Expr)>,
+    pub edit: Option<(Expr, Expr, Option<Expr>)>,
+    pub getters: Vec<Getter>,
+    pub setters: Vec<Setter>,
+    #[cfg_attr(not(feature = "glib"), allow(dead_code))]
+    pub refcounted: bool,
+    pub dynamic_drop: bool,
+    pub dynamic_clone: bool,
+    pub impls: Vec<Path>,
 }
 
-impl Parse for ForeignType {
-    fn parse(input: ParseStream) -> parse::Result<ForeignType> {
+impl ForeignType {
+    fn parse(input: ParseStream, naming: &Naming) -> parse::Result<ForeignType> {
         let attrs = input.call(Attribute::parse_outer)?;
         let visibility = input.parse()?;
         input.parse::<Token![unsafe]>()?;
         input.parse::<Token![type]>()?;
-        let name = input.parse()?;
-        let generics = input.parse()?;
+        let (name, ref_name) = naming.apply(input.parse()?)?;
+        let generics: Generics = input.parse()?;
         let oibits = input.call(parse_oibits)?;
         let inner;
-        braced!(inner in input);
-        let ctype = inner.call(parse_type::<kw::CType>)?;
-        let phantom_data = inner.call(parse_phantom_data)?;
-        let drop = inner.call(parse_fn::<kw::drop>)?;
-        let clone = inner.call(parse_clone)?;
+        let brace = braced!(inner in input);
+        let mut items = Items::default();
+        while !inner.is_empty() {
+            items.parse_item(&inner)?;
+        }
+
+        let ctype = match items.ctype {
+            Some(ctype) => ctype,
+            None => return Err(parse::Error::new(brace.span, "missing `type CType`")),
+        };
+        if let Type::Ptr(_) | Type::Reference(_) = ctype {
+            return Err(parse::Error::new_spanned(
+                ctype,
+                "`CType` must be the type pointed to, not a pointer or reference",
+            ));
+        }
+        let ctype_const = items.ctype_const;
+        if let Some(Type::Ptr(_) | Type::Reference(_)) = ctype_const {
+            return Err(parse::Error::new_spanned(
+                ctype_const,
+                "`CTypeConst` must be the type pointed to, not a pointer or reference",
+            ));
+        }
+        let dtor = items.dtor;
+        let pool =
+            match items.pool {
+                Some(pool) => match generics.lifetimes().next() {
+                    Some(def) => Some((def.lifetime.clone(), pool)),
+                    None => return Err(parse::Error::new_spanned(
+                        pool,
+                        "pool-allocated types must have a lifetime parameter for the pool borrow",
+                    )),
+                },
+                None => None,
+            };
+        let phantom_data = match (&pool, items.phantom_data) {
+            (Some((lifetime, pool)), Some(phantom_data)) => {
+                Some(parse_quote!((&#lifetime #pool, #phantom_data)))
+            }
+            (Some((lifetime, pool)), None) => Some(parse_quote!(&#lifetime #pool)),
+            (None, phantom_data) => phantom_data,
+        };
+        let must_use = match items.must_use {
+            Some(lit) => lit.value,
+            None => true,
+        };
+        let py_capsule_name = items.py_capsule_name;
+        if let Some(capsule_name) = &py_capsule_name {
+            if capsule_name.value().contains('\0') {
+                return Err(parse::Error::new_spanned(
+                    capsule_name,
+                    "capsule names cannot contain nul bytes",
+                ));
+            }
+        }
+        let message = "`const PY_CAPSULE_NAME` requires values to be freed with `fn drop`, and \
+                       cannot be combined with `type Dtor`, `type Pool` or `fn drop_shallow`";
+        check_conflict(&py_capsule_name, &dtor, message)?;
+        check_conflict(&py_capsule_name, &pool, message)?;
+        check_conflict(&py_capsule_name, &items.drop_shallow, message)?;
+        let drop_queue = items.drop_queue;
+        check_conflict(
+            &drop_queue,
+            &dtor,
+            "`static DROP_QUEUE` cannot be combined with `type Dtor`",
+        )?;
+        if pool.is_some() {
+            let message = "pool-allocated types are freed with their pool, and cannot have a \
+                           destructor";
+            check_conflict(&items.drop, &pool, message)?;
+            check_conflict(&items.drop_shallow, &pool, message)?;
+            check_conflict(&items.drop_many, &pool, message)?;
+            check_conflict(&items.ref_, &pool, message)?;
+            check_conflict(&items.unref, &pool, message)?;
+        }
+        let refcount = items.ref_.as_ref().or(items.unref.as_ref());
+        let message = "reference counted types cannot also have `fn drop`, `fn clone`, \
+                       `fn drop_shallow` or `fn drop_many`";
+        check_conflict(&items.drop, &refcount, message)?;
+        check_conflict(&items.clone, &refcount, message)?;
+        check_conflict(&items.drop_shallow, &refcount, message)?;
+        check_conflict(&items.drop_many, &refcount, message)?;
+        let (drop, drop_shallow, drop_many, clone, refcounted) = if pool.is_some() {
+            (Some(parse_quote!(|_| {})), None, None, items.clone, false)
+        } else if let Some(ref_) = items.ref_ {
+            let unref = match items.unref {
+                Some(unref) => unref,
+                None => {
+                    return Err(parse::Error::new_spanned(
+                        ref_,
+                        "`fn ref` requires `fn unref`",
+                    ))
+                }
+            };
+            (Some(unref), None, None, Some(Some(ref_)), true)
+        } else if let Some(unref) = items.unref {
+            return Err(parse::Error::new_spanned(
+                unref,
+                "`fn unref` requires `fn ref`",
+            ));
+        } else {
+            let drop = match items.drop {
+                Some(drop) => drop,
+                None => return Err(parse::Error::new(brace.span, "missing `fn drop`")),
+            };
+            (
+                drop,
+                items.drop_shallow,
+                items.drop_many,
+                items.clone,
+                false,
+            )
+        };
+        if let Some(drop_many) = &drop_many {
+            if drop_queue.is_some() || dtor.is_some() || drop_shallow.is_some() {
+                return Err(parse::Error::new_spanned(
+                    drop_many,
+                    "`fn drop_many` cannot be combined with `static DROP_QUEUE`, `type Dtor` or \
+                     `fn drop_shallow`",
+                ));
+            }
+        }
+        let drop = drop.map(|drop| annotate_closure(drop, &ctype));
+        let drop_shallow = drop_shallow.map(|drop_shallow| annotate_closure(drop_shallow, &ctype));
+        let clone = clone.map(|clone| clone.map(|clone| annotate_closure(clone, &ctype)));
+        check_conflict(
+            &drop_queue,
+            &drop_shallow,
+            "`static DROP_QUEUE` cannot be combined with `fn drop_shallow`",
+        )?;
+        let (_, ty_generics, _) = generics.split_for_impl();
+        let dynamic_drop = drop.is_none();
+        let drop = drop.unwrap_or_else(
+            || parse_quote!(|ptr| <#name #ty_generics>::__dynamic_drop().call(ptr)),
+        );
+        // Without an explicit `type Dtor`, the destructors are stored as Rust function pointers, which
+        // C functions and closures are wrapped to coerce to.
+        let (dtor, drop, drop_shallow) = match (dtor, drop_shallow) {
+            (None, Some(drop_shallow)) => (
+                Some(parse_quote!(unsafe fn(*mut #ctype))),
+                parse_quote!(|ptr| unsafe { (#drop)(ptr) }),
+                Some(parse_quote!(|ptr| unsafe { (#drop_shallow)(ptr) })),
+            ),
+            (dtor, drop_shallow) => (dtor, drop, drop_shallow),
+        };
+        let dynamic_clone = matches!(clone, Some(None));
+        let clone = clone.map(|clone| {
+            clone.unwrap_or_else(
+                || parse_quote!(|ptr| <#name #ty_generics>::__dynamic_clone().call(ptr)),
+            )
+        });
+        let last_error = items.last_error;
+        let read = items.read;
+        let write = items.write;
+        let flush = items.flush;
+        if let (Some(flush), None) = (&flush, &write) {
+            return Err(parse::Error::new_spanned(
+                flush,
+                "`fn flush` requires `fn write`",
+            ));
+        }
+        if let Some((ty @ Type::Ptr(_), _)) = &last_error {
+            return Err(parse::Error::new_spanned(
+                ty,
+                "`fn last_error` must return an error code, such as an integer, rather than a \
+                 pointer",
+            ));
+        }
+        let message = items.message;
+        let code = items.code;
+        let bytes = match (items.len, items.data) {
+            (Some(len), Some(data)) => Some((len, data)),
+            (Some(len), None) => {
+                return Err(parse::Error::new_spanned(
+                    len,
+                    "`fn len` requires `fn data`",
+                ))
+            }
+            (None, Some(data)) => {
+                return Err(parse::Error::new_spanned(
+                    data,
+                    "`fn data` requires `fn len`",
+                ))
+            }
+            (None, None) => None,
+        };
+        let edit = match (items.begin_edit, items.commit_edit, items.rollback_edit) {
+            (Some(begin), Some(commit), rollback) => Some((begin, commit, rollback)),
+            (Some(begin), None, _) => {
+                return Err(parse::Error::new_spanned(
+                    begin,
+                    "`fn begin_edit` requires `fn commit_edit`",
+                ))
+            }
+            (None, Some(f), _) | (None, None, Some(f)) => {
+                return Err(parse::Error::new_spanned(
+                    f,
+                    "`fn commit_edit` and `fn rollback_edit` require `fn begin_edit`",
+                ))
+            }
+            (None, None, None) => None,
+        };
+        let impls = items.impls;
+        if let (Some(_), Some(debug)) = (&message, impls.iter().find(|i| i.is_ident("Debug"))) {
+            return Err(parse::Error::new_spanned(
+                debug,
+                "`impl Debug` cannot be combined with `fn message`",
+            ));
+        }
 
         Ok(ForeignType {
             attrs,
             visibility,
             name,
+            ref_name,
             generics,
             oibits,
             ctype,
+            ctype_const,
             phantom_data,
+            immutable: items.immutable,
+            toll_free_bridged: items.toll_free_bridged,
+            core_foundation: items.core_foundation,
+            dtor,
+            pool,
+            py_capsule_name,
+            must_use,
+            drop_queue,
             drop,
+            drop_shallow,
+            drop_many,
             clone,
+            close: items.close,
+            free_array: items.free_array,
+            from_bytes: items.from_bytes,
+            from_str: items.from_str,
+            cmp: items.cmp,
+            last_error,
+            read,
+            write,
+            flush,
+            message,
+            code,
+            pop: items.pop,
+            bytes,
+            edit,
+            getters: items.getters,
+            setters: items.setters,
+            refcounted,
+            dynamic_drop,
+            dynamic_clone,
+            impls,
+        })
+    }
+}
+
+/// The items accepted in the body of `unsafe type`, listed in error messages.
+const KEYS: &str = "`type CType`, `type CTypeConst`, `type PhantomData`, `type Immutable`, \
+                    `type TollFreeBridged`, `type CoreFoundation`, `type Dtor`, `type Pool`, \
+                    `const PY_CAPSULE_NAME`, `const MUST_USE`, `static DROP_QUEUE`, `fn drop`, \
+                    `fn drop_shallow`, `fn drop_many`, `fn clone`, `fn ref`, `fn unref`, \
+                    `fn close`, `fn free_array`, `fn from_bytes`, `fn from_str`, `fn cmp`, \
+                    `fn last_error`, `fn read`, `fn write`, `fn flush`, `fn message`, `fn code`, \
+                    `fn pop`, `fn len`, `fn data`, `fn begin_edit`, `fn commit_edit`, \
+                    `fn rollback_edit`, `fn get`, `fn set` and `impl`";
+
+/// The items accepted in the body of `unsafe type ref`, listed in error messages.
+const REF_KEYS: &str = "`type CType` and `type PhantomData`";
+
+/// The items accepted in the body of `unsafe type map`, listed in error messages.
+const MAP_KEYS: &str = "`type CType`, `fn new`, `fn drop`, `fn insert`, `fn get`, `fn len` and \
+                        `fn for_each`";
+
+/// The items in the body of `unsafe type`, which may be given in any order.
+#[derive(Default)]
+struct Items {
+    ctype: Option<Type>,
+    ctype_const: Option<Type>,
+    phantom_data: Option<Type>,
+    immutable: Option<Type>,
+    toll_free_bridged: Option<Type>,
+    core_foundation: Option<Type>,
+    dtor: Option<Type>,
+    pool: Option<Type>,
+    py_capsule_name: Option<LitStr>,
+    must_use: Option<LitBool>,
+    drop_queue: Option<Expr>,
+    drop: Option<Option<Expr>>,
+    drop_shallow: Option<Expr>,
+    drop_many: Option<Expr>,
+    clone: Option<Option<Expr>>,
+    ref_: Option<Expr>,
+    unref: Option<Expr>,
+    close: Option<Expr>,
+    free_array: Option<Expr>,
+    from_bytes: Option<Expr>,
+    from_str: Option<Expr>,
+    cmp: Option<Expr>,
+    last_error: Option<(Type, Expr)>,
+    read: Option<Expr>,
+    write: Option<Expr>,
+    flush: Option<Expr>,
+    message: Option<Expr>,
+    code: Option<(Type, Expr)>,
+    pop: Option<(Type, Expr)>,
+    len: Option<Expr>,
+    data: Option<Expr>,
+    begin_edit: Option<Expr>,
+    commit_edit: Option<Expr>,
+    rollback_edit: Option<Expr>,
+    getters: Vec<Getter>,
+    setters: Vec<Setter>,
+    impls: Vec<Path>,
+}
+
+impl Items {
+    fn parse_item(&mut self, input: ParseStream) -> parse::Result<()> {
+        let fork = input.fork();
+        let attrs = fork.call(Attribute::parse_outer)?;
+        if fork.peek(Token![impl]) {
+            let impl_ = input.call(parse_impl)?;
+            let key = impl_.to_token_stream().to_string();
+            if self
+                .impls
+                .iter()
+                .any(|i| i.to_token_stream().to_string() == key)
+            {
+                return Err(parse::Error::new_spanned(
+                    &impl_,
+                    format!("duplicate `impl {}`", key.replace(' ', "")),
+                ));
+            }
+            self.impls.push(impl_);
+            return Ok(());
+        };
+        let (name, key) = peek_item_key(&fork, KEYS)?;
+        if let Some(attr) = attrs.first() {
+            if key != "fn get" && key != "fn set" {
+                return Err(parse::Error::new_spanned(
+                    attr,
+                    "attributes are only allowed on `fn get` and `fn set`",
+                ));
+            }
+        }
+
+        match &*key {
+            "type CType" => set_once(&mut self.ctype, input.call(parse_type::<kw::CType>)?),
+            "type CTypeConst" => set_once(
+                &mut self.ctype_const,
+                input.call(parse_type::<kw::CTypeConst>)?,
+            ),
+            "type PhantomData" => set_once(
+                &mut self.phantom_data,
+                input.call(parse_type::<kw::PhantomData>)?,
+            ),
+            "type Immutable" => set_once(
+                &mut self.immutable,
+                input.call(parse_type::<kw::Immutable>)?,
+            ),
+            "type TollFreeBridged" => set_once(
+                &mut self.toll_free_bridged,
+                input.call(parse_type::<kw::TollFreeBridged>)?,
+            ),
+            "type CoreFoundation" => set_once(
+                &mut self.core_foundation,
+                input.call(parse_type::<kw::CoreFoundation>)?,
+            ),
+            "type Dtor" => set_once(&mut self.dtor, input.call(parse_type::<kw::Dtor>)?),
+            "type Pool" => set_once(&mut self.pool, input.call(parse_type::<kw::Pool>)?),
+            "const PY_CAPSULE_NAME" => set_once(
+                &mut self.py_capsule_name,
+                input.call(parse_const::<kw::PY_CAPSULE_NAME, LitStr>)?,
+            ),
+            "const MUST_USE" => set_once(
+                &mut self.must_use,
+                input.call(parse_const::<kw::MUST_USE, LitBool>)?,
+            ),
+            "static DROP_QUEUE" => set_once(&mut self.drop_queue, input.call(parse_drop_queue)?),
+            "fn drop" => set_once(&mut self.drop, input.call(parse_fn_or_dyn::<kw::drop>)?),
+            "fn drop_shallow" => set_once(
+                &mut self.drop_shallow,
+                input.call(parse_fn::<kw::drop_shallow>)?,
+            ),
+            "fn drop_many" => set_once(&mut self.drop_many, input.call(parse_fn::<kw::drop_many>)?),
+            "fn clone" => set_once(&mut self.clone, input.call(parse_fn_or_dyn::<kw::clone>)?),
+            "fn ref" => set_once(&mut self.ref_, input.call(parse_fn::<Token![ref]>)?),
+            "fn unref" => set_once(&mut self.unref, input.call(parse_fn::<kw::unref>)?),
+            "fn close" => set_once(&mut self.close, input.call(parse_fn::<kw::close>)?),
+            "fn free_array" => set_once(
+                &mut self.free_array,
+                input.call(parse_fn::<kw::free_array>)?,
+            ),
+            "fn from_bytes" => set_once(
+                &mut self.from_bytes,
+                input.call(parse_fn::<kw::from_bytes>)?,
+            ),
+            "fn from_str" => set_once(&mut self.from_str, input.call(parse_fn::<kw::from_str>)?),
+            "fn cmp" => set_once(&mut self.cmp, input.call(parse_fn::<kw::cmp>)?),
+            "fn last_error" => set_once(
+                &mut self.last_error,
+                input.call(parse_typed_fn::<kw::last_error>)?,
+            ),
+            "fn read" => set_once(&mut self.read, input.call(parse_fn::<kw::read>)?),
+            "fn write" => set_once(&mut self.write, input.call(parse_fn::<kw::write>)?),
+            "fn flush" => set_once(&mut self.flush, input.call(parse_fn::<kw::flush>)?),
+            "fn message" => set_once(&mut self.message, input.call(parse_fn::<kw::message>)?),
+            "fn code" => set_once(&mut self.code, input.call(parse_typed_fn::<kw::code>)?),
+            "fn pop" => set_once(&mut self.pop, input.call(parse_typed_fn::<kw::pop>)?),
+            "fn len" => set_once(&mut self.len, input.call(parse_fn::<kw::len>)?),
+            "fn data" => set_once(&mut self.data, input.call(parse_fn::<kw::data>)?),
+            "fn begin_edit" => set_once(
+                &mut self.begin_edit,
+                input.call(parse_fn::<kw::begin_edit>)?,
+            ),
+            "fn commit_edit" => set_once(
+                &mut self.commit_edit,
+                input.call(parse_fn::<kw::commit_edit>)?,
+            ),
+            "fn rollback_edit" => set_once(
+                &mut self.rollback_edit,
+                input.call(parse_fn::<kw::rollback_edit>)?,
+            ),
+            "fn get" => {
+                self.getters.push(input.parse()?);
+                Ok(())
+            }
+            "fn set" => {
+                self.setters.push(input.parse()?);
+                Ok(())
+            }
+            _ => return Err(unknown_item(&name, &key, KEYS)),
+        }
+        .map_err(|()| duplicate_item(&name, &key))
+    }
+}
+
+/// Returns the name of the next item in a body, and its key, such as `fn drop`, without consuming
+/// it.
+fn peek_item_key(fork: ParseStream, keys: &str) -> parse::Result<(Ident, String)> {
+    let kind = if fork.peek(Token![type]) {
+        "type"
+    } else if fork.peek(Token![const]) {
+        "const"
+    } else if fork.peek(Token![static]) {
+        "static"
+    } else if fork.peek(Token![fn]) {
+        "fn"
+    } else {
+        return Err(fork.error(format!("expected one of {}", keys)));
+    };
+    fork.parse::<TokenTree>()?;
+    let name = fork.call(Ident::parse_any)?;
+    let key = format!("{} {}", kind, name);
+    Ok((name, key))
+}
+
+fn unknown_item(name: &Ident, key: &str, keys: &str) -> parse::Error {
+    parse::Error::new(
+        name.span(),
+        format!("unknown item `{}`, expected one of {}", key, keys),
+    )
+}
+
+fn duplicate_item(name: &Ident, key: &str) -> parse::Error {
+    parse::Error::new(name.span(), format!("duplicate `{}`", key))
+}
+
+/// Stores an item, failing if it was already given.
+fn set_once<T>(slot: &mut Option<T>, value: T) -> Result<(), ()> {
+    if slot.is_some() {
+        return Err(());
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// Returns an error spanning `item` if `conflict` was also given.
+fn check_conflict<T, U>(item: &Option<T>, conflict: &Option<U>, message: &str) -> parse::Result<()>
+where
+    T: ToTokens,
+{
+    match (item, conflict) {
+        (Some(item), Some(_)) => Err(parse::Error::new_spanned(item, message)),
+        _ => Ok(()),
+    }
+}
+
+pub struct Getter {
+    pub attrs: Vec<Attribute>,
+    pub name: Ident,
+    pub ty: Type,
+    pub getter: Expr,
+}
+
+impl Parse for Getter {
+    fn parse(input: ParseStream) -> parse::Result<Getter> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        input.parse::<Token![fn]>()?;
+        input.parse::<kw::get>()?;
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let ty = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let getter = input.parse()?;
+        input.parse::<Token![;]>()?;
+
+        Ok(Getter {
+            attrs,
+            name,
+            ty,
+            getter,
         })
     }
 }
 
-fn parse_oibit(input: ParseStream) -> parse::Result<Ident> {
+pub struct Setter {
+    pub attrs: Vec<Attribute>,
+    pub name: Ident,
+    pub takes: bool,
+    pub ty: Type,
+    pub status: Option<Type>,
+    pub setter: Expr,
+}
+
+impl Parse for Setter {
+    fn parse(input: ParseStream) -> parse::Result<Setter> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        input.parse::<Token![fn]>()?;
+        input.parse::<kw::set>()?;
+        let name = input.parse()?;
+        let takes = input.call(parse_ownership)?;
+        input.parse::<Token![:]>()?;
+        let ty = input.parse()?;
+        let status = if input.peek(Token![->]) {
+            input.parse::<Token![->]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        input.parse::<Token![=]>()?;
+        let setter = input.parse()?;
+        input.parse::<Token![;]>()?;
+
+        Ok(Setter {
+            attrs,
+            name,
+            takes,
+            ty,
+            status,
+            setter,
+        })
+    }
+}
+
+/// Parses `(takes)` or `(copies)`, returning `true` for the former.
+fn parse_ownership(input: ParseStream) -> parse::Result<bool> {
+    let mode;
+    parenthesized!(mode in input);
+    let lookahead = mode.lookahead1();
+    if lookahead.peek(kw::takes) {
+        mode.parse::<kw::takes>()?;
+        Ok(true)
+    } else if lookahead.peek(kw::copies) {
+        mode.parse::<kw::copies>()?;
+        Ok(false)
+    } else {
+        Err(lookahead.error())
+    }
+}
+
+/// A marker trait listed after the name of a type, which is suppressed if preceded by `!`.
+pub struct Oibit {
+    pub negative: Option<Token![!]>,
+    pub name: Ident,
+}
+
+impl Oibit {
+    pub fn is(&self, name: &str) -> bool {
+        self.negative.is_none() && self.name == name
+    }
+}
+
+fn parse_oibit(input: ParseStream) -> parse::Result<Oibit> {
+    let negative = input.parse::<Option<Token![!]>>()?;
     let lookahead = input.lookahead1();
-    if lookahead.peek(kw::Sync) || lookahead.peek(kw::Send) {
-        input.parse()
+    let unwind = lookahead.peek(kw::UnwindSafe) || lookahead.peek(kw::RefUnwindSafe);
+    if unwind || (negative.is_none() && (lookahead.peek(kw::Sync) || lookahead.peek(kw::Send))) {
+        let name = input.parse()?;
+        Ok(Oibit { negative, name })
+    } else if negative.is_none() && lookahead.peek(kw::auto) {
+        let auto = input.parse::<kw::auto>()?;
+        let name = Ident::new("auto", auto.span);
+        Ok(Oibit { negative, name })
     } else {
         Err(lookahead.error())
     }
 }
 
-fn parse_oibits(input: ParseStream) -> parse::Result<Punctuated<Ident, Token![+]>> {
-    let mut out = Punctuated::new();
+fn parse_oibits(input: ParseStream) -> parse::Result<Punctuated<Oibit, Token![+]>> {
+    let mut out = Punctuated::<Oibit, Token![+]>::new();
 
     if input.parse::<Option<Token![:]>>()?.is_some() {
         loop {
@@ -96,6 +1046,24 @@ fn parse_oibits(input: ParseStream) -> parse::Result<Punctuated<Ident, Token![+]
         }
     }
 
+    // `auto` derives both traits from the `CType`, so they can't also be listed.
+    if out.iter().any(|o| o.is("auto")) {
+        if let Some(o) = out.iter().find(|o| o.is("Send") || o.is("Sync")) {
+            return Err(parse::Error::new_spanned(
+                &o.name,
+                "`auto` cannot be combined with `Send` or `Sync`",
+            ));
+        }
+    }
+    for o in out.iter().filter(|o| o.negative.is_some()) {
+        if out.iter().any(|p| p.is(&o.name.to_string())) {
+            return Err(parse::Error::new_spanned(
+                &o.name,
+                format!("`{}` cannot be both implemented and suppressed", o.name),
+            ));
+        }
+    }
+
     Ok(out)
 }
 
@@ -111,12 +1079,26 @@ where
     Ok(type_)
 }
 
-fn parse_phantom_data(input: ParseStream) -> parse::Result<Option<Type>> {
-    if input.peek(Token![type]) && input.peek2(kw::PhantomData) {
-        input.call(parse_type::<kw::PhantomData>).map(Some)
-    } else {
-        Ok(None)
-    }
+fn parse_const<T, L>(input: ParseStream) -> parse::Result<L>
+where
+    T: Parse,
+    L: Parse,
+{
+    input.parse::<Token![const]>()?;
+    input.parse::<T>()?;
+    input.parse::<Token![=]>()?;
+    let lit = input.parse()?;
+    input.parse::<Token![;]>()?;
+    Ok(lit)
+}
+
+fn parse_drop_queue(input: ParseStream) -> parse::Result<Expr> {
+    input.parse::<Token![static]>()?;
+    input.parse::<kw::DROP_QUEUE>()?;
+    input.parse::<Token![=]>()?;
+    let queue = input.parse()?;
+    input.parse::<Token![;]>()?;
+    Ok(queue)
 }
 
 fn parse_fn<T>(input: ParseStream) -> parse::Result<Expr>
@@ -131,10 +1113,80 @@ where
     Ok(path)
 }
 
-fn parse_clone(input: ParseStream) -> parse::Result<Option<Expr>> {
-    if input.peek(Token![fn]) && input.peek2(kw::clone) {
-        input.call(parse_fn::<kw::clone>).map(Some)
+/// Parses a function which may be `dyn`, returning `None` in that case.
+fn parse_fn_or_dyn<T>(input: ParseStream) -> parse::Result<Option<Expr>>
+where
+    T: Parse,
+{
+    input.parse::<Token![fn]>()?;
+    input.parse::<T>()?;
+    input.parse::<Token![=]>()?;
+    let path = if input.parse::<Option<Token![dyn]>>()?.is_some() {
+        None
     } else {
-        Ok(None)
+        Some(input.parse()?)
+    };
+    input.parse::<Token![;]>()?;
+    Ok(path)
+}
+
+/// Gives the parameter of a closure taking a pointer to the `CType` an explicit type if it has none,
+/// since the closure may not otherwise constrain it.
+fn annotate_closure(expr: Expr, ctype: &Type) -> Expr {
+    match expr {
+        Expr::Closure(mut closure) if closure.inputs.len() == 1 => {
+            let input = &mut closure.inputs[0];
+            if let Pat::Ident(_) | Pat::Wild(_) = input {
+                *input = Pat::Type(PatType {
+                    attrs: vec![],
+                    pat: Box::new(input.clone()),
+                    colon_token: Default::default(),
+                    ty: parse_quote!(*mut #ctype),
+                });
+            }
+            Expr::Closure(closure)
+        }
+        expr => expr,
     }
 }
+
+/// Parses a function with an explicit return type, as in `fn name: Type = path;`.
+fn parse_typed_fn<T>(input: ParseStream) -> parse::Result<(Type, Expr)>
+where
+    T: Parse,
+{
+    input.parse::<Token![fn]>()?;
+    input.parse::<T>()?;
+    input.parse::<Token![:]>()?;
+    let ty = input.parse()?;
+    input.parse::<Token![=]>()?;
+    let f = input.parse()?;
+    input.parse::<Token![;]>()?;
+    Ok((ty, f))
+}
+
+fn parse_insert(input: ParseStream) -> parse::Result<(bool, Expr)> {
+    input.parse::<Token![fn]>()?;
+    input.parse::<kw::insert>()?;
+    let takes = input.call(parse_ownership)?;
+    input.parse::<Token![=]>()?;
+    let insert = input.parse()?;
+    input.parse::<Token![;]>()?;
+    Ok((takes, insert))
+}
+
+fn parse_impl(input: ParseStream) -> parse::Result<Path> {
+    input.parse::<Token![impl]>()?;
+    let impl_: Path = input.parse()?;
+    let valid = impl_.is_ident("AsHandle")
+        || impl_.is_ident("Debug")
+        || (impl_.segments.len() > 1 && impl_.segments.last().unwrap().ident == "ForeignType");
+    if !valid {
+        return Err(parse::Error::new_spanned(
+            impl_,
+            "expected `AsHandle`, `Debug` or a path to another version's `ForeignType` trait",
+        ));
+    }
+    input.parse::<Token![;]>()?;
+    Ok(impl_)
+}
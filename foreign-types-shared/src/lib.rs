@@ -4,9 +4,16 @@
 #![warn(missing_docs)]
 #![doc(html_root_url = "https://docs.rs/foreign-types-shared/0.3")]
 
+use core::any;
 use core::cell::UnsafeCell;
+use core::ffi::c_void;
 use core::marker::{PhantomData, PhantomPinned};
 use core::mem;
+use core::panic::{RefUnwindSafe, UnwindSafe};
+
+pub use crate::shared::{RefCounted, Shared};
+
+mod shared;
 
 /// An opaque type used to define `ForeignTypeRef` types.
 ///
@@ -15,6 +22,24 @@ use core::mem;
 // TODO: Replace this with `extern type` to also mark it as `!Sized`.
 pub struct Opaque(UnsafeCell<PhantomData<(*mut UnsafeCell<()>, PhantomPinned)>>);
 
+// Fails to compile if `Opaque` is `UnwindSafe` or `RefUnwindSafe`, since the reference types rely on
+// it to implement neither unless they opt in.
+const _: fn() = || {
+    trait AmbiguousIfImpl<A> {
+        fn some_item() {}
+    }
+
+    impl<T: ?Sized> AmbiguousIfImpl<()> for T {}
+
+    struct IsUnwindSafe;
+    impl<T: ?Sized + UnwindSafe> AmbiguousIfImpl<IsUnwindSafe> for T {}
+
+    struct IsRefUnwindSafe;
+    impl<T: ?Sized + RefUnwindSafe> AmbiguousIfImpl<IsRefUnwindSafe> for T {}
+
+    let _ = <Opaque as AmbiguousIfImpl<_>>::some_item;
+};
+
 /// A type implemented by wrappers over foreign types.
 ///
 /// # Safety
@@ -33,6 +58,20 @@ pub unsafe trait ForeignType: Sized {
     /// The type representing a reference to this type.
     type Ref: ForeignTypeRef<CType = Self::CType>;
 
+    /// A function which frees an owned instance of the native type.
+    ///
+    /// Calling it with a pointer is equivalent to dropping the value returned by `from_ptr`. This
+    /// allows generic containers to destroy elements stored as raw pointers.
+    const DROP: unsafe fn(*mut Self::CType) = drop_ptr::<Self>;
+
+    /// A function which duplicates an instance of the native type, returning a new owned instance,
+    /// or `None` if this type cannot be cloned.
+    ///
+    /// Calling it with a pointer is equivalent to cloning the value it refers to and calling
+    /// `into_ptr` on the result.
+    #[allow(clippy::type_complexity)]
+    const CLONE: Option<unsafe fn(*mut Self::CType) -> *mut Self::CType> = None;
+
     /// Constructs an instance of this type from its raw type.
     ///
     /// # Safety
@@ -40,16 +79,108 @@ pub unsafe trait ForeignType: Sized {
     /// `ptr` must be a valid, owned instance of the native type.
     unsafe fn from_ptr(ptr: *mut Self::CType) -> Self;
 
+    /// Constructs an instance of this type from its raw type, returning `None` if `ptr` is null.
+    ///
+    /// # Safety
+    ///
+    /// If `ptr` is not null, it must be a valid, owned instance of the native type.
+    #[inline]
+    unsafe fn from_ptr_opt(ptr: *mut Self::CType) -> Option<Self> {
+        if ptr.is_null() {
+            None
+        } else {
+            Some(Self::from_ptr(ptr))
+        }
+    }
+
     /// Returns a raw pointer to the wrapped value.
     fn as_ptr(&self) -> *mut Self::CType;
 
     /// Consumes the wrapper and returns the raw pointer.
+    ///
+    /// The caller becomes responsible for freeing the value, so ignoring the pointer leaks it.
     #[inline]
+    #[must_use = "ignoring the pointer leaks the value"]
     fn into_ptr(self) -> *mut Self::CType {
         let ptr = self.as_ptr();
         mem::forget(self);
         ptr
     }
+
+    /// Returns the address of the wrapped value.
+    ///
+    /// The address is only meant for comparisons, hashing and diagnostics, and should not be
+    /// turned back into a pointer.
+    #[inline]
+    fn addr(&self) -> usize {
+        ptr_addr(self.as_ptr())
+    }
+
+    /// Converts this value into an owned instance of another foreign type wrapping the same pointer.
+    ///
+    /// The pointer is cast to `U::CType`, and ownership is transferred without calling any
+    /// destructor or constructor.
+    ///
+    /// # Safety
+    ///
+    /// The native value must be a valid, owned instance of `U::CType`, such as when both types
+    /// wrap the same C type, or when `U::CType` is a variant of `Self::CType` and the value is
+    /// known to be of that variant. Dropping the result must free the value correctly.
+    #[inline]
+    unsafe fn cast<U: ForeignType>(self) -> U {
+        U::from_ptr(self.into_ptr().cast())
+    }
+
+    /// Replaces this value with `new`, returning the previous value without dropping it.
+    #[inline]
+    #[must_use = "if the previous value is not needed, assign the new value directly"]
+    fn replace(&mut self, new: Self) -> Self {
+        mem::replace(self, new)
+    }
+
+    /// Replaces this value with an instance constructed from its raw type, returning the previous
+    /// value without dropping it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid, owned instance of the native type.
+    #[inline]
+    #[must_use = "if the previous value is not needed, assign the new value directly"]
+    unsafe fn replace_ptr(&mut self, ptr: *mut Self::CType) -> Self {
+        self.replace(Self::from_ptr(ptr))
+    }
+
+    /// Swaps this value with `other`, without dropping either.
+    #[inline]
+    fn swap(&mut self, other: &mut Self) {
+        mem::swap(self, other);
+    }
+}
+
+unsafe fn drop_ptr<T: ForeignType>(ptr: *mut T::CType) {
+    drop(T::from_ptr(ptr));
+}
+
+/// Returns the address of a pointer.
+///
+/// The address is only meant for comparisons, hashing and diagnostics. This is the only place where
+/// the crates convert a pointer to an integer. The conversion is an `as` cast, which exposes the
+/// pointer's provenance, since the strict provenance `pointer::addr` method is newer than the
+/// minimum supported Rust version.
+#[inline]
+pub fn ptr_addr<T>(ptr: *const T) -> usize {
+    ptr as usize
+}
+
+/// Clones the instance of the native type `ptr` as a `T`, for use as `ForeignType::CLONE`.
+///
+/// # Safety
+///
+/// `ptr` must be a valid instance of the native type which may be borrowed as a `T`.
+#[doc(hidden)]
+pub unsafe fn clone_ptr<T: ForeignType + Clone>(ptr: *mut T::CType) -> *mut T::CType {
+    let value = mem::ManuallyDrop::new(T::from_ptr(ptr));
+    T::clone(&value).into_ptr()
 }
 
 /// A trait implemented by types which reference borrowed foreign types.
@@ -72,7 +203,7 @@ pub unsafe trait ForeignTypeRef: Sized {
     #[inline]
     unsafe fn from_ptr<'a>(ptr: *mut Self::CType) -> &'a Self {
         debug_assert!(!ptr.is_null());
-        &*(ptr as *mut _)
+        &*ptr.cast::<Self>()
     }
 
     /// Constructs a mutable reference of this type from its raw type.
@@ -83,12 +214,205 @@ pub unsafe trait ForeignTypeRef: Sized {
     #[inline]
     unsafe fn from_ptr_mut<'a>(ptr: *mut Self::CType) -> &'a mut Self {
         debug_assert!(!ptr.is_null());
-        &mut *(ptr as *mut _)
+        &mut *ptr.cast::<Self>()
+    }
+
+    /// Constructs a shared instance of this type from its raw type, returning `None` if `ptr` is
+    /// null.
+    ///
+    /// # Safety
+    ///
+    /// If `ptr` is not null, it must be a valid, immutable, instance of the type for the `'a`
+    /// lifetime.
+    #[inline]
+    unsafe fn from_ptr_opt<'a>(ptr: *mut Self::CType) -> Option<&'a Self> {
+        if ptr.is_null() {
+            None
+        } else {
+            Some(Self::from_ptr(ptr))
+        }
+    }
+
+    /// Constructs a mutable reference of this type from its raw type, returning `None` if `ptr` is
+    /// null.
+    ///
+    /// # Safety
+    ///
+    /// If `ptr` is not null, it must be a valid, unique, instance of the type for the `'a`
+    /// lifetime.
+    #[inline]
+    unsafe fn from_ptr_mut_opt<'a>(ptr: *mut Self::CType) -> Option<&'a mut Self> {
+        if ptr.is_null() {
+            None
+        } else {
+            Some(Self::from_ptr_mut(ptr))
+        }
+    }
+
+    /// Constructs a shared instance of this type from its raw type, borrowed from `anchor`.
+    ///
+    /// This is equivalent to `from_ptr`, but ties the lifetime of the returned reference to the
+    /// value which owns the native value, such as the parent object an accessor was called on.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid, immutable, instance of the type for as long as `anchor` is borrowed.
+    #[inline]
+    unsafe fn from_ptr_of<P>(anchor: &P, ptr: *mut Self::CType) -> &Self
+    where
+        P: ?Sized,
+    {
+        let _ = anchor;
+        Self::from_ptr(ptr)
+    }
+
+    /// Constructs a mutable reference of this type from its raw type, borrowed from `anchor`.
+    ///
+    /// This is equivalent to `from_ptr_mut`, but ties the lifetime of the returned reference to the
+    /// value which owns the native value.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid, unique, instance of the type for as long as `anchor` is borrowed.
+    #[inline]
+    unsafe fn from_ptr_mut_of<P>(anchor: &mut P, ptr: *mut Self::CType) -> &mut Self
+    where
+        P: ?Sized,
+    {
+        let _ = anchor;
+        Self::from_ptr_mut(ptr)
+    }
+
+    /// Constructs a shared instance of this type from its raw type, borrowed from `anchor`, returning
+    /// `None` if `ptr` is null.
+    ///
+    /// # Safety
+    ///
+    /// If `ptr` is not null, it must be a valid, immutable, instance of the type for as long as
+    /// `anchor` is borrowed.
+    #[inline]
+    unsafe fn from_ptr_opt_of<P>(anchor: &P, ptr: *mut Self::CType) -> Option<&Self>
+    where
+        P: ?Sized,
+    {
+        let _ = anchor;
+        Self::from_ptr_opt(ptr)
     }
 
     /// Returns a raw pointer to the wrapped value.
     #[inline]
     fn as_ptr(&self) -> *mut Self::CType {
-        self as *const _ as *mut _
+        (self as *const Self as *mut Self).cast()
+    }
+
+    /// Returns a raw pointer to an optional value, or null if it is `None`.
+    ///
+    /// This is useful for passing optional arguments to C functions.
+    #[inline]
+    fn opt_as_ptr(this: Option<&Self>) -> *mut Self::CType {
+        match this {
+            Some(this) => this.as_ptr(),
+            None => core::ptr::null_mut(),
+        }
+    }
+
+    /// Returns the address of the wrapped value.
+    ///
+    /// The address is only meant for comparisons, hashing and diagnostics, and should not be
+    /// turned back into a pointer.
+    #[inline]
+    fn addr(&self) -> usize {
+        ptr_addr(self.as_ptr())
     }
+
+    /// Reinterprets this reference as a reference to another foreign type.
+    ///
+    /// # Safety
+    ///
+    /// The native value must be a valid instance of `U::CType`, such as when both types wrap the
+    /// same C type, or when `U::CType` is a variant of `Self::CType` and the value is known to be of
+    /// that variant.
+    #[inline]
+    unsafe fn cast_ref<U: ForeignTypeRef>(&self) -> &U {
+        U::from_ptr(self.as_ptr().cast())
+    }
+
+    /// Reinterprets this mutable reference as a mutable reference to another foreign type.
+    ///
+    /// # Safety
+    ///
+    /// The requirements of `cast_ref` apply.
+    #[inline]
+    unsafe fn cast_mut<U: ForeignTypeRef>(&mut self) -> &mut U {
+        U::from_ptr_mut(self.as_ptr().cast())
+    }
+}
+
+/// An object-safe view of an owned foreign value of any type.
+///
+/// This is implemented for all types implementing `ForeignType`, and allows values of different
+/// types to be stored together, such as in a `Vec<Box<dyn AnyForeignType>>`, and passed to C
+/// functions accepting untyped pointers.
+pub trait AnyForeignType {
+    /// Returns a raw, untyped pointer to the wrapped value.
+    fn as_any_ptr(&self) -> *mut c_void;
+
+    /// Returns the name of the Rust type wrapping the value.
+    ///
+    /// Like `core::any::type_name`, this is intended for diagnostics, and its exact contents are
+    /// unspecified.
+    fn type_name(&self) -> &'static str;
+}
+
+impl<T: ForeignType> AnyForeignType for T {
+    #[inline]
+    fn as_any_ptr(&self) -> *mut c_void {
+        self.as_ptr().cast()
+    }
+
+    #[inline]
+    fn type_name(&self) -> &'static str {
+        any::type_name::<T>()
+    }
+}
+
+/// A foreign type whose owned and reference types are both `Send`.
+///
+/// This is implemented automatically for all such types, such as those declared with `: Send` in
+/// `foreign_type!`. Unlike a `T: ForeignType + Send` bound, a `T: SendForeignType` bound also
+/// allows `T::Ref` to be sent between threads.
+pub trait SendForeignType: ForeignType<Ref = <Self as SendForeignType>::SendRef> + Send {
+    /// The reference type, which is the same as `ForeignType::Ref`.
+    type SendRef: ForeignTypeRef<CType = Self::CType> + Send;
+}
+
+impl<T> SendForeignType for T
+where
+    T: ForeignType + Send,
+    T::Ref: Send,
+{
+    type SendRef = T::Ref;
+}
+
+/// A foreign type whose owned and reference types are all `Send` and `Sync`.
+///
+/// This is implemented automatically for all such types, such as those declared with `: Sync + Send`
+/// in `foreign_type!`. Unlike a `T: ForeignType + Send + Sync` bound, a `T: SyncForeignType` bound
+/// also allows `T::Ref` to be shared between threads.
+// Requiring `Send` allows this trait to refine `SendForeignType`'s reference type rather than
+// `ForeignType::Ref` directly, since bounds constraining the same associated type twice cannot be
+// combined.
+pub trait SyncForeignType:
+    SendForeignType<SendRef = <Self as SyncForeignType>::SyncRef> + Sync
+{
+    /// The reference type, which is the same as `ForeignType::Ref`.
+    type SyncRef: ForeignTypeRef<CType = Self::CType> + Send + Sync;
+}
+
+impl<T> SyncForeignType for T
+where
+    T: SendForeignType + Sync,
+    T::Ref: Sync,
+{
+    type SyncRef = T::Ref;
 }
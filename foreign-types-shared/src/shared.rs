@@ -0,0 +1,168 @@
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::Deref;
+use core::ptr::NonNull;
+
+use crate::{ForeignType, ForeignTypeRef, SyncForeignType};
+
+/// A foreign type whose instances are reference counted by the C library.
+///
+/// An owned instance of the type owns a single reference.
+///
+/// # Safety
+///
+/// `retain` must increment the reference count of the instance, such that it stays valid until
+/// `release` has been called once more than `retain`. The instance must not be mutated while more
+/// than one reference to it exists.
+pub unsafe trait RefCounted: ForeignType {
+    /// Increments the reference count of `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid instance of the native type.
+    unsafe fn retain(ptr: *mut Self::CType);
+
+    /// Decrements the reference count of `ptr`, freeing it if it reaches zero.
+    ///
+    /// By default, this drops the value returned by `from_ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid instance of the native type, and the caller must own a reference to it.
+    #[inline]
+    unsafe fn release(ptr: *mut Self::CType) {
+        (Self::DROP)(ptr);
+    }
+
+    /// Returns `true` if `ptr` has a reference count of one.
+    ///
+    /// By default, this returns `false`, which is correct for libraries that do not expose the
+    /// reference count.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid instance of the native type.
+    #[inline]
+    unsafe fn is_unique(ptr: *mut Self::CType) -> bool {
+        let _ = ptr;
+        false
+    }
+}
+
+/// A shared reference to a reference counted foreign value.
+///
+/// This is the analogue of `Arc` for values whose reference count is managed by the C library:
+/// cloning a `Shared` retains the value, and dropping it releases it.
+pub struct Shared<T: RefCounted> {
+    ptr: NonNull<T::CType>,
+    _p: PhantomData<T>,
+}
+
+// Like `Arc`, sending a `Shared` to another thread shares the value with it.
+unsafe impl<T: RefCounted + SyncForeignType> Send for Shared<T> {}
+unsafe impl<T: RefCounted + SyncForeignType> Sync for Shared<T> {}
+
+impl<T: RefCounted> Shared<T> {
+    /// Converts a uniquely owned value into a shared one.
+    #[inline]
+    pub fn new(value: T) -> Shared<T> {
+        unsafe { Shared::from_ptr(value.into_ptr()) }
+    }
+
+    /// Constructs an instance of this type from its raw type, taking ownership of a reference.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid instance of the native type, and the caller must own a reference to
+    /// it.
+    #[inline]
+    pub unsafe fn from_ptr(ptr: *mut T::CType) -> Shared<T> {
+        debug_assert!(!ptr.is_null());
+        Shared {
+            ptr: NonNull::new_unchecked(ptr),
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns a raw pointer to the wrapped value.
+    #[inline]
+    pub fn as_ptr(this: &Shared<T>) -> *mut T::CType {
+        this.ptr.as_ptr()
+    }
+
+    /// Consumes the wrapper and returns the raw pointer, without releasing the reference.
+    #[inline]
+    pub fn into_ptr(this: Shared<T>) -> *mut T::CType {
+        let ptr = Shared::as_ptr(&this);
+        mem::forget(this);
+        ptr
+    }
+
+    /// Returns `true` if this is the only reference to the value.
+    #[inline]
+    pub fn is_unique(this: &Shared<T>) -> bool {
+        unsafe { T::is_unique(Shared::as_ptr(this)) }
+    }
+
+    /// Returns the value as a uniquely owned `T` if this is the only reference to it, or the
+    /// `Shared` otherwise.
+    #[inline]
+    pub fn try_unwrap(this: Shared<T>) -> Result<T, Shared<T>> {
+        if Shared::is_unique(&this) {
+            unsafe { Ok(T::from_ptr(Shared::into_ptr(this))) }
+        } else {
+            Err(this)
+        }
+    }
+}
+
+impl<T: RefCounted> Clone for Shared<T> {
+    #[inline]
+    fn clone(&self) -> Shared<T> {
+        unsafe {
+            T::retain(Shared::as_ptr(self));
+            Shared::from_ptr(Shared::as_ptr(self))
+        }
+    }
+}
+
+impl<T: RefCounted> Drop for Shared<T> {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { T::release(Shared::as_ptr(self)) }
+    }
+}
+
+impl<T: RefCounted> Deref for Shared<T> {
+    type Target = T::Ref;
+
+    #[inline]
+    fn deref(&self) -> &T::Ref {
+        unsafe { T::Ref::from_ptr(Shared::as_ptr(self)) }
+    }
+}
+
+impl<T: RefCounted> AsRef<T::Ref> for Shared<T> {
+    #[inline]
+    fn as_ref(&self) -> &T::Ref {
+        self
+    }
+}
+
+impl<T: RefCounted> From<T> for Shared<T> {
+    #[inline]
+    fn from(value: T) -> Shared<T> {
+        Shared::new(value)
+    }
+}
+
+impl<T> fmt::Debug for Shared<T>
+where
+    T: RefCounted,
+    T::Ref: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, fmt)
+    }
+}
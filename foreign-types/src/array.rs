@@ -0,0 +1,264 @@
+//! Arrays of pointers to foreign types.
+//!
+//! C APIs commonly return a list of objects as a null-terminated array of pointers, in the style of
+//! `argv`. `NullTerminated` wraps such an array, freeing the array storage and optionally the
+//! elements themselves when it is dropped.
+//!
+//! Arrays with an explicit length can instead be converted to and from a `Vec` of owned values with
+//! `vec_from_raw_parts` and `into_c_array` when the `std` Cargo feature is enabled.
+
+use core::fmt;
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use crate::{ForeignType, ForeignTypeRef};
+
+/// Takes ownership of an array of `len` pointers and of each of its elements.
+///
+/// The elements are moved into the returned `Vec`, after which `free_array` is called on the array.
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` pointers, and ownership of each of them must be
+/// transferable to an instance of `T`. It must be safe to call `free_array` with `ptr` once. If
+/// `len` is 0, `ptr` may be null, in which case `free_array` is not called.
+#[cfg(feature = "std")]
+pub unsafe fn vec_from_raw_parts<T: ForeignType>(
+    ptr: *mut *mut T::CType,
+    len: usize,
+    free_array: unsafe fn(*mut *mut T::CType),
+) -> Vec<T> {
+    if ptr.is_null() {
+        debug_assert_eq!(len, 0);
+        return Vec::new();
+    }
+
+    let mut out = Vec::with_capacity(len);
+    for i in 0..len {
+        out.push(T::from_ptr(*ptr.add(i)));
+    }
+    free_array(ptr);
+    out
+}
+
+/// Transfers ownership of each value to a newly allocated C array.
+///
+/// `alloc` is called with the number of values and must return storage for that many pointers, or
+/// null on failure, in which case the values are dropped and null is returned. The caller is
+/// responsible for freeing the array and its elements.
+///
+/// # Safety
+///
+/// A non-null pointer returned by `alloc` must be valid for writes of the requested number of
+/// pointers.
+#[cfg(feature = "std")]
+pub unsafe fn into_c_array<T: ForeignType>(
+    values: Vec<T>,
+    alloc: unsafe fn(usize) -> *mut *mut T::CType,
+) -> *mut *mut T::CType {
+    let ptr = alloc(values.len());
+    if ptr.is_null() {
+        return ptr;
+    }
+
+    for (i, value) in values.into_iter().enumerate() {
+        ptr.add(i).write(value.into_ptr());
+    }
+    ptr
+}
+
+/// An owned, null-terminated array of pointers to values of the foreign type `T`.
+pub struct NullTerminated<T: ForeignType> {
+    ptr: NonNull<*mut T::CType>,
+    len: usize,
+    free: unsafe fn(*mut *mut T::CType),
+    owns_elements: bool,
+    _p: PhantomData<T>,
+}
+
+impl<T: ForeignType> NullTerminated<T> {
+    /// Constructs an instance of this type from a null-terminated array whose elements are
+    /// borrowed.
+    ///
+    /// `free` is called on the array when the returned value is dropped. The elements themselves
+    /// are not freed.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid, non-null pointer to an array terminated by a null pointer. Each
+    /// element must be valid for the lifetime of the returned value, and it must be safe to call
+    /// `free` with `ptr` once.
+    #[inline]
+    pub unsafe fn from_ptr(
+        ptr: *mut *mut T::CType,
+        free: unsafe fn(*mut *mut T::CType),
+    ) -> NullTerminated<T> {
+        NullTerminated::new(ptr, free, false)
+    }
+
+    /// Constructs an instance of this type from a null-terminated array which owns its elements.
+    ///
+    /// Each element is dropped as an instance of `T` before `free` is called on the array when the
+    /// returned value is dropped.
+    ///
+    /// # Safety
+    ///
+    /// In addition to the requirements of `from_ptr`, ownership of each element must be
+    /// transferable to an instance of `T`.
+    #[inline]
+    pub unsafe fn from_ptr_owned(
+        ptr: *mut *mut T::CType,
+        free: unsafe fn(*mut *mut T::CType),
+    ) -> NullTerminated<T> {
+        NullTerminated::new(ptr, free, true)
+    }
+
+    unsafe fn new(
+        ptr: *mut *mut T::CType,
+        free: unsafe fn(*mut *mut T::CType),
+        owns_elements: bool,
+    ) -> NullTerminated<T> {
+        debug_assert!(!ptr.is_null());
+        let mut len = 0;
+        while !(*ptr.add(len)).is_null() {
+            len += 1;
+        }
+
+        NullTerminated {
+            ptr: NonNull::new_unchecked(ptr),
+            len,
+            free,
+            owns_elements,
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns the number of elements in the array, not including the null terminator.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the array has no elements.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the element at `idx`, or `None` if it is out of bounds.
+    #[inline]
+    pub fn get(&self, idx: usize) -> Option<&T::Ref> {
+        if idx < self.len {
+            unsafe { Some(T::Ref::from_ptr(*self.ptr.as_ptr().add(idx))) }
+        } else {
+            None
+        }
+    }
+
+    /// Returns an iterator over references to the elements of the array.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            ptr: self.ptr.as_ptr(),
+            end: unsafe { self.ptr.as_ptr().add(self.len) },
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns a raw pointer to the array.
+    #[inline]
+    pub fn as_ptr(&self) -> *mut *mut T::CType {
+        self.ptr.as_ptr()
+    }
+}
+
+impl<T: ForeignType> Drop for NullTerminated<T> {
+    fn drop(&mut self) {
+        unsafe {
+            if self.owns_elements {
+                for i in 0..self.len {
+                    drop(T::from_ptr(*self.ptr.as_ptr().add(i)));
+                }
+            }
+            (self.free)(self.ptr.as_ptr());
+        }
+    }
+}
+
+impl<'a, T: ForeignType> IntoIterator for &'a NullTerminated<T> {
+    type Item = &'a T::Ref;
+    type IntoIter = Iter<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> fmt::Debug for NullTerminated<T>
+where
+    T: ForeignType,
+    T::Ref: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_list().entries(self).finish()
+    }
+}
+
+/// An iterator over references to the elements of a `NullTerminated` array.
+pub struct Iter<'a, T: ForeignType> {
+    ptr: *mut *mut T::CType,
+    end: *mut *mut T::CType,
+    _p: PhantomData<&'a NullTerminated<T>>,
+}
+
+impl<'a, T: ForeignType> Iterator for Iter<'a, T> {
+    type Item = &'a T::Ref;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a T::Ref> {
+        if self.ptr == self.end {
+            None
+        } else {
+            unsafe {
+                let item = T::Ref::from_ptr(*self.ptr);
+                self.ptr = self.ptr.add(1);
+                Some(item)
+            }
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl<'a, T: ForeignType> DoubleEndedIterator for Iter<'a, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<&'a T::Ref> {
+        if self.ptr == self.end {
+            None
+        } else {
+            unsafe {
+                self.end = self.end.sub(1);
+                Some(T::Ref::from_ptr(*self.end))
+            }
+        }
+    }
+}
+
+impl<'a, T: ForeignType> ExactSizeIterator for Iter<'a, T> {
+    #[inline]
+    fn len(&self) -> usize {
+        // Both pointers are into the same array, and `end` is never before `ptr`.
+        unsafe { self.end.offset_from(self.ptr) as usize }
+    }
+}
+
+impl<'a, T: ForeignType> FusedIterator for Iter<'a, T> {}
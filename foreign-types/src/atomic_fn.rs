@@ -0,0 +1,93 @@
+//! Atomic storage for function pointers.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// A function pointer type which can be stored in an `AtomicFn`.
+///
+/// # Safety
+///
+/// `from_raw` must return the function pointer which `into_raw` converted to the given pointer.
+pub(crate) unsafe trait FnPtr: Copy {
+    fn into_raw(self) -> *mut ();
+
+    /// # Safety
+    ///
+    /// `raw` must have been returned by `into_raw` on a value of the same type.
+    unsafe fn from_raw(raw: *mut ()) -> Self;
+}
+
+unsafe impl<T> FnPtr for unsafe extern "C" fn(*mut T) {
+    fn into_raw(self) -> *mut () {
+        self as *mut ()
+    }
+
+    unsafe fn from_raw(raw: *mut ()) -> Self {
+        mem::transmute::<*mut (), Self>(raw)
+    }
+}
+
+unsafe impl<T> FnPtr for unsafe extern "C" fn(*mut T) -> *mut T {
+    fn into_raw(self) -> *mut () {
+        self as *mut ()
+    }
+
+    unsafe fn from_raw(raw: *mut ()) -> Self {
+        mem::transmute::<*mut (), Self>(raw)
+    }
+}
+
+#[cfg(feature = "trace")]
+unsafe impl FnPtr for fn(&crate::trace::Event) {
+    fn into_raw(self) -> *mut () {
+        self as *mut ()
+    }
+
+    unsafe fn from_raw(raw: *mut ()) -> Self {
+        mem::transmute::<*mut (), Self>(raw)
+    }
+}
+
+/// An optional function pointer of type `F` which can be set and read atomically.
+///
+/// Only values of type `F` are ever stored, so loading one back never reinterprets a pointer as a
+/// function of a different type.
+pub(crate) struct AtomicFn<F> {
+    raw: AtomicPtr<()>,
+    _p: PhantomData<F>,
+}
+
+impl<F> AtomicFn<F> {
+    pub(crate) const fn new() -> AtomicFn<F> {
+        AtomicFn {
+            raw: AtomicPtr::new(ptr::null_mut()),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<F: FnPtr> AtomicFn<F> {
+    pub(crate) fn store(&self, f: Option<F>) {
+        let raw = f.map_or(ptr::null_mut(), F::into_raw);
+        self.raw.store(raw, Ordering::Release);
+    }
+
+    pub(crate) fn load(&self) -> Option<F> {
+        let raw = self.raw.load(Ordering::Acquire);
+        if raw.is_null() {
+            None
+        } else {
+            // Only `store` writes to `raw`, with a pointer returned by `F::into_raw`.
+            Some(unsafe { F::from_raw(raw) })
+        }
+    }
+}
+
+impl<F> fmt::Debug for AtomicFn<F> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.raw.load(Ordering::Relaxed).fmt(fmt)
+    }
+}
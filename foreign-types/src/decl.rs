@@ -0,0 +1,230 @@
+/// A declarative version of `foreign_type!` which does not depend on procedural macros.
+///
+/// This supports the common subset of `foreign_type!`: a `CType`, a `drop` function, an optional
+/// `clone` function, and optional `Sync` and `Send` implementations. Generic parameters,
+/// `PhantomData` and the other extensions are not supported. Since declarative macros cannot
+/// construct identifiers, the name of the reference type must be given explicitly with
+/// `type Ref`.
+///
+/// Unlike `foreign_type!`, this macro is available when the `macros` Cargo feature is disabled,
+/// which avoids building `syn` and the rest of the procedural macro stack.
+///
+/// # Examples
+///
+/// ```
+/// use foreign_types::foreign_type_decl;
+///
+/// # mod openssl_sys { pub type SSL = (); pub unsafe fn SSL_free(_: *mut SSL) {} pub unsafe fn SSL_dup(x: *mut SSL) -> *mut SSL {x} }
+/// # mod foo_sys { pub type THING = (); pub unsafe fn THING_free(_: *mut THING) {} }
+/// foreign_type_decl! {
+///     /// Documentation for the owned type.
+///     pub unsafe type Ssl: Sync + Send {
+///         type CType = openssl_sys::SSL;
+///         type Ref = SslRef;
+///         fn drop = openssl_sys::SSL_free;
+///         fn clone = openssl_sys::SSL_dup;
+///     }
+///
+///     pub unsafe type Thing {
+///         type CType = foo_sys::THING;
+///         type Ref = ThingRef;
+///         fn drop = foo_sys::THING_free;
+///     }
+/// }
+///
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! foreign_type_decl {
+    ($(
+        $(#[$attr:meta])*
+        $vis:vis unsafe type $name:ident $(: $($oibit:ident $(+)?)+)? {
+            type CType = $ctype:ty;
+            type Ref = $ref_name:ident;
+            fn drop = $drop:expr;
+            $(fn clone = $clone:expr;)?
+        }
+    )*) => {
+        $(
+            $(#[$attr])*
+            #[repr(transparent)]
+            $vis struct $name($crate::export::NonNull<$ctype>);
+
+            #[doc = $crate::export::concat!(
+                "A borrowed reference to a [`",
+                $crate::export::stringify!($name),
+                "`](struct.",
+                $crate::export::stringify!($name),
+                ".html).",
+            )]
+            $vis struct $ref_name($crate::Opaque);
+
+            const _: () = assert!(
+                !$crate::export::needs_drop::<$ctype>(),
+                $crate::export::concat!(
+                    "the `CType` of `",
+                    $crate::export::stringify!($name),
+                    "` must not need to be dropped",
+                ),
+            );
+
+            $($(
+                unsafe impl $crate::export::$oibit for $name {}
+                unsafe impl $crate::export::$oibit for $ref_name {}
+            )+)?
+
+            unsafe impl $crate::ForeignType for $name {
+                type CType = $ctype;
+                type Ref = $ref_name;
+
+                #[inline]
+                unsafe fn from_ptr(ptr: *mut $ctype) -> $name {
+                    debug_assert!(!ptr.is_null());
+                    $name(<$crate::export::NonNull<_>>::new_unchecked(ptr))
+                }
+
+                #[inline]
+                fn as_ptr(&self) -> *mut $ctype {
+                    <$crate::export::NonNull<_>>::as_ptr(self.0)
+                }
+            }
+
+            unsafe impl $crate::ForeignTypeRef for $ref_name {
+                type CType = $ctype;
+            }
+
+            impl $name {
+                /// Constructs an instance of this type from its raw type in a `const` context.
+                ///
+                /// This is equivalent to `ForeignType::from_ptr`.
+                ///
+                /// # Safety
+                ///
+                /// `ptr` must be a valid, owned instance of the native type.
+                #[inline]
+                $vis const unsafe fn from_ptr_const(ptr: *mut $ctype) -> $name {
+                    $name($crate::export::NonNull::new_unchecked(ptr))
+                }
+
+                /// Returns a raw pointer to the wrapped value in a `const` context.
+                ///
+                /// This is equivalent to `ForeignType::as_ptr`.
+                #[inline]
+                $vis const fn as_ptr_const(&self) -> *mut $ctype {
+                    self.0.as_ptr()
+                }
+            }
+
+            impl $ref_name {
+                /// Constructs a shared instance of this type from its raw type in a `const` context.
+                ///
+                /// This is equivalent to `ForeignTypeRef::from_ptr`.
+                ///
+                /// # Safety
+                ///
+                /// `ptr` must be a valid, immutable, instance of the type for the returned lifetime.
+                #[inline]
+                $vis const unsafe fn from_ptr_const<'a>(ptr: *mut $ctype) -> &'a $ref_name {
+                    &*ptr.cast::<$ref_name>()
+                }
+            }
+
+            impl $crate::export::Drop for $name {
+                #[inline]
+                fn drop(&mut self) {
+                    unsafe {
+                        ($drop)($crate::ForeignType::as_ptr(self));
+                    }
+                }
+            }
+
+            impl $crate::export::Deref for $name {
+                type Target = $ref_name;
+
+                #[inline]
+                fn deref(&self) -> &$ref_name {
+                    unsafe {
+                        $crate::ForeignTypeRef::from_ptr($crate::ForeignType::as_ptr(self))
+                    }
+                }
+            }
+
+            impl $crate::export::DerefMut for $name {
+                #[inline]
+                fn deref_mut(&mut self) -> &mut $ref_name {
+                    unsafe {
+                        $crate::ForeignTypeRef::from_ptr_mut($crate::ForeignType::as_ptr(self))
+                    }
+                }
+            }
+
+            impl $crate::export::Borrow<$ref_name> for $name {
+                #[inline]
+                fn borrow(&self) -> &$ref_name {
+                    &**self
+                }
+            }
+
+            impl $crate::export::BorrowMut<$ref_name> for $name {
+                #[inline]
+                fn borrow_mut(&mut self) -> &mut $ref_name {
+                    &mut **self
+                }
+            }
+
+            impl $crate::export::AsRef<$ref_name> for $name {
+                #[inline]
+                fn as_ref(&self) -> &$ref_name {
+                    &**self
+                }
+            }
+
+            impl $crate::export::AsMut<$ref_name> for $name {
+                #[inline]
+                fn as_mut(&mut self) -> &mut $ref_name {
+                    &mut **self
+                }
+            }
+
+            $(
+                impl $crate::export::Clone for $name {
+                    #[inline]
+                    fn clone(&self) -> $name {
+                        unsafe {
+                            let ptr = ($clone)($crate::ForeignType::as_ptr(self));
+                            $crate::ForeignType::from_ptr(ptr)
+                        }
+                    }
+                }
+
+                $crate::__foreign_type_decl_to_owned!($name $ref_name $clone);
+            )?
+        )*
+    };
+}
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __foreign_type_decl_to_owned {
+    ($name:ident $ref_name:ident $clone:expr) => {
+        impl $crate::export::ToOwned for $ref_name {
+            type Owned = $name;
+
+            #[inline]
+            fn to_owned(&self) -> $name {
+                unsafe {
+                    let ptr = ($clone)($crate::ForeignTypeRef::as_ptr(self));
+                    $crate::ForeignType::from_ptr(ptr)
+                }
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "std"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __foreign_type_decl_to_owned {
+    ($name:ident $ref_name:ident $clone:expr) => {};
+}
@@ -0,0 +1,117 @@
+//! Storage for functions resolved at runtime.
+//!
+//! When a C library is loaded dynamically, the addresses of its destructor and copy functions are
+//! not known at compile time. Types declared with `fn drop = dyn;` or `fn clone = dyn;` store these
+//! functions in a `DropFn` or `CloneFn` which is filled in with `set_drop_fn` or `set_clone_fn` once
+//! the library has been loaded. Dropping or cloning an instance before the corresponding function
+//! has been set panics.
+
+use core::fmt;
+
+use crate::atomic_fn::AtomicFn;
+
+/// A destructor for values of type `T` which is set at runtime.
+pub struct DropFn<T> {
+    f: AtomicFn<unsafe extern "C" fn(*mut T)>,
+}
+
+impl<T> DropFn<T> {
+    /// Creates a new `DropFn` which has not been set.
+    pub const fn new() -> DropFn<T> {
+        DropFn { f: AtomicFn::new() }
+    }
+
+    /// Sets the destructor.
+    ///
+    /// # Safety
+    ///
+    /// `f` must free the values of type `T` it is passed by `call`, and must remain callable for
+    /// as long as such values may be dropped.
+    pub unsafe fn set(&self, f: unsafe extern "C" fn(*mut T)) {
+        self.f.store(Some(f));
+    }
+
+    /// Returns the destructor, if it has been set.
+    pub fn get(&self) -> Option<unsafe extern "C" fn(*mut T)> {
+        self.f.load()
+    }
+
+    /// Calls the destructor.
+    ///
+    /// # Safety
+    ///
+    /// The safety requirements of the destructor must be upheld.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the destructor has not been set.
+    pub unsafe fn call(&self, ptr: *mut T) {
+        let f = self.get().expect("dynamic drop function has not been set");
+        f(ptr)
+    }
+}
+
+impl<T> Default for DropFn<T> {
+    fn default() -> DropFn<T> {
+        DropFn::new()
+    }
+}
+
+impl<T> fmt::Debug for DropFn<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("DropFn").field(&self.f).finish()
+    }
+}
+
+/// A copy function for values of type `T` which is set at runtime.
+pub struct CloneFn<T> {
+    f: AtomicFn<unsafe extern "C" fn(*mut T) -> *mut T>,
+}
+
+impl<T> CloneFn<T> {
+    /// Creates a new `CloneFn` which has not been set.
+    pub const fn new() -> CloneFn<T> {
+        CloneFn { f: AtomicFn::new() }
+    }
+
+    /// Sets the copy function.
+    ///
+    /// # Safety
+    ///
+    /// `f` must return a new, owned copy of the value of type `T` it is passed by `call`, and must
+    /// remain callable for as long as such values may be cloned.
+    pub unsafe fn set(&self, f: unsafe extern "C" fn(*mut T) -> *mut T) {
+        self.f.store(Some(f));
+    }
+
+    /// Returns the copy function, if it has been set.
+    pub fn get(&self) -> Option<unsafe extern "C" fn(*mut T) -> *mut T> {
+        self.f.load()
+    }
+
+    /// Calls the copy function.
+    ///
+    /// # Safety
+    ///
+    /// The safety requirements of the copy function must be upheld.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the copy function has not been set.
+    pub unsafe fn call(&self, ptr: *mut T) -> *mut T {
+        let f = self.get().expect("dynamic clone function has not been set");
+        f(ptr)
+    }
+}
+
+impl<T> Default for CloneFn<T> {
+    fn default() -> CloneFn<T> {
+        CloneFn::new()
+    }
+}
+
+impl<T> fmt::Debug for CloneFn<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("CloneFn").field(&self.f).finish()
+    }
+}
@@ -0,0 +1,131 @@
+//! Guards bracketing edits of C objects.
+//!
+//! Some C APIs require mutations of an object to be bracketed by calls beginning and ending an
+//! edit, such as Core Foundation's `CFAttributedStringBeginEditing` and
+//! `CFAttributedStringEndEditing`, or by a pair of functions committing or aborting a transaction.
+//! Types declaring these functions with `fn begin_edit`, `fn commit_edit` and optionally
+//! `fn rollback_edit` get an `edit` method on the reference type, which begins an edit and returns
+//! an `EditGuard` dereferencing to the value:
+//!
+//! ```
+//! use foreign_types::{foreign_type, ForeignTypeRef};
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!         pub fn FOO_begin(foo: *mut FOO);
+//!         pub fn FOO_commit(foo: *mut FOO);
+//!         pub fn FOO_abort(foo: *mut FOO);
+//!         pub fn FOO_set_value(foo: *mut FOO, value: i32);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!         fn begin_edit = foo_sys::FOO_begin;
+//!         fn commit_edit = foo_sys::FOO_commit;
+//!         fn rollback_edit = foo_sys::FOO_abort;
+//!     }
+//! }
+//!
+//! impl FooRef {
+//!     pub fn set_value(&mut self, value: i32) {
+//!         unsafe { foo_sys::FOO_set_value(self.as_ptr(), value) }
+//!     }
+//! }
+//!
+//! fn reset(foo: &mut FooRef) {
+//!     let mut edit = foo.edit();
+//!     edit.set_value(0);
+//!     edit.finish();
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! The edit is committed by `EditGuard::finish`. If the guard is dropped instead, for example
+//! because an error was returned while editing, the edit is rolled back, or committed if the type
+//! has no `fn rollback_edit`. The macro also declares an alias of the guard for the type, named
+//! like `FooEditGuard<'a>`.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+use crate::ForeignTypeRef;
+
+/// An edit of a value of the foreign type `T`, which is committed or rolled back when finished.
+pub struct EditGuard<'a, T: ForeignTypeRef> {
+    value: &'a mut T,
+    commit: unsafe fn(*mut T::CType),
+    rollback: Option<unsafe fn(*mut T::CType)>,
+}
+
+impl<'a, T: ForeignTypeRef> EditGuard<'a, T> {
+    /// Creates a guard for an edit of `value` which has already begun.
+    ///
+    /// If `rollback` is `None`, the edit is committed when the guard is dropped.
+    ///
+    /// # Safety
+    ///
+    /// An edit of `value` must have begun, and `commit` and `rollback` must end it.
+    #[inline]
+    pub unsafe fn new(
+        value: &'a mut T,
+        commit: unsafe fn(*mut T::CType),
+        rollback: Option<unsafe fn(*mut T::CType)>,
+    ) -> EditGuard<'a, T> {
+        EditGuard {
+            value,
+            commit,
+            rollback,
+        }
+    }
+
+    /// Commits the edit.
+    #[inline]
+    pub fn finish(self) {
+        let ptr = self.value.as_ptr();
+        let commit = self.commit;
+        core::mem::forget(self);
+        unsafe { commit(ptr) }
+    }
+}
+
+impl<'a, T: ForeignTypeRef> Deref for EditGuard<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T: ForeignTypeRef> DerefMut for EditGuard<'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<'a, T: ForeignTypeRef> Drop for EditGuard<'a, T> {
+    #[inline]
+    fn drop(&mut self) {
+        let end = self.rollback.unwrap_or(self.commit);
+        unsafe { end(self.value.as_ptr()) }
+    }
+}
+
+impl<'a, T> fmt::Debug for EditGuard<'a, T>
+where
+    T: ForeignTypeRef + fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("EditGuard")
+            .field("value", &self.value)
+            .finish()
+    }
+}
@@ -0,0 +1,72 @@
+//! Construction of foreign values through out-parameters.
+//!
+//! Many C constructors have the shape `int FOO_create(FOO **out, ...)`, returning a status code and
+//! writing the new object to `out` on success. `try_init` handles the out-parameter and the status
+//! check for such functions:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//! use foreign_types::init::try_init;
+//! use std::os::raw::c_int;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_create(out: *mut *mut FOO, flags: std::os::raw::c_int) -> std::os::raw::c_int;
+//!         pub fn FOO_free(foo: *mut FOO);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//! }
+//!
+//! impl Foo {
+//!     pub fn new(flags: c_int) -> Result<Foo, c_int> {
+//!         unsafe { try_init(|out| foo_sys::FOO_create(out, flags)) }
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+
+use std::os::raw::c_int;
+use std::ptr;
+
+use crate::ForeignType;
+
+/// Constructs a value by calling `f` with a pointer to an out-parameter.
+///
+/// `f` is expected to return 0 on success, in which case the pointer it wrote to the out-parameter
+/// is wrapped in a `T`. Any other status is returned as an error, and the out-parameter is ignored.
+///
+/// # Safety
+///
+/// If `f` returns 0, it must have written a valid, owned instance of the native type to the
+/// out-parameter.
+///
+/// # Panics
+///
+/// Panics if `f` returns 0 but leaves the out-parameter null.
+pub unsafe fn try_init<T, F>(f: F) -> Result<T, c_int>
+where
+    T: ForeignType,
+    F: FnOnce(*mut *mut T::CType) -> c_int,
+{
+    let mut out = ptr::null_mut();
+    match f(&mut out) {
+        0 => {
+            assert!(
+                !out.is_null(),
+                "initializer reported success but did not set its out-parameter",
+            );
+            Ok(T::from_ptr(out))
+        }
+        status => Err(status),
+    }
+}
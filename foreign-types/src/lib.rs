@@ -131,6 +131,1100 @@
 //! If `fn clone` is specified, then it must take `CType` as an argument and return a copy of it as `CType`.
 //! It will be used to implement `Clone`, and if the `std` Cargo feature is enabled, `ToOwned`.
 //!
+//! The items in the body of a type can be given in any order. Each may only be given once, except
+//! for `fn get` and `fn set`, which may be repeated, and `impl`, which may be repeated with different
+//! traits. The same applies to the bodies of `unsafe type ref` and `unsafe type map` below.
+//!
+//! ```compile_fail
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     pub unsafe type Foo {
+//!         impl Debug;
+//!         fn drop = foo_sys::FOO_free;
+//!         type CType = foo_sys::FOO;
+//!         // error: duplicate `impl Debug`
+//!         impl Debug;
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! The functions can be any expression which can be called with a pointer to the `CType`, so
+//! destructors with a different signature can be adapted with a closure instead of a separate shim
+//! function. The closure is inlined into the generated code, and its parameter is given the type
+//! `*mut CType` if it has none:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     pub const FOO_FREE_DEEP: u32 = 1;
+//!
+//!     extern {
+//!         pub fn FOO_free_ex(foo: *mut FOO, flags: u32);
+//!         pub fn FOO_copy(dst: *mut *mut FOO, src: *const FOO) -> i32;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = |p| unsafe { foo_sys::FOO_free_ex(p, foo_sys::FOO_FREE_DEEP) };
+//!         fn clone = |p| unsafe {
+//!             let mut copy = std::ptr::null_mut();
+//!             assert_eq!(foo_sys::FOO_copy(&mut copy, p), 0, "failed to copy a Foo");
+//!             copy
+//!         };
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! Instead of asserting `Sync + Send` independently of the C type, they can be inferred from it
+//! with `auto`. The owned and reference types are then `Send` if the `CType` is `Send`, and `Sync`
+//! if it is `Sync`, so that they follow the declarations of the sys crate:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     // `Send` and `Sync`, as an uninhabited enum.
+//!     pub enum FOO {}
+//!
+//!     // Neither `Send` nor `Sync`, due to the raw pointer.
+//!     pub struct BAR {
+//!         pub foo: *mut FOO,
+//!     }
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!         pub fn BAR_free(bar: *mut BAR);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo: auto {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//!
+//!     /// A Bar.
+//!     pub unsafe type Bar: auto {
+//!         type CType = foo_sys::BAR;
+//!         fn drop = foo_sys::BAR_free;
+//!     }
+//! }
+//!
+//! fn assert_send<T: Send>() {}
+//!
+//! fn main() {
+//!     assert_send::<Foo>();
+//!     // error: `*mut FOO` cannot be sent between threads safely
+//!     // assert_send::<Bar>();
+//! }
+//! ```
+//!
+//! The owned type is `UnwindSafe` and `RefUnwindSafe` if the `CType` is `RefUnwindSafe`, while the
+//! reference type is neither `UnwindSafe` nor `RefUnwindSafe`, since it wraps an `Opaque` handle to
+//! memory which C may mutate. Either trait can
+//! be implemented for both types by listing it in the same way, or suppressed by listing it with
+//! `!`, for example for types wrapping objects with interior mutability:
+//!
+//! ```compile_fail
+//! use foreign_types::foreign_type;
+//! use std::panic::UnwindSafe;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo: Sync + Send + !UnwindSafe + !RefUnwindSafe {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//! }
+//!
+//! fn assert_unwind_safe<T: UnwindSafe>() {}
+//!
+//! fn main() {
+//!     // error: the type `&'static mut ()` may not be safely transferred across an unwind boundary
+//!     assert_unwind_safe::<Foo>();
+//! }
+//! ```
+//!
+//! The owned type is marked `#[must_use]`, since dropping a newly created value right away is almost
+//! always a mistake. A custom `#[must_use = "..."]` attribute on the type takes precedence, and the
+//! attribute can be left out entirely with `const MUST_USE = false;`:
+//!
+//! ```compile_fail
+//! #![deny(unused_must_use)]
+//!
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_new() -> *mut FOO;
+//!         pub fn FOO_free(foo: *mut FOO);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//! }
+//!
+//! fn main() {
+//!     use foreign_types::ForeignType;
+//!
+//!     // error: unused `Foo` that must be used
+//!     unsafe { Foo::from_ptr(foo_sys::FOO_new()) };
+//! }
+//! ```
+//!
+//! `CType` is the type pointed to, not the pointer itself, and must not have drop glue since it is
+//! owned by the C library. The macro rejects pointer and reference types, and types without generic
+//! parameters additionally check at compile time that `CType` does not need to be dropped:
+//!
+//! ```compile_fail
+//! use foreign_types::foreign_type;
+//!
+//! foreign_type! {
+//!     pub unsafe type Foo {
+//!         type CType = String;
+//!         fn drop = |_| {};
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! Some headers declare a distinct type for const pointers, such as `FOO_const_t`. It can be given
+//! with `type CTypeConst`, which generates `from_const_ptr` and
+//! `as_const_ptr` methods on the reference type converting to and from `*const CTypeConst`:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!     pub enum FOO_const {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!         pub fn FOO_len(foo: *const FOO_const) -> usize;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         type CTypeConst = foo_sys::FOO_const;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//! }
+//!
+//! impl FooRef {
+//!     pub fn len(&self) -> usize {
+//!         unsafe { foo_sys::FOO_len(self.as_const_ptr()) }
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! The trait methods cannot be called in `const` contexts, so the generated types additionally provide
+//! `const` inherent `from_ptr_const` and `as_ptr_const` methods. These can be used to build wrappers
+//! around pointers which are known at compile time and store them in `static`s. Values constructed
+//! this way are not seen by the `track-ownership`, `testing` and `trace` Cargo features, which
+//! also skip them when they are dropped.
+//!
+//! Reference counted types can use `fn ref` and `fn unref` in place of `fn clone` and `fn drop`.
+//! They behave identically, except that `RefCounted` is also implemented for the owned type so that
+//! it can be converted into a `Shared` pointer. If the `glib` Cargo feature is enabled, the GLib
+//! translation traits `FromGlibPtrFull`, `FromGlibPtrNone`, `ToGlibPtr` and `GlibPtrDefault` are
+//! also implemented for the owned type so that it can be passed to and from gtk-rs APIs:
+//!
+//! ```ignore
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_ref(foo: *mut FOO) -> *mut FOO;
+//!         pub fn FOO_unref(foo: *mut FOO);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A reference counted Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn ref = foo_sys::FOO_ref;
+//!         fn unref = foo_sys::FOO_unref;
+//!     }
+//! }
+//! ```
+//!
+//! Some C APIs use a single C type for both an immutable and a mutable flavor of a value, with the
+//! mutable flavor supporting a superset of the operations (for example `CFString` and
+//! `CFMutableString`). The mutable type can name its immutable counterpart with `type Immutable`,
+//! which makes its reference type dereference to the immutable reference type and allows the owned
+//! value to be converted into the immutable owned type for free:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// An immutable Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//!
+//!     /// A mutable Foo.
+//!     pub unsafe type MutableFoo {
+//!         type CType = foo_sys::FOO;
+//!         type Immutable = Foo;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//! }
+//!
+//! fn freeze(foo: MutableFoo) -> Foo {
+//!     foo.into()
+//! }
+//!
+//! fn inspect(foo: &MutableFooRef) -> &FooRef {
+//!     foo
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! CoreFoundation types which are toll-free bridged to an Objective-C class can name the class with
+//! `type TollFreeBridged`. This generates conversions between the owned type and `objc2`'s
+//! `Retained` pointer, which transfer the +1 retain count in both directions without retaining or
+//! releasing, and between references to the two types. This requires the `objc2` Cargo feature,
+//! and the conversions are only generated on Apple targets:
+//!
+//! ```ignore
+//! foreign_type! {
+//!     /// A CoreFoundation string.
+//!     pub unsafe type CFString: Sync + Send {
+//!         type CType = core_foundation_sys::string::__CFString;
+//!         type TollFreeBridged = objc2_foundation::NSString;
+//!         fn drop = |p| core_foundation_sys::base::CFRelease(p as _);
+//!         fn clone = |p| core_foundation_sys::base::CFRetain(p as _) as _;
+//!     }
+//! }
+//! ```
+//!
+//! Similarly, types wrapping a CoreFoundation type can name the corresponding `core-foundation`
+//! wrapper with `type CoreFoundation`. Owned values are converted in both directions following the
+//! create rule, transferring ownership, while converting a reference into the `core-foundation`
+//! wrapper follows the get rule and retains the value. This requires the `core-foundation` Cargo
+//! feature, and the conversions are only generated on Apple targets:
+//!
+//! ```ignore
+//! foreign_type! {
+//!     /// A CoreFoundation string.
+//!     pub unsafe type CFString: Sync + Send {
+//!         type CType = core_foundation_sys::string::__CFString;
+//!         type CoreFoundation = core_foundation::string::CFString;
+//!         fn drop = |p| core_foundation_sys::base::CFRelease(p as _);
+//!         fn clone = |p| core_foundation_sys::base::CFRetain(p as _) as _;
+//!     }
+//! }
+//! ```
+//!
+//! Owned values can be passed through Python extension modules in a `PyCapsule` by naming the
+//! capsule with `const PY_CAPSULE_NAME`. This generates an `into_py_capsule` method which moves the
+//! raw pointer into a capsule with that name, whose destructor frees it with `fn drop`, so that C
+//! code can retrieve it with `PyCapsule_GetPointer`. The unsafe `from_py_capsule` methods check
+//! the capsule's name, and either borrow the value or take ownership of it back out of the
+//! capsule. Since the name is all that is checked, the caller must ensure that capsules with that
+//! name hold the native type. This requires the `pyo3` Cargo feature, and the type must be `Send`.
+//! Values freed with anything other than `fn drop`, such as those of types with `type Dtor`, cannot
+//! be stored in capsules:
+//!
+//! ```ignore
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo: Sync + Send {
+//!         type CType = foo_sys::FOO;
+//!         const PY_CAPSULE_NAME = "mymodule.Foo";
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//! }
+//! ```
+//!
+//! Types without a way to describe themselves can add `impl Debug;`, which implements `Debug` for
+//! both types by printing the name of the type and the pointer, such as `Foo(0x7f5a2c000b70)`.
+//! This lets structs holding them derive `Debug`:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!         impl Debug;
+//!     }
+//! }
+//!
+//! #[derive(Debug)]
+//! pub struct Context {
+//!     foo: Foo,
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! On Windows, types wrapping a `HANDLE` can add `impl AsHandle;`. If the
+//! `std` Cargo feature is enabled, this implements `AsHandle` and `AsRawHandle` for both types,
+//! `IntoRawHandle` and conversions to and from `OwnedHandle` for the owned type, and adds a
+//! `from_raw_handle_checked` constructor which rejects null and `INVALID_HANDLE_VALUE` handles. The
+//! `CType` should be `c_void`, and conversions through `OwnedHandle` are only meaningful if the
+//! handle is closed with `CloseHandle`:
+//!
+//! ```ignore
+//! foreign_type! {
+//!     /// A Windows event.
+//!     pub unsafe type Event: Sync + Send {
+//!         type CType = core::ffi::c_void;
+//!         fn drop = |h| { windows_sys::Win32::Foundation::CloseHandle(h as _); };
+//!         impl AsHandle;
+//!     }
+//! }
+//! ```
+//!
+//! Crates built against foreign-types 0.3, such as `openssl`, use an older, incompatible version of
+//! the `ForeignType` and `ForeignTypeRef` traits. Listing the older crate's `ForeignType` trait with
+//! `impl` additionally implements both of its traits, so that the types can be
+//! used with generic code written against either version:
+//!
+//! ```ignore
+//! // In Cargo.toml: foreign-types-03 = { package = "foreign-types", version = "0.3" }
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo: Sync + Send {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!         impl foreign_types_03::ForeignType;
+//!     }
+//! }
+//! ```
+//!
+//! Functions returning several objects often do so as a null-terminated array of pointers, which is
+//! wrapped by the `array::NullTerminated` type. Specifying `fn free_array` adds a
+//! `from_null_terminated` constructor which takes ownership of both the array, freed with that
+//! function, and its elements:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//! use foreign_types::array::NullTerminated;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!         pub fn FOO_free_array(foos: *mut *mut FOO);
+//!         pub fn FOO_list() -> *mut *mut FOO;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!         fn free_array = foo_sys::FOO_free_array;
+//!     }
+//! }
+//!
+//! impl Foo {
+//!     pub fn list() -> NullTerminated<Foo> {
+//!         unsafe { Foo::from_null_terminated(foo_sys::FOO_list()) }
+//!     }
+//! }
+//!
+//! fn count_foos() -> usize {
+//!     let foos = Foo::list();
+//!     let mut count = 0;
+//!     for _foo in &foos {
+//!         // `_foo` is a `&FooRef`.
+//!         count += 1;
+//!     }
+//!     count
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! Types which can be parsed from a byte buffer can specify the parsing function with
+//! `fn from_bytes`. It is called with a pointer to and the length of the
+//! buffer, and must return null on failure. This generates a safe `from_bytes` constructor and, if
+//! the `arbitrary` Cargo feature is enabled, an implementation of `arbitrary::Arbitrary` which
+//! parses the fuzzer's input, making fuzz targets for C parsers trivial to write:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     use std::os::raw::c_uchar;
+//!
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!         pub fn FOO_parse(data: *const c_uchar, len: usize) -> *mut FOO;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A parsed Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!         fn from_bytes = foo_sys::FOO_parse;
+//!     }
+//! }
+//!
+//! fn is_valid(data: &[u8]) -> bool {
+//!     Foo::from_bytes(data).is_some()
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! Similarly, types which can be parsed from a string can specify `fn from_str`.
+//! It is called with a pointer to a nul-terminated copy of the string, and must return null on
+//! failure. If the `std` Cargo feature is enabled, this generates an implementation of `FromStr`
+//! whose error type is named after the type, such as `ParseFooError`:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     use std::os::raw::c_char;
+//!
+//!     pub enum MIME {}
+//!
+//!     extern {
+//!         pub fn MIME_free(mime: *mut MIME);
+//!         pub fn MIME_parse_str(s: *const c_char) -> *mut MIME;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A MIME type.
+//!     pub unsafe type Mime {
+//!         type CType = foo_sys::MIME;
+//!         fn drop = foo_sys::MIME_free;
+//!         fn from_str = foo_sys::MIME_parse_str;
+//!     }
+//! }
+//!
+//! # #[cfg(feature = "std")]
+//! fn plain_text() -> Result<Mime, ParseMimeError> {
+//!     "text/plain".parse()
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! Types with a C comparison function can specify it with `fn cmp`. It is called
+//! with two pointers and must return a negative value, zero or a positive value if the first is less
+//! than, equal to or greater than the second, respectively. This generates implementations of
+//! `PartialEq`, `Eq`, `PartialOrd` and `Ord` for both the owned and reference types, so that they
+//! can be sorted and used as keys in a `BTreeMap`:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     use std::os::raw::c_int;
+//!
+//!     pub enum X509_NAME {}
+//!
+//!     extern {
+//!         pub fn X509_NAME_free(name: *mut X509_NAME);
+//!         pub fn X509_NAME_cmp(a: *const X509_NAME, b: *const X509_NAME) -> c_int;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// An X509 name.
+//!     pub unsafe type X509Name {
+//!         type CType = foo_sys::X509_NAME;
+//!         fn drop = foo_sys::X509_NAME_free;
+//!         fn cmp = foo_sys::X509_NAME_cmp;
+//!     }
+//! }
+//!
+//! fn sort_names(names: &mut [X509Name]) {
+//!     names.sort();
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! Many C constructors return null on failure, leaving the reason to be retrieved from a function
+//! such as `ERR_get_error`. Specifying that function and its return type with `fn last_error`
+//! generates an error type named after the type, and a `from_ptr_checked`
+//! constructor which returns it if the pointer is null. The error type derives `Clone`, `Copy`,
+//! `PartialEq`, `Eq` and `Debug`, and its `Display` implementation formats the code with `Display`,
+//! so the return type must implement all of these traits. This is the case for the integer codes
+//! such functions usually return; pointers to error messages are rejected:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//! use std::os::raw::c_ulong;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_new() -> *mut FOO;
+//!         pub fn FOO_free(foo: *mut FOO);
+//!         pub fn FOO_get_error() -> std::os::raw::c_ulong;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!         fn last_error: c_ulong = foo_sys::FOO_get_error;
+//!     }
+//! }
+//!
+//! impl Foo {
+//!     pub fn new() -> Result<Foo, FooError> {
+//!         unsafe { Foo::from_ptr_checked(foo_sys::FOO_new()) }
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! Stream-like types can specify `fn read` and `fn write`. They are called
+//! with the pointer, a buffer and its length, and must return the number of bytes read or written,
+//! or a negative value on failure. If the `std` Cargo feature is enabled, they are used to implement
+//! `io::Read` and `io::Write` for the reference type. Failures are reported as `io::Error`s wrapping
+//! the type's last error if `fn last_error` is also specified, in which case its type must be `Send`
+//! and `Sync`, and returning more bytes than requested is reported as an `InvalidData` error.
+//! `fn flush` may be given along with `fn write`, and is called with the pointer to implement `Write::flush`,
+//! returning a negative value on failure. Without it, flushing does nothing:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     use std::os::raw::{c_int, c_void};
+//!
+//!     pub enum BIO {}
+//!
+//!     extern {
+//!         pub fn BIO_free(bio: *mut BIO);
+//!         pub fn BIO_read(bio: *mut BIO, buf: *mut c_void, len: c_int) -> c_int;
+//!         pub fn BIO_write(bio: *mut BIO, buf: *const c_void, len: c_int) -> c_int;
+//!         pub fn BIO_flush(bio: *mut BIO) -> c_int;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A BIO.
+//!     pub unsafe type Bio {
+//!         type CType = foo_sys::BIO;
+//!         fn drop = foo_sys::BIO_free;
+//!         fn read = foo_sys::BIO_read;
+//!         fn write = foo_sys::BIO_write;
+//!         fn flush = foo_sys::BIO_flush;
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! Wrappers around C error objects can specify `fn message`, which returns a pointer to a
+//! nul-terminated description of the error, and `fn code: Type = path;`, which returns its code. The code is exposed through a `code` method on the reference type, and if the `std` Cargo
+//! feature is enabled, the message is used to implement `Display`, `Debug` and `Error` for both
+//! types, so that the errors can be propagated with `?`:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     use std::os::raw::{c_char, c_int};
+//!
+//!     pub enum ERROR {}
+//!
+//!     extern {
+//!         pub fn ERROR_free(error: *mut ERROR);
+//!         pub fn ERROR_message(error: *mut ERROR) -> *const c_char;
+//!         pub fn ERROR_code(error: *mut ERROR) -> c_int;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// An error.
+//!     pub unsafe type Error: Send + Sync {
+//!         type CType = foo_sys::ERROR;
+//!         fn drop = foo_sys::ERROR_free;
+//!         fn message = foo_sys::ERROR_message;
+//!         fn code: std::os::raw::c_int = foo_sys::ERROR_code;
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! Containers with a function removing an element and transferring its ownership to the caller can
+//! declare it with `fn pop: Element = path;`. The owned type then
+//! implements `IntoIterator`, yielding the elements as owned values. See the `pop` module for
+//! details.
+//!
+//! Types which are buffers of contiguous bytes can declare the functions returning their
+//! length and a pointer to their data with `fn len = path; fn data = path;`. This adds `as_bytes`
+//! and `as_bytes_mut` methods to the reference type, viewing the contents as a slice. A negative
+//! length is treated as an empty buffer:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     use std::os::raw::{c_int, c_uchar};
+//!
+//!     pub enum BUF {}
+//!
+//!     extern {
+//!         pub fn BUF_free(buf: *mut BUF);
+//!         pub fn BUF_length(buf: *mut BUF) -> c_int;
+//!         pub fn BUF_data(buf: *mut BUF) -> *mut c_uchar;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A buffer.
+//!     pub unsafe type Buf {
+//!         type CType = foo_sys::BUF;
+//!         fn drop = foo_sys::BUF_free;
+//!         fn len = foo_sys::BUF_length;
+//!         fn data = foo_sys::BUF_data;
+//!     }
+//! }
+//!
+//! fn is_empty(buf: &BufRef) -> bool {
+//!     buf.as_bytes().is_empty()
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! The length may be of any integer type, and the data pointer may be null if the buffer is empty.
+//!
+//! Types whose mutations must be bracketed by calls beginning and ending an edit can declare them
+//! with `fn begin_edit`, `fn commit_edit` and optionally `fn rollback_edit`. This adds an
+//! `edit` method to the reference type, returning a guard which commits the edit when finished and
+//! rolls it back when dropped. See the `edit` module for details.
+//!
+//! The owned type converts into a `NonNull` pointer to its `CType` with `From`, and implements
+//! `TryFrom<raw::OwnedPtr<CType>>`, which fails if the pointer is null. See the `raw` module for
+//! details.
+//!
+//! Global objects exported by a library, such as default allocators or method tables, can be
+//! declared alongside the types with `static ref`. This creates a static which dereferences to the
+//! given reference type, evaluating the initializer and checking that it is not null the first time
+//! it is used. The initializer must evaluate to a `*mut` pointer to the C type, so pointers to
+//! constant objects have to be cast explicitly:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub static FOO_DEFAULT: *mut FOO;
+//!         pub fn FOO_free(foo: *mut FOO);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//!
+//!     /// The default Foo.
+//!     pub static ref DEFAULT_FOO: FooRef = unsafe { foo_sys::FOO_DEFAULT };
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! Objects which are never owned by callers, such as method tables and interned singletons, can be
+//! declared with `unsafe type ref`. Only the given name is declared, as a reference type implementing
+//! `ForeignTypeRef`, with no owned type or destructor:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod ssl_sys {
+//!     pub enum SSL_METHOD {}
+//!
+//!     extern {
+//!         pub fn TLS_method() -> *const SSL_METHOD;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A TLS protocol implementation.
+//!     pub unsafe type ref SslMethod: Sync + Send {
+//!         type CType = ssl_sys::SSL_METHOD;
+//!     }
+//!
+//!     /// The general purpose TLS method.
+//!     pub static ref TLS_METHOD: SslMethod = unsafe { ssl_sys::TLS_method() as *mut _ };
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! Dictionaries mapping one foreign type to another can be declared with `unsafe type map`, which
+//! creates an alias of `map::ForeignMap`. See the `map` module for details.
+//!
+//! Invocations declaring many types can start with options applying to all of them, in this
+//! order:
+//!
+//! * `mod name;` places every generated item in a new module, which imports everything from the
+//!   enclosing module. The visibility of the items is then relative to the new module, and they can
+//!   be selectively re-exported with `pub use`.
+//! * `const STRIP_PREFIX = "...";` removes a prefix from the name of every type starting with it.
+//! * `const REF_SUFFIX = "...";` replaces the `Ref` suffix used to name the reference types.
+//!
+//! Types refer to each other by their final names:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod ossl_sys {
+//!     pub enum CTX {}
+//!     pub enum SESSION {}
+//!
+//!     extern {
+//!         pub fn CTX_free(ctx: *mut CTX);
+//!         pub fn SESSION_free(session: *mut SESSION);
+//!         pub fn SESSION_get_ctx(session: *mut SESSION) -> *mut CTX;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     pub mod ossl;
+//!     const STRIP_PREFIX = "Ossl";
+//!
+//!     pub unsafe type OsslCtx {
+//!         type CType = ossl_sys::CTX;
+//!         fn drop = ossl_sys::CTX_free;
+//!     }
+//!
+//!     pub unsafe type OsslSession {
+//!         type CType = ossl_sys::SESSION;
+//!         fn drop = ossl_sys::SESSION_free;
+//!         fn get ctx: CtxRef = ossl_sys::SESSION_get_ctx;
+//!     }
+//! }
+//!
+//! pub use ossl::{Ctx, CtxRef, Session, SessionRef};
+//!
+//! # fn main() {}
+//! ```
+//!
+//! Enabling the `track-ownership` Cargo feature makes the generated types record every owned pointer
+//! in a global table, panicking if a pointer is wrapped as owned twice, freed twice, or borrowed
+//! after being freed. See the `track` module for details. This is intended for testing binding
+//! crates and should not be enabled in production builds.
+//!
+//! Similarly, the `testing` Cargo feature adds a `live_count` associated function to the generated
+//! owned types and enables the `assert_no_leaks!` macro to check that a test does not leak any
+//! wrappers. See the `testing` module for details.
+//!
+//! The `mock` Cargo feature enables the `mock` module, a fake C library whose functions can be used
+//! to test declarations and the methods built on them without linking the real library.
+//!
+//! The `trace` Cargo feature reports every creation, clone and drop of an owned instance to a hook
+//! which can forward them to `log` or `tracing`, to help find the wrapper responsible for a leak or
+//! double free. See the `trace` module for details.
+//!
+//! For embedded targets, the `defmt` Cargo feature implements `defmt::Format` for the generated
+//! owned and reference types, printing the type name and pointer. It does not require `std`.
+//!
+//! The procedural macro behind `foreign_type!` can be disabled by turning off the default `macros`
+//! Cargo feature. The `foreign_type_decl!` macro is then available to declare simple types without
+//! depending on `syn`.
+//!
+//! Some APIs allocate values in more than one way, each of which must be paired with a different
+//! destructor. Declaring the type of the destructor with `type Dtor` stores it in each owned value.
+//! `fn drop` is then the default destructor used by `ForeignType::from_ptr`, and the generated
+//! `from_ptr_with_dtor` constructor selects a different one. Clones use the destructor of the value
+//! they were cloned from, while `ToOwned` uses the default destructor:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!         pub fn FOO_free_with_allocator(foo: *mut FOO);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         type Dtor = unsafe extern "C" fn(*mut foo_sys::FOO);
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//! }
+//!
+//! unsafe fn wrap_allocated(ptr: *mut foo_sys::FOO) -> Foo {
+//!     Foo::from_ptr_with_dtor(ptr, foo_sys::FOO_free_with_allocator)
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! A common special case is a container with both a shallow destructor, which frees only the
+//! container, and a deep destructor, which also frees its elements, such as OpenSSL's `sk_free` and
+//! `sk_pop_free`. The shallow destructor can be given with `fn drop_shallow`, and `fn drop` is then
+//! the deep destructor. This stores the destructor in each owned value as if `type Dtor` had been
+//! declared, and generates `from_ptr_deep` and `from_ptr_shallow` constructors:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum STACK {}
+//!
+//!     extern {
+//!         pub fn STACK_free(stack: *mut STACK);
+//!         pub fn STACK_free_all(stack: *mut STACK);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A stack.
+//!     pub unsafe type Stack {
+//!         type CType = foo_sys::STACK;
+//!         fn drop = foo_sys::STACK_free_all;
+//!         fn drop_shallow = foo_sys::STACK_free;
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! Libraries which can free many values at once can declare the function with `fn drop_many`. It is called with a pointer to an array of values and its length, and if the `std`
+//! Cargo feature is enabled, generates a `drop_many` function on the owned type which frees a
+//! `Vec` of values in a single call:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!         pub fn FOO_free_all(foos: *mut *mut FOO, n: usize);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!         fn drop_many = foo_sys::FOO_free_all;
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! Destructors which must run on a particular thread, or which may block, can be deferred by naming
+//! a `queue::DropQueue` static with `static DROP_QUEUE`. See the `queue` module for details.
+//!
+//! Alternatively, types whose destructor performs I/O can provide a non-blocking shutdown path with
+//! `fn close`. It is called with the raw pointer, and must return a future which frees it. This
+//! generates an `async fn close(self)` method on the owned type. Dropping a value without closing it
+//! still calls `fn drop`, prints a warning in debug builds if the `std` Cargo feature is enabled,
+//! and is reported as `trace::EventKind::DropUnclosed` if the `trace` Cargo feature is enabled:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod conn_sys {
+//!     use std::os::raw::c_int;
+//!
+//!     pub enum CONN {}
+//!
+//!     pub const WANT_WRITE: c_int = 1;
+//!
+//!     extern {
+//!         pub fn CONN_free(conn: *mut CONN);
+//!         pub fn CONN_shutdown(conn: *mut CONN) -> c_int;
+//!         pub fn CONN_fd(conn: *mut CONN) -> c_int;
+//!     }
+//! }
+//! # async fn writable(_: std::os::raw::c_int) {}
+//!
+//! foreign_type! {
+//!     /// A connection.
+//!     pub unsafe type Connection {
+//!         type CType = conn_sys::CONN;
+//!         fn drop = conn_sys::CONN_free;
+//!         fn close = close_connection;
+//!     }
+//! }
+//!
+//! // Flushes the connection without blocking the executor, then frees it.
+//! async unsafe fn close_connection(conn: *mut conn_sys::CONN) {
+//!     while conn_sys::CONN_shutdown(conn) == conn_sys::WANT_WRITE {
+//!         writable(conn_sys::CONN_fd(conn)).await;
+//!     }
+//!     conn_sys::CONN_free(conn);
+//! }
+//!
+//! async fn finish(conn: Connection) {
+//!     conn.close().await;
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! A panic in a Rust function passed as `fn drop` or `fn clone` unwinds out of `Drop` or `Clone`,
+//! which is undefined behavior if the value is being dropped or cloned by a C callback. If the
+//! `abort-on-panic` Cargo feature is enabled, the generated code instead reports the panic and
+//! aborts. See the `panic` module for details.
+//!
+//! Libraries such as APR and talloc allocate values from a pool, which frees all of them at once
+//! when it is destroyed. The pool is declared as a normal type, and each pool-allocated type names
+//! it with `type Pool` in place of `fn drop`. Pool-allocated types must have a lifetime parameter,
+//! the first of which bounds the borrow of the pool. Dropping them does nothing, and the generated
+//! `from_pool_ptr` constructor ties them to the pool they were allocated from:
+//!
+//! ```
+//! use foreign_types::{foreign_type, ForeignTypeRef};
+//!
+//! mod pool_sys {
+//!     pub enum POOL {}
+//!     pub enum NODE {}
+//!
+//!     extern {
+//!         pub fn POOL_destroy(pool: *mut POOL);
+//!         pub fn NODE_new(pool: *mut POOL) -> *mut NODE;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A memory pool.
+//!     pub unsafe type Pool {
+//!         type CType = pool_sys::POOL;
+//!         fn drop = pool_sys::POOL_destroy;
+//!     }
+//!
+//!     /// A node allocated from a pool.
+//!     pub unsafe type Node<'pool> {
+//!         type CType = pool_sys::NODE;
+//!         type Pool = Pool;
+//!     }
+//! }
+//!
+//! fn new_node(pool: &PoolRef) -> Node<'_> {
+//!     unsafe { Node::from_pool_ptr(pool, pool_sys::NODE_new(pool.as_ptr())) }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! If the C library is loaded at runtime, for example with `libloading`, the destructor and copy
+//! functions can be declared as `dyn`. They are then stored in statics which must be set with the
+//! generated `set_drop_fn` and `set_clone_fn` functions before any instance is dropped or cloned,
+//! or the drop or clone panics:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo from a dynamically loaded library.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = dyn;
+//!         fn clone = dyn;
+//!     }
+//! }
+//!
+//! # unsafe extern "C" fn FOO_free(_: *mut foo_sys::FOO) {}
+//! # unsafe extern "C" fn FOO_dup(p: *mut foo_sys::FOO) -> *mut foo_sys::FOO { p }
+//! // Typically looked up with `libloading::Library::get`.
+//! unsafe {
+//!     Foo::set_drop_fn(FOO_free);
+//!     Foo::set_clone_fn(FOO_dup);
+//! }
+//! ```
+//!
 //! Say we then have a separate type in our C API that contains a `FOO`:
 //!
 //! ```
@@ -148,7 +1242,9 @@
 //!
 //! The documentation for the C library states that `BAR_get_foo` returns a reference into the `BAR`
 //! passed to it, which translates into a reference in Rust. It also says that we're allowed to
-//! modify the `FOO`, so we'll define a pair of accessor methods, one immutable and one mutable:
+//! modify the `FOO`, so we'll define a pair of accessor methods, one immutable and one mutable.
+//! `from_ptr_of` and `from_ptr_mut_of` tie the lifetime of the returned reference to the `BarRef`,
+//! which `from_ptr` would otherwise leave unconstrained:
 //!
 //! ```
 //! use foreign_types::{ForeignTypeRef, foreign_type};
@@ -180,11 +1276,95 @@
 //!
 //! impl BarRef {
 //!     fn foo(&self) -> &FooRef {
-//!         unsafe { FooRef::from_ptr(foo_sys::BAR_get_foo(self.as_ptr())) }
+//!         unsafe { FooRef::from_ptr_of(self, foo_sys::BAR_get_foo(self.as_ptr())) }
 //!     }
 //!
 //!     fn foo_mut(&mut self) -> &mut FooRef {
-//!         unsafe { FooRef::from_ptr_mut(foo_sys::BAR_get_foo(self.as_ptr())) }
+//!         let ptr = self.as_ptr();
+//!         unsafe { FooRef::from_ptr_mut_of(self, foo_sys::BAR_get_foo(ptr)) }
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! Since accessors like these are so common, they can also be generated by listing them with
+//! `fn get`. This is equivalent to the `impl BarRef` block above, except that
+//! the methods have the visibility of the type and panic if the C function returns null:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!     pub enum BAR {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!         pub fn BAR_free(bar: *mut BAR);
+//!         pub fn BAR_get_foo(bar: *mut BAR) -> *mut FOO;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo: Sync + Send {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//!
+//!     /// A Bar.
+//!     pub unsafe type Bar: Sync + Send {
+//!         type CType = foo_sys::BAR;
+//!         fn drop = foo_sys::BAR_free;
+//!         /// Returns the Bar's Foo.
+//!         fn get foo: FooRef = foo_sys::BAR_get_foo;
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! Setters are generated in the same way with `fn set`, which must state whether the C function
+//! `takes` ownership of its argument or `copies` it. The former generates a method accepting the
+//! owned type, whose pointer is passed with `into_ptr`, and the latter a method accepting a
+//! reference. If the C function returns a status, its type follows the argument type after `->`
+//! and the method returns it. With `takes`, ownership is transferred before the call regardless of
+//! the status, so the C function must free its argument if it fails. Getters and setters may be
+//! listed in any order:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     use std::os::raw::c_int;
+//!
+//!     pub enum FOO {}
+//!     pub enum BAR {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!         pub fn BAR_free(bar: *mut BAR);
+//!         pub fn BAR_set0_foo(bar: *mut BAR, foo: *mut FOO);
+//!         pub fn BAR_set1_peer(bar: *mut BAR, peer: *mut FOO) -> c_int;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo: Sync + Send {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//!
+//!     /// A Bar.
+//!     pub unsafe type Bar: Sync + Send {
+//!         type CType = foo_sys::BAR;
+//!         fn drop = foo_sys::BAR_free;
+//!         // Generates `fn set_foo(&mut self, foo: Foo)`.
+//!         fn set foo(takes): Foo = foo_sys::BAR_set0_foo;
+//!         // Generates `fn set_peer(&mut self, peer: &FooRef) -> c_int`.
+//!         fn set peer(copies): FooRef -> std::os::raw::c_int = foo_sys::BAR_set1_peer;
 //!     }
 //! }
 //!
@@ -197,22 +1377,112 @@
 #[cfg(feature = "std")]
 extern crate std;
 
+#[cfg(feature = "macros")]
 #[doc(hidden)]
 pub use foreign_types_macros::foreign_type_impl;
 #[doc(inline)]
-pub use foreign_types_shared::{ForeignType, ForeignTypeRef, Opaque};
+pub use foreign_types_shared::{
+    AnyForeignType, ForeignType, ForeignTypeRef, Opaque, RefCounted, SendForeignType, Shared,
+    SyncForeignType,
+};
+
+pub mod array;
+mod atomic_fn;
+mod decl;
+pub mod dynamic;
+pub mod edit;
+#[cfg(feature = "std")]
+pub mod init;
+pub mod map;
+pub mod maybe_owned;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "std")]
+pub mod panic;
+pub mod pop;
+#[cfg(feature = "pyo3")]
+mod py_capsule;
+#[cfg(feature = "std")]
+pub mod queue;
+pub mod raw;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod thread;
+#[cfg(feature = "trace")]
+pub mod trace;
+#[cfg(feature = "track-ownership")]
+pub mod track;
 
 #[doc(hidden)]
 pub mod export {
     pub use core::borrow::{Borrow, BorrowMut};
+    pub use core::cell::UnsafeCell;
     pub use core::clone::Clone;
-    pub use core::convert::{AsMut, AsRef};
+    pub use core::cmp::{self, Eq, Ord, PartialEq, PartialOrd};
+    pub use core::convert::{AsMut, AsRef, From, TryFrom};
+    pub use core::fmt;
+    pub use core::iter::IntoIterator;
     pub use core::marker::{PhantomData, Send, Sync};
+    pub use core::mem::{drop, forget, needs_drop};
     pub use core::ops::{Deref, DerefMut, Drop};
-    pub use core::ptr::NonNull;
+    pub use core::option::Option;
+    pub use core::panic::{RefUnwindSafe, UnwindSafe};
+    pub use core::ptr::{null_mut, NonNull};
+    pub use core::result::Result::{self, Err, Ok};
+    pub use core::slice;
+    pub use core::str::FromStr;
+    pub use core::sync::atomic::{AtomicPtr, Ordering};
+    pub use core::{concat, format_args, stringify, write};
+    pub use foreign_types_shared::{clone_ptr, ptr_addr};
 
     #[cfg(feature = "std")]
     pub use std::borrow::ToOwned;
+    #[cfg(feature = "std")]
+    pub use std::error::Error;
+    #[cfg(feature = "std")]
+    pub use std::ffi::{CStr, CString};
+    #[cfg(feature = "std")]
+    pub use std::io;
+    #[cfg(feature = "std")]
+    pub use std::vec::Vec;
+
+    #[cfg(feature = "pyo3")]
+    pub use crate::py_capsule::{
+        py_capsule_destructor_pointer, py_capsule_new, py_capsule_pointer, py_capsule_take,
+    };
+    #[cfg(feature = "arbitrary")]
+    pub use arbitrary;
+    #[cfg(all(feature = "core-foundation", target_vendor = "apple"))]
+    pub use core_foundation;
+    #[cfg(feature = "defmt")]
+    pub use defmt;
+    #[cfg(feature = "glib")]
+    pub use glib;
+    #[cfg(all(feature = "objc2", target_vendor = "apple"))]
+    pub use objc2;
+    #[cfg(feature = "pyo3")]
+    pub use pyo3;
+
+    /// Has the same auto traits as `T`, for the bounds generated by `: auto`.
+    pub struct AutoTraits<'a, T: ?Sized>(PhantomData<&'a ()>, PhantomData<T>);
+
+    /// Formats a value of a generated type as its name and address.
+    #[cfg(feature = "defmt")]
+    #[inline]
+    pub fn defmt_format_ptr(fmt: defmt::Formatter<'_>, name: &str, addr: usize) {
+        defmt::write!(fmt, "{=str}({=usize:#x})", name, addr)
+    }
+
+    /// Reports that a value of a type with `fn close` was dropped without being closed.
+    #[cfg(feature = "std")]
+    #[cold]
+    pub fn warn_unclosed(type_name: &str) {
+        std::eprintln!(
+            "warning: `{}` was dropped without being closed, freeing it synchronously",
+            type_name
+        );
+    }
 }
 
 /// A macro to easily define wrappers for foreign types.
@@ -242,6 +1512,7 @@ pub mod export {
 ///
 /// # fn main() {}
 /// ```
+#[cfg(feature = "macros")]
 #[macro_export(local_inner_macros)]
 macro_rules! foreign_type {
     ($($t:tt)*) => {
@@ -0,0 +1,379 @@
+//! Keyed dictionaries of foreign types.
+//!
+//! C libraries commonly provide a hash table or dictionary type, such as `CFDictionary` or
+//! `GHashTable`, which maps one kind of object to another. `ForeignMap` wraps such a container,
+//! providing typed lookups, insertions and iteration. The C functions backing it are described by an
+//! implementation of `MapFunctions`, which is typically generated with `type map` in
+//! `foreign_type!`:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//! # #[cfg(feature = "std")]
+//! use foreign_types::panic::abort_on_panic;
+//! # #[cfg(not(feature = "std"))]
+//! # fn abort_on_panic<R>(_: &str, f: impl FnOnce() -> R) -> R { f() }
+//!
+//! mod foo_sys {
+//!     use std::os::raw::c_void;
+//!
+//!     pub enum KEY {}
+//!     pub enum VALUE {}
+//!     pub enum DICT {}
+//!
+//!     extern {
+//!         pub fn KEY_free(key: *mut KEY);
+//!         pub fn VALUE_free(value: *mut VALUE);
+//!         pub fn DICT_new() -> *mut DICT;
+//!         pub fn DICT_free(dict: *mut DICT);
+//!         pub fn DICT_insert(dict: *mut DICT, key: *mut KEY, value: *mut VALUE);
+//!         pub fn DICT_lookup(dict: *mut DICT, key: *mut KEY) -> *mut VALUE;
+//!         pub fn DICT_size(dict: *mut DICT) -> usize;
+//!         pub fn DICT_foreach(
+//!             dict: *mut DICT,
+//!             f: unsafe extern "C" fn(*mut KEY, *mut VALUE, *mut c_void),
+//!             data: *mut c_void,
+//!         );
+//!     }
+//! }
+//!
+//! // Adapts `DICT_foreach` to the closure expected by `MapFunctions::for_each`.
+//! unsafe fn dict_for_each(
+//!     dict: *mut foo_sys::DICT,
+//!     f: &mut dyn FnMut(*mut foo_sys::KEY, *mut foo_sys::VALUE),
+//! ) {
+//!     unsafe extern "C" fn trampoline(
+//!         key: *mut foo_sys::KEY,
+//!         value: *mut foo_sys::VALUE,
+//!         data: *mut std::os::raw::c_void,
+//!     ) {
+//!         let f = &mut *(data as *mut &mut dyn FnMut(*mut foo_sys::KEY, *mut foo_sys::VALUE));
+//!         // Unwinding out of an `extern "C"` function is undefined behavior.
+//!         abort_on_panic("DICT_foreach", || f(key, value));
+//!     }
+//!
+//!     foo_sys::DICT_foreach(dict, trampoline, &mut &mut *f as *mut _ as *mut _);
+//! }
+//!
+//! foreign_type! {
+//!     /// A key.
+//!     pub unsafe type Key {
+//!         type CType = foo_sys::KEY;
+//!         fn drop = foo_sys::KEY_free;
+//!     }
+//!
+//!     /// A value.
+//!     pub unsafe type Value {
+//!         type CType = foo_sys::VALUE;
+//!         fn drop = foo_sys::VALUE_free;
+//!     }
+//!
+//!     /// A dictionary from keys to values.
+//!     pub unsafe type map Dict<Key => Value> {
+//!         type CType = foo_sys::DICT;
+//!         fn new = foo_sys::DICT_new;
+//!         fn drop = foo_sys::DICT_free;
+//!         fn insert(takes) = foo_sys::DICT_insert;
+//!         fn get = foo_sys::DICT_lookup;
+//!         fn len = foo_sys::DICT_size;
+//!         fn for_each = dict_for_each;
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! ```
+//!
+//! This declares `Dict` as an alias of `ForeignMap<DictFunctions>`. `fn insert` must state whether
+//! the C function `takes` ownership of the key and value, or `copies` them, in which case the values
+//! passed to `ForeignMap::insert` are dropped after it returns.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+#[cfg(feature = "std")]
+use std::vec::{self, Vec};
+
+use crate::{ForeignType, ForeignTypeRef};
+
+/// The C functions backing a `ForeignMap`.
+///
+/// # Safety
+///
+/// The functions must behave as documented, and `INSERT_TAKES_OWNERSHIP` must correctly describe
+/// `insert`.
+pub unsafe trait MapFunctions {
+    /// The raw C type of the map.
+    type CType;
+
+    /// The type of the keys.
+    type Key: ForeignType;
+
+    /// The type of the values.
+    type Value: ForeignType;
+
+    /// `true` if `insert` takes ownership of the key and value, and `false` if it copies or retains
+    /// them.
+    const INSERT_TAKES_OWNERSHIP: bool;
+
+    /// Creates a new, empty map, returning null on failure.
+    ///
+    /// # Safety
+    ///
+    /// This is unsafe since it calls into C.
+    unsafe fn new() -> *mut Self::CType;
+
+    /// Frees a map and the keys and values it owns.
+    ///
+    /// # Safety
+    ///
+    /// `map` must be a valid, owned map.
+    unsafe fn free(map: *mut Self::CType);
+
+    /// Inserts a key and value into a map, replacing any existing value for the key.
+    ///
+    /// # Safety
+    ///
+    /// `map` must be a valid, mutable map, and `key` and `value` valid instances of their types.
+    unsafe fn insert(
+        map: *mut Self::CType,
+        key: *mut <Self::Key as ForeignType>::CType,
+        value: *mut <Self::Value as ForeignType>::CType,
+    );
+
+    /// Returns the value for a key, or null if there is none.
+    ///
+    /// The returned value must remain owned by the map.
+    ///
+    /// # Safety
+    ///
+    /// `map` must be a valid map, and `key` a valid instance of its type.
+    unsafe fn get(
+        map: *mut Self::CType,
+        key: *mut <Self::Key as ForeignType>::CType,
+    ) -> *mut <Self::Value as ForeignType>::CType;
+
+    /// Returns the number of entries in a map.
+    ///
+    /// # Safety
+    ///
+    /// `map` must be a valid map.
+    unsafe fn len(map: *mut Self::CType) -> usize;
+
+    /// Calls `f` with each key and value in a map.
+    ///
+    /// # Safety
+    ///
+    /// `map` must be a valid map.
+    #[allow(clippy::type_complexity)]
+    unsafe fn for_each(
+        map: *mut Self::CType,
+        f: &mut dyn FnMut(
+            *mut <Self::Key as ForeignType>::CType,
+            *mut <Self::Value as ForeignType>::CType,
+        ),
+    );
+}
+
+/// An owned map from values of the foreign type `M::Key` to values of the foreign type `M::Value`.
+pub struct ForeignMap<M: MapFunctions> {
+    ptr: NonNull<M::CType>,
+    _p: PhantomData<(M::Key, M::Value)>,
+}
+
+type KeyRef<M> = <<M as MapFunctions>::Key as ForeignType>::Ref;
+type ValueRef<M> = <<M as MapFunctions>::Value as ForeignType>::Ref;
+
+impl<M: MapFunctions> ForeignMap<M> {
+    /// Creates a new, empty map.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the map could not be allocated.
+    #[inline]
+    pub fn new() -> ForeignMap<M> {
+        unsafe {
+            let ptr = M::new();
+            assert!(!ptr.is_null(), "failed to allocate a map");
+            ForeignMap::from_ptr(ptr)
+        }
+    }
+
+    /// Constructs an instance of this type from its raw type.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid, owned map.
+    #[inline]
+    pub unsafe fn from_ptr(ptr: *mut M::CType) -> ForeignMap<M> {
+        debug_assert!(!ptr.is_null());
+        ForeignMap {
+            ptr: NonNull::new_unchecked(ptr),
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns a raw pointer to the map.
+    #[inline]
+    pub fn as_ptr(&self) -> *mut M::CType {
+        self.ptr.as_ptr()
+    }
+
+    /// Consumes the wrapper and returns the raw pointer.
+    #[inline]
+    #[must_use = "ignoring the pointer leaks the map"]
+    pub fn into_ptr(self) -> *mut M::CType {
+        let ptr = self.as_ptr();
+        core::mem::forget(self);
+        ptr
+    }
+
+    /// Returns the number of entries in the map.
+    #[inline]
+    pub fn len(&self) -> usize {
+        unsafe { M::len(self.as_ptr()) }
+    }
+
+    /// Returns `true` if the map has no entries.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a reference to the value for `key`, or `None` if there is none.
+    #[inline]
+    pub fn get(&self, key: &KeyRef<M>) -> Option<&ValueRef<M>> {
+        unsafe {
+            let value = M::get(self.as_ptr(), key.as_ptr());
+            ForeignTypeRef::from_ptr_opt(value)
+        }
+    }
+
+    /// Returns `true` if the map has a value for `key`.
+    #[inline]
+    pub fn contains_key(&self, key: &KeyRef<M>) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts `value` for `key`, replacing any existing value.
+    #[inline]
+    pub fn insert(&mut self, key: M::Key, value: M::Value) {
+        unsafe {
+            if M::INSERT_TAKES_OWNERSHIP {
+                M::insert(self.as_ptr(), key.into_ptr(), value.into_ptr());
+            } else {
+                M::insert(self.as_ptr(), key.as_ptr(), value.as_ptr());
+            }
+        }
+    }
+
+    /// Calls `f` with references to each key and value in the map.
+    ///
+    /// If the `std` Cargo feature is enabled, a panic in `f` aborts the process, since it would
+    /// otherwise unwind through the C function iterating over the map.
+    #[inline]
+    pub fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&KeyRef<M>, &ValueRef<M>),
+    {
+        unsafe {
+            M::for_each(self.as_ptr(), &mut |key, value| {
+                let key = ForeignTypeRef::from_ptr(key);
+                let value = ForeignTypeRef::from_ptr(value);
+                // The closure is called from a C callback, which it must not unwind out of.
+                #[cfg(feature = "std")]
+                crate::panic::abort_on_panic("ForeignMap::for_each", || f(key, value));
+                #[cfg(not(feature = "std"))]
+                f(key, value);
+            });
+        }
+    }
+
+    /// Returns an iterator over references to the keys and values in the map.
+    ///
+    /// The entries are collected up front, in the order visited by `MapFunctions::for_each`.
+    #[cfg(feature = "std")]
+    pub fn iter(&self) -> Iter<'_, M> {
+        let mut entries = Vec::with_capacity(self.len());
+        self.for_each(|key, value| {
+            entries.push((key.as_ptr(), value.as_ptr()));
+        });
+        Iter {
+            entries: entries.into_iter(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<M: MapFunctions> Default for ForeignMap<M> {
+    #[inline]
+    fn default() -> ForeignMap<M> {
+        ForeignMap::new()
+    }
+}
+
+impl<M: MapFunctions> Drop for ForeignMap<M> {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { M::free(self.as_ptr()) }
+    }
+}
+
+impl<M> fmt::Debug for ForeignMap<M>
+where
+    M: MapFunctions,
+    KeyRef<M>: fmt::Debug,
+    ValueRef<M>: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let mut map = fmt.debug_map();
+        self.for_each(|key, value| {
+            map.entry(&key, &value);
+        });
+        map.finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, M: MapFunctions> IntoIterator for &'a ForeignMap<M> {
+    type Item = (&'a KeyRef<M>, &'a ValueRef<M>);
+    type IntoIter = Iter<'a, M>;
+
+    #[inline]
+    fn into_iter(self) -> Iter<'a, M> {
+        self.iter()
+    }
+}
+
+/// An iterator over references to the keys and values of a `ForeignMap`.
+#[cfg(feature = "std")]
+#[allow(clippy::type_complexity)]
+pub struct Iter<'a, M: MapFunctions> {
+    entries: vec::IntoIter<(
+        *mut <M::Key as ForeignType>::CType,
+        *mut <M::Value as ForeignType>::CType,
+    )>,
+    _p: PhantomData<&'a ForeignMap<M>>,
+}
+
+#[cfg(feature = "std")]
+impl<'a, M: MapFunctions> Iterator for Iter<'a, M> {
+    type Item = (&'a KeyRef<M>, &'a ValueRef<M>);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next().map(|(key, value)| unsafe {
+            (
+                ForeignTypeRef::from_ptr(key),
+                ForeignTypeRef::from_ptr(value),
+            )
+        })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, M: MapFunctions> ExactSizeIterator for Iter<'a, M> {}
@@ -0,0 +1,115 @@
+//! Values which may be either owned or borrowed.
+//!
+//! Some C functions return a pointer which the caller owns in some cases and merely borrows in
+//! others, as indicated by a flag or by which function was called. `MaybeOwned` wraps such a pointer,
+//! freeing it only if it is owned.
+
+use core::fmt;
+use core::ops::Deref;
+
+#[cfg(feature = "std")]
+use std::borrow::ToOwned;
+
+use crate::{ForeignType, ForeignTypeRef};
+
+/// A value of the foreign type `T` which is either owned or borrowed for the lifetime `'a`.
+///
+/// This is analogous to `Cow`, and derefs to `T::Ref` in either case.
+pub enum MaybeOwned<'a, T: ForeignType> {
+    /// An owned value, which is freed when dropped.
+    Owned(T),
+    /// A borrowed value.
+    Borrowed(&'a T::Ref),
+}
+
+impl<'a, T: ForeignType> MaybeOwned<'a, T> {
+    /// Constructs an instance of this type from its raw type, taking ownership of it if `owned` is
+    /// `true`.
+    ///
+    /// # Safety
+    ///
+    /// If `owned` is `true`, `ptr` must be a valid, owned instance of the native type. Otherwise,
+    /// it must be a valid, immutable instance of the type for the `'a` lifetime.
+    #[inline]
+    pub unsafe fn from_ptr(ptr: *mut T::CType, owned: bool) -> MaybeOwned<'a, T> {
+        if owned {
+            MaybeOwned::Owned(T::from_ptr(ptr))
+        } else {
+            MaybeOwned::Borrowed(T::Ref::from_ptr(ptr))
+        }
+    }
+
+    /// Returns `true` if the value is owned.
+    #[inline]
+    pub fn is_owned(&self) -> bool {
+        matches!(self, MaybeOwned::Owned(_))
+    }
+
+    /// Returns `true` if the value is borrowed.
+    #[inline]
+    pub fn is_borrowed(&self) -> bool {
+        !self.is_owned()
+    }
+
+    /// Returns the owned value, cloning it if it is borrowed.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn into_owned(self) -> T
+    where
+        T::Ref: ToOwned<Owned = T>,
+    {
+        match self {
+            MaybeOwned::Owned(owned) => owned,
+            MaybeOwned::Borrowed(borrowed) => borrowed.to_owned(),
+        }
+    }
+}
+
+impl<'a, T: ForeignType> Deref for MaybeOwned<'a, T> {
+    type Target = T::Ref;
+
+    #[inline]
+    fn deref(&self) -> &T::Ref {
+        match self {
+            MaybeOwned::Owned(owned) => unsafe { T::Ref::from_ptr(owned.as_ptr()) },
+            MaybeOwned::Borrowed(borrowed) => borrowed,
+        }
+    }
+}
+
+impl<'a, T: ForeignType> AsRef<T::Ref> for MaybeOwned<'a, T> {
+    #[inline]
+    fn as_ref(&self) -> &T::Ref {
+        self
+    }
+}
+
+impl<'a, T: ForeignType> From<T> for MaybeOwned<'a, T> {
+    #[inline]
+    fn from(owned: T) -> MaybeOwned<'a, T> {
+        MaybeOwned::Owned(owned)
+    }
+}
+
+impl<'a, T> Clone for MaybeOwned<'a, T>
+where
+    T: ForeignType + Clone,
+{
+    #[inline]
+    fn clone(&self) -> MaybeOwned<'a, T> {
+        match self {
+            MaybeOwned::Owned(owned) => MaybeOwned::Owned(owned.clone()),
+            MaybeOwned::Borrowed(borrowed) => MaybeOwned::Borrowed(*borrowed),
+        }
+    }
+}
+
+impl<'a, T> fmt::Debug for MaybeOwned<'a, T>
+where
+    T: ForeignType,
+    T::Ref: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, fmt)
+    }
+}
@@ -0,0 +1,225 @@
+//! A fake C library used by the `mock` Cargo feature.
+//!
+//! `MOCK` is a C type backed by a Rust allocation, with functions following common C conventions.
+//! Downstream crates can use it in place of a real library to unit test their `foreign_type!`
+//! declarations and the methods built on them, without linking anything:
+//!
+//! ```
+//! use foreign_types::{foreign_type, ForeignType, ForeignTypeRef};
+//! use foreign_types::mock::{self, MOCK};
+//!
+//! foreign_type! {
+//!     /// A mock value.
+//!     pub unsafe type Foo {
+//!         type CType = MOCK;
+//!         fn drop = mock::mock_free;
+//!         fn clone = mock::mock_dup;
+//!         fn get child: FooRef = mock::mock_get0_child;
+//!         fn set child(takes): Foo = mock::mock_set0_child;
+//!     }
+//! }
+//!
+//! impl FooRef {
+//!     pub fn value(&self) -> i32 {
+//!         unsafe { mock::mock_get_value(self.as_ptr()) }
+//!     }
+//! }
+//!
+//! let mut foo = unsafe { Foo::from_ptr(mock::mock_new_with(1)) };
+//! foo.set_child(unsafe { Foo::from_ptr(mock::mock_new_with(2)) });
+//! let copy = foo.clone();
+//! assert_eq!(copy.value(), 1);
+//! assert_eq!(copy.child().value(), 2);
+//! assert_eq!(mock::live(), 4);
+//!
+//! drop(foo);
+//! drop(copy);
+//! assert_eq!(mock::live(), 0);
+//! ```
+//!
+//! Freed values are poisoned rather than deallocated, so that the functions can detect being
+//! passed a dangling pointer, and panic. Freeing a value twice, or passing a freed value to
+//! `mock_dup` or the accessors, therefore fails the test instead of corrupting memory.
+//!
+//! The counters are per thread, so tests using them can run concurrently as long as each test
+//! frees its values on the thread which allocated them.
+
+use std::boxed::Box;
+use std::cell::Cell;
+use std::os::raw::c_int;
+use std::ptr;
+use std::thread_local;
+
+const MAGIC: u32 = 0x4d4f_434b;
+const POISON: u32 = 0xdead_beef;
+
+/// A fake C type.
+#[allow(clippy::upper_case_acronyms)]
+#[repr(C)]
+pub struct MOCK {
+    magic: u32,
+    value: c_int,
+    child: *mut MOCK,
+}
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    static FREED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Returns the number of values allocated on this thread.
+pub fn allocated() -> usize {
+    ALLOCATED.with(Cell::get)
+}
+
+/// Returns the number of values freed on this thread.
+pub fn freed() -> usize {
+    FREED.with(Cell::get)
+}
+
+/// Returns the number of values allocated on this thread which have not been freed.
+pub fn live() -> usize {
+    allocated() - freed()
+}
+
+unsafe fn check(ptr: *mut MOCK, function: &str) -> *mut MOCK {
+    assert!(!ptr.is_null(), "{} called with a null pointer", function);
+    match (*ptr).magic {
+        MAGIC => ptr,
+        POISON => panic!("{} called with a freed MOCK", function),
+        _ => panic!("{} called with an invalid MOCK", function),
+    }
+}
+
+/// Allocates a new value, initialized to zero.
+///
+/// # Safety
+///
+/// This is unsafe to match the C functions it stands in for.
+pub unsafe fn mock_new() -> *mut MOCK {
+    mock_new_with(0)
+}
+
+/// Allocates a new value, initialized to `value`.
+///
+/// # Safety
+///
+/// This is unsafe to match the C functions it stands in for.
+pub unsafe fn mock_new_with(value: c_int) -> *mut MOCK {
+    ALLOCATED.with(|c| c.set(c.get() + 1));
+    Box::into_raw(Box::new(MOCK {
+        magic: MAGIC,
+        value,
+        child: ptr::null_mut(),
+    }))
+}
+
+/// Frees a value, along with its child.
+///
+/// # Safety
+///
+/// `ptr` must have been returned by `mock_new`, `mock_new_with` or `mock_dup`.
+///
+/// # Panics
+///
+/// Panics if `ptr` has already been freed.
+pub unsafe fn mock_free(ptr: *mut MOCK) {
+    let ptr = check(ptr, "mock_free");
+    if !(*ptr).child.is_null() {
+        mock_free((*ptr).child);
+    }
+    (*ptr).magic = POISON;
+    FREED.with(|c| c.set(c.get() + 1));
+}
+
+/// Allocates a deep copy of a value.
+///
+/// # Safety
+///
+/// `ptr` must have been returned by `mock_new`, `mock_new_with` or `mock_dup`.
+///
+/// # Panics
+///
+/// Panics if `ptr` has been freed.
+pub unsafe fn mock_dup(ptr: *mut MOCK) -> *mut MOCK {
+    let ptr = check(ptr, "mock_dup");
+    let dup = mock_new_with((*ptr).value);
+    if !(*ptr).child.is_null() {
+        (*dup).child = mock_dup((*ptr).child);
+    }
+    dup
+}
+
+/// Returns the integer stored in a value.
+///
+/// # Safety
+///
+/// `ptr` must have been returned by `mock_new`, `mock_new_with` or `mock_dup`.
+///
+/// # Panics
+///
+/// Panics if `ptr` has been freed.
+pub unsafe fn mock_get_value(ptr: *mut MOCK) -> c_int {
+    (*check(ptr, "mock_get_value")).value
+}
+
+/// Sets the integer stored in a value.
+///
+/// # Safety
+///
+/// `ptr` must have been returned by `mock_new`, `mock_new_with` or `mock_dup`.
+///
+/// # Panics
+///
+/// Panics if `ptr` has been freed.
+pub unsafe fn mock_set_value(ptr: *mut MOCK, value: c_int) {
+    (*check(ptr, "mock_set_value")).value = value;
+}
+
+/// Returns a value's child, or null if it has none.
+///
+/// The child remains owned by the value.
+///
+/// # Safety
+///
+/// `ptr` must have been returned by `mock_new`, `mock_new_with` or `mock_dup`.
+///
+/// # Panics
+///
+/// Panics if `ptr` has been freed.
+pub unsafe fn mock_get0_child(ptr: *mut MOCK) -> *mut MOCK {
+    (*check(ptr, "mock_get0_child")).child
+}
+
+/// Sets a value's child, taking ownership of it and freeing the previous child.
+///
+/// # Safety
+///
+/// `ptr` and `child` must have been returned by `mock_new`, `mock_new_with` or `mock_dup`, and
+/// `child` must not be owned by anything else.
+///
+/// # Panics
+///
+/// Panics if `ptr` or `child` has been freed.
+pub unsafe fn mock_set0_child(ptr: *mut MOCK, child: *mut MOCK) {
+    let ptr = check(ptr, "mock_set0_child");
+    let child = check(child, "mock_set0_child");
+    if !(*ptr).child.is_null() {
+        mock_free((*ptr).child);
+    }
+    (*ptr).child = child;
+}
+
+/// Sets a value's child to a copy of `child`, freeing the previous child.
+///
+/// # Safety
+///
+/// `ptr` and `child` must have been returned by `mock_new`, `mock_new_with` or `mock_dup`.
+///
+/// # Panics
+///
+/// Panics if `ptr` or `child` has been freed.
+pub unsafe fn mock_set1_child(ptr: *mut MOCK, child: *mut MOCK) {
+    let child = mock_dup(check(child, "mock_set1_child"));
+    mock_set0_child(ptr, child);
+}
@@ -0,0 +1,55 @@
+//! Containment of panics at FFI boundaries.
+//!
+//! Unwinding out of a Rust function called by C is undefined behavior, or aborts the process
+//! without any indication of the cause. Callbacks passed to C should therefore run their body in
+//! `abort_on_panic`, which reports the panic and aborts cleanly instead.
+//!
+//! ```
+//! use foreign_types::panic::abort_on_panic;
+//! use std::os::raw::c_void;
+//!
+//! unsafe extern "C" fn callback(data: *mut c_void) {
+//!     abort_on_panic("callback", || {
+//!         let count = &mut *(data as *mut usize);
+//!         *count += 1;
+//!     })
+//! }
+//! ```
+//!
+//! The closures passed to `map::ForeignMap::for_each` are always run this way. If the
+//! `abort-on-panic` Cargo feature is enabled, so are the destructors and clone functions called by
+//! types generated by `foreign_type!`.
+
+use std::any::Any;
+use std::boxed::Box;
+use std::eprintln;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::string::String;
+
+/// Calls `f`, aborting the process if it panics.
+///
+/// `context` describes the call in the message printed before aborting.
+#[inline]
+pub fn abort_on_panic<F, R>(context: &str, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(payload) => abort(context, payload),
+    }
+}
+
+#[cold]
+fn abort(context: &str, payload: Box<dyn Any + Send>) -> ! {
+    let message = match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message,
+            None => "Box<dyn Any>",
+        },
+    };
+    eprintln!("fatal: panic in {}: {}; aborting", context, message);
+    process::abort();
+}
@@ -0,0 +1,113 @@
+//! Consuming iteration over C containers.
+//!
+//! Container types such as OpenSSL's stacks commonly provide a function which removes an element
+//! and transfers its ownership to the caller, returning null once the container is empty. Types
+//! declared with `fn pop: Element = ...;` implement `IntoIterator` by calling it repeatedly, yielding
+//! owned elements:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!     pub enum STACK_OF_FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!         pub fn sk_FOO_free(stack: *mut STACK_OF_FOO);
+//!         pub fn sk_FOO_pop(stack: *mut STACK_OF_FOO) -> *mut FOO;
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//!
+//!     /// A stack of Foos.
+//!     pub unsafe type FooStack {
+//!         type CType = foo_sys::STACK_OF_FOO;
+//!         fn drop = foo_sys::sk_FOO_free;
+//!         fn pop: Foo = foo_sys::sk_FOO_pop;
+//!     }
+//! }
+//!
+//! fn into_vec(stack: FooStack) -> Vec<Foo> {
+//!     stack.into_iter().collect()
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! The container itself is dropped along with the iterator, freeing it along with any elements
+//! which have not been popped.
+
+use core::fmt;
+use core::iter::FusedIterator;
+
+use crate::ForeignType;
+
+/// An iterator which pops the owned elements of type `T` out of a container of type `C`.
+pub struct IntoIter<C: ForeignType, T: ForeignType> {
+    container: C,
+    pop: unsafe fn(*mut C::CType) -> *mut T::CType,
+    done: bool,
+}
+
+impl<C: ForeignType, T: ForeignType> IntoIter<C, T> {
+    /// Creates an iterator popping elements out of `container` with `pop`.
+    ///
+    /// # Safety
+    ///
+    /// `pop` must remove an element from the container and return it as an owned instance of the
+    /// native type, or return null if the container is empty.
+    #[inline]
+    pub unsafe fn new(container: C, pop: unsafe fn(*mut C::CType) -> *mut T::CType) -> Self {
+        IntoIter {
+            container,
+            pop,
+            done: false,
+        }
+    }
+
+    /// Returns a reference to the container holding the remaining elements.
+    #[inline]
+    pub fn container(&self) -> &C {
+        &self.container
+    }
+
+    /// Stops iterating, returning the container holding the remaining elements.
+    #[inline]
+    pub fn into_container(self) -> C {
+        self.container
+    }
+}
+
+impl<C: ForeignType, T: ForeignType> Iterator for IntoIter<C, T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        if self.done {
+            return None;
+        }
+        let element = unsafe { T::from_ptr_opt((self.pop)(self.container.as_ptr())) };
+        self.done = element.is_none();
+        element
+    }
+}
+
+impl<C: ForeignType, T: ForeignType> FusedIterator for IntoIter<C, T> {}
+
+impl<C, T> fmt::Debug for IntoIter<C, T>
+where
+    C: ForeignType + fmt::Debug,
+    T: ForeignType,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("IntoIter")
+            .field("container", &self.container)
+            .finish()
+    }
+}
@@ -0,0 +1,91 @@
+//! Support for `const PY_CAPSULE_NAME`.
+//!
+//! The capsules store the raw pointer to the native value under a nul-terminated name, so that C
+//! code and other extension modules can read it with `PyCapsule_GetPointer`.
+
+use std::ffi::{c_void, CStr};
+use std::format;
+use std::ptr;
+
+use pyo3::exceptions::PyTypeError;
+use pyo3::types::{PyAnyMethods, PyCapsule, PyCapsuleMethods};
+use pyo3::{ffi, Bound, PyErr, PyResult, Python};
+
+fn c_name(name: &'static [u8]) -> &'static CStr {
+    CStr::from_bytes_with_nul(name).expect("capsule name is not nul-terminated")
+}
+
+/// Creates a capsule holding `ptr`, which is passed to `destructor` when the capsule is destroyed.
+///
+/// On failure, `ptr` is not passed to `destructor`, and remains owned by the caller.
+///
+/// # Safety
+///
+/// `destructor` must free `ptr` when passed the capsule.
+pub unsafe fn py_capsule_new<'py>(
+    py: Python<'py>,
+    ptr: *mut c_void,
+    name: &'static [u8],
+    destructor: ffi::PyCapsule_Destructor,
+) -> PyResult<Bound<'py, PyCapsule>> {
+    let capsule = ffi::PyCapsule_New(ptr, c_name(name).as_ptr(), Some(destructor));
+    let capsule = Bound::from_owned_ptr_or_err(py, capsule)?;
+    Ok(capsule.downcast_into_unchecked())
+}
+
+/// Returns the pointer held by a capsule, after checking that it has the expected name.
+pub fn py_capsule_pointer(
+    capsule: &Bound<'_, PyCapsule>,
+    name: &'static [u8],
+) -> PyResult<*mut c_void> {
+    let name = c_name(name);
+    match capsule.name()? {
+        Some(actual) if actual == name => {}
+        _ => {
+            return Err(PyTypeError::new_err(format!(
+                "expected a capsule named `{}`",
+                name.to_string_lossy()
+            )))
+        }
+    }
+    let ptr = capsule.pointer();
+    if ptr.is_null() {
+        return Err(PyTypeError::new_err("capsule holds a null pointer"));
+    }
+    Ok(ptr)
+}
+
+/// Takes the pointer out of a capsule, after checking that it has the expected name.
+///
+/// The capsule's destructor and name are cleared, so that it no longer frees the value and later
+/// lookups by name fail.
+pub fn py_capsule_take(
+    capsule: &Bound<'_, PyCapsule>,
+    name: &'static [u8],
+) -> PyResult<*mut c_void> {
+    let ptr = py_capsule_pointer(capsule, name)?;
+    unsafe {
+        if ffi::PyCapsule_SetDestructor(capsule.as_ptr(), None) != 0
+            || ffi::PyCapsule_SetName(capsule.as_ptr(), ptr::null()) != 0
+        {
+            return Err(PyErr::fetch(capsule.py()));
+        }
+    }
+    Ok(ptr)
+}
+
+/// Returns the pointer held by a capsule being destroyed, or null if it has none.
+///
+/// # Safety
+///
+/// `capsule` must be a valid capsule.
+pub unsafe fn py_capsule_destructor_pointer(
+    capsule: *mut ffi::PyObject,
+    name: &'static [u8],
+) -> *mut c_void {
+    let ptr = ffi::PyCapsule_GetPointer(capsule, c_name(name).as_ptr());
+    if ptr.is_null() {
+        ffi::PyErr_Clear();
+    }
+    ptr
+}
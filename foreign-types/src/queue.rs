@@ -0,0 +1,172 @@
+//! Deferred destruction of foreign values.
+//!
+//! Some C objects must be freed on a particular thread, such as the main thread of a GUI
+//! application, or have destructors which may block. Types declared with `static DROP_QUEUE = ...;`
+//! do not call their destructor when dropped, but push it onto a `DropQueue`, which the application
+//! drains at a suitable time on a suitable thread.
+//!
+//! Pushing onto the queue never blocks.
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//! use foreign_types::queue::DropQueue;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_free(foo: *mut FOO);
+//!     }
+//! }
+//!
+//! static MAIN_THREAD_QUEUE: DropQueue = DropQueue::new();
+//!
+//! foreign_type! {
+//!     /// A Foo which must be freed on the main thread.
+//!     pub unsafe type Foo: Send {
+//!         type CType = foo_sys::FOO;
+//!         static DROP_QUEUE = MAIN_THREAD_QUEUE;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//! }
+//!
+//! // In the main thread's event loop:
+//! MAIN_THREAD_QUEUE.drain();
+//! ```
+
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use std::boxed::Box;
+
+struct Node {
+    ptr: *mut (),
+    drop: unsafe fn(*mut ()),
+    next: *mut Node,
+}
+
+/// A queue of pending destructor calls.
+///
+/// Destructors still pending when the queue itself is dropped are never run, so the values they
+/// would have freed are leaked.
+pub struct DropQueue {
+    head: AtomicPtr<Node>,
+}
+
+// The queue only stores pointers which are passed to their destructors by `drain`, and callers of
+// `push` guarantee that this may happen on whichever thread drains the queue.
+unsafe impl Send for DropQueue {}
+unsafe impl Sync for DropQueue {}
+
+impl DropQueue {
+    /// Creates a new, empty queue.
+    pub const fn new() -> DropQueue {
+        DropQueue {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Schedules `drop` to be called with `ptr` when the queue is next drained.
+    ///
+    /// # Safety
+    ///
+    /// It must be safe to call `drop` with `ptr` on any thread which drains the queue.
+    pub unsafe fn push(&self, ptr: *mut (), drop: unsafe fn(*mut ())) {
+        let node = Box::into_raw(Box::new(Node {
+            ptr,
+            drop,
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            (*node).next = head;
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(new) => head = new,
+            }
+        }
+    }
+
+    /// Runs all pending destructors in the order they were pushed, returning the number run.
+    ///
+    /// Destructors pushed while the queue is being drained are run by the next call.
+    ///
+    /// If a destructor panics, the destructors after it are still run while the panic unwinds, so
+    /// that their values are not leaked. A second panic among them aborts the process.
+    pub fn drain(&self) -> usize {
+        let mut head = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+
+        // The list is in reverse order of pushing.
+        let mut reversed = ptr::null_mut();
+        while !head.is_null() {
+            unsafe {
+                let next = (*head).next;
+                (*head).next = reversed;
+                reversed = head;
+                head = next;
+            }
+        }
+
+        let mut drain = Drain {
+            next: reversed,
+            count: 0,
+        };
+        drain.run();
+        drain.count
+    }
+    /// Returns `true` if no destructors are pending.
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+}
+
+// Runs the destructors of a detached list, continuing from where it stopped if dropped during a
+// panic.
+struct Drain {
+    next: *mut Node,
+    count: usize,
+}
+
+impl Drain {
+    fn run(&mut self) {
+        while !self.next.is_null() {
+            let node = unsafe { Box::from_raw(self.next) };
+            self.next = node.next;
+            self.count += 1;
+            unsafe { (node.drop)(node.ptr) };
+        }
+    }
+}
+
+impl Drop for Drain {
+    fn drop(&mut self) {
+        self.run();
+    }
+}
+
+impl Default for DropQueue {
+    fn default() -> DropQueue {
+        DropQueue::new()
+    }
+}
+
+impl Drop for DropQueue {
+    fn drop(&mut self) {
+        let mut head = *self.head.get_mut();
+        while !head.is_null() {
+            let node = unsafe { Box::from_raw(head) };
+            head = node.next;
+        }
+    }
+}
+
+impl fmt::Debug for DropQueue {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("DropQueue")
+            .field("empty", &self.is_empty())
+            .finish()
+    }
+}
@@ -0,0 +1,99 @@
+//! Checked conversions from raw pointers.
+//!
+//! A raw pointer returned by a C function carries no indication of whether the caller owns it, so
+//! converting it into an owned type can't be done through a safe trait like `TryFrom`. `OwnedPtr`
+//! is a witness that a pointer is owned, which is created unsafely where that is known and can then
+//! be converted with `TryFrom`, failing if the pointer is null. Types generated by `foreign_type!`
+//! implement `TryFrom<OwnedPtr<CType>>`, along with `From<Self>` for `NonNull<CType>`:
+//!
+//! ```
+//! use foreign_types::foreign_type;
+//! use foreign_types::raw::{NullPtrError, OwnedPtr};
+//! use std::convert::TryFrom;
+//!
+//! mod foo_sys {
+//!     pub enum FOO {}
+//!
+//!     extern {
+//!         pub fn FOO_new() -> *mut FOO;
+//!         pub fn FOO_free(foo: *mut FOO);
+//!     }
+//! }
+//!
+//! foreign_type! {
+//!     /// A Foo.
+//!     pub unsafe type Foo {
+//!         type CType = foo_sys::FOO;
+//!         fn drop = foo_sys::FOO_free;
+//!     }
+//! }
+//!
+//! impl Foo {
+//!     pub fn new() -> Result<Foo, NullPtrError> {
+//!         let foo = Foo::try_from(unsafe { OwnedPtr::new(foo_sys::FOO_new()) })?;
+//!         Ok(foo)
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! Since `OwnedPtr` is `#[repr(transparent)]`, it can also be used directly as the return type of
+//! declarations of C functions which are known to transfer ownership to the caller.
+
+use core::fmt;
+
+/// A raw pointer which is either null or an owned instance of the native type `T`.
+///
+/// Dropping an `OwnedPtr` leaks the value it points to.
+#[repr(transparent)]
+pub struct OwnedPtr<T>(*mut T);
+
+impl<T> OwnedPtr<T> {
+    /// Wraps a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must either be null or a valid, owned instance of the native type.
+    #[inline]
+    pub unsafe fn new(ptr: *mut T) -> OwnedPtr<T> {
+        OwnedPtr(ptr)
+    }
+
+    /// Returns the raw pointer.
+    #[inline]
+    pub fn as_ptr(&self) -> *mut T {
+        self.0
+    }
+
+    /// Returns `true` if the pointer is null.
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        self.0.is_null()
+    }
+
+    /// Consumes the wrapper and returns the raw pointer.
+    #[inline]
+    #[must_use = "ignoring the pointer leaks the value"]
+    pub fn into_ptr(self) -> *mut T {
+        self.0
+    }
+}
+
+impl<T> fmt::Debug for OwnedPtr<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("OwnedPtr").field(&self.0).finish()
+    }
+}
+
+/// The error returned when converting a null `OwnedPtr` into an owned type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NullPtrError;
+
+impl fmt::Display for NullPtrError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("unexpected null pointer")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for NullPtrError {}
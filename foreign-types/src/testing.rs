@@ -0,0 +1,93 @@
+//! Leak checking utilities used by the `testing` Cargo feature.
+//!
+//! Every type generated by `foreign_type!` gains a `live_count` associated function returning the
+//! number of its owned instances which are currently alive. Instances are counted when created
+//! with `ForeignType::from_ptr` (including through `Clone`), and uncounted when dropped or
+//! released with `ForeignType::into_ptr`. Instances created with the `const` `from_ptr_const`
+//! constructor are not counted.
+//!
+//! The counters are global, so tests using them must not run concurrently with other tests
+//! creating instances of the same types.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A counter of live owned instances of a foreign type.
+pub struct LiveCounter(AtomicUsize);
+
+impl LiveCounter {
+    /// Creates a new counter with no live instances.
+    pub const fn new() -> LiveCounter {
+        LiveCounter(AtomicUsize::new(0))
+    }
+
+    /// Returns the number of live instances.
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Records the creation of an instance.
+    pub fn increment(&self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Records the destruction of an instance.
+    ///
+    /// The count never goes below zero. Decrementing a count of zero means that an instance was
+    /// destroyed without having been counted, and panics in debug builds.
+    pub fn decrement(&self) {
+        let result = self
+            .0
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                count.checked_sub(1)
+            });
+        debug_assert!(result.is_ok(), "live count decremented below zero");
+    }
+}
+
+impl Default for LiveCounter {
+    fn default() -> LiveCounter {
+        LiveCounter::new()
+    }
+}
+
+/// Runs a block and asserts that it did not leak any instances of the listed types.
+///
+/// The number of live instances of each type is recorded before the block runs and compared
+/// afterwards. The value of the block is returned.
+///
+/// # Examples
+///
+/// ```
+/// use foreign_types::{assert_no_leaks, foreign_type, ForeignType};
+///
+/// # mod foo_sys { pub enum FOO {} pub unsafe fn FOO_new() -> *mut FOO { 1 as *mut FOO } pub unsafe fn FOO_free(_: *mut FOO) {} }
+/// foreign_type! {
+///     pub unsafe type Foo {
+///         type CType = foo_sys::FOO;
+///         fn drop = foo_sys::FOO_free;
+///     }
+/// }
+///
+/// assert_no_leaks!(Foo => {
+///     let foo = unsafe { Foo::from_ptr(foo_sys::FOO_new()) };
+///     drop(foo);
+/// });
+/// ```
+#[macro_export]
+macro_rules! assert_no_leaks {
+    ($($t:ty),+ $(,)? => $body:block) => {{
+        let names = [$(stringify!($t)),+];
+        let before = [$(<$t>::live_count()),+];
+        let value = $body;
+        let after = [$(<$t>::live_count()),+];
+        for ((name, before), after) in names.iter().zip(before.iter()).zip(after.iter()) {
+            assert!(
+                after <= before,
+                "leaked {} instance(s) of {}",
+                after - before,
+                name,
+            );
+        }
+        value
+    }};
+}
@@ -0,0 +1,246 @@
+//! Wrappers controlling how values are shared between threads.
+//!
+//! Some C objects, such as UI handles, may only be accessed from the thread that created them.
+//! `ThreadBound` allows such values to be moved between threads while checking on every access
+//! that it takes place on the owning thread.
+//!
+//! Other C objects may be used from any thread, but not concurrently. Rather than declaring such
+//! types `Sync`, they can be wrapped in a `SyncForeign`, which serializes access through a mutex.
+
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use std::eprintln;
+use std::sync::{Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
+
+use crate::{ForeignType, ForeignTypeRef};
+
+/// A value of the foreign type `T` which may only be accessed from the thread that created it.
+///
+/// `ThreadBound` is `Send` regardless of `T`, but dereferencing it on any other thread panics. If
+/// it is dropped on another thread, the value is leaked and a warning is printed, since its
+/// destructor cannot safely run there.
+pub struct ThreadBound<T: ForeignType> {
+    value: ManuallyDrop<T>,
+    thread: ThreadId,
+}
+
+// The value is only ever accessed or dropped on the owning thread.
+unsafe impl<T: ForeignType> Send for ThreadBound<T> {}
+
+impl<T: ForeignType> ThreadBound<T> {
+    /// Binds `value` to the current thread.
+    #[inline]
+    pub fn new(value: T) -> ThreadBound<T> {
+        ThreadBound {
+            value: ManuallyDrop::new(value),
+            thread: thread::current().id(),
+        }
+    }
+
+    /// Returns the ID of the thread which owns the value.
+    #[inline]
+    pub fn thread_id(&self) -> ThreadId {
+        self.thread
+    }
+
+    /// Returns `true` if the current thread owns the value.
+    #[inline]
+    pub fn is_owning_thread(&self) -> bool {
+        thread::current().id() == self.thread
+    }
+
+    /// Returns a reference to the value, or `None` if called from another thread.
+    #[inline]
+    pub fn get(&self) -> Option<&T> {
+        if self.is_owning_thread() {
+            Some(&self.value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the value, or `None` if called from another thread.
+    #[inline]
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.is_owning_thread() {
+            Some(&mut self.value)
+        } else {
+            None
+        }
+    }
+
+    /// Unwraps the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from another thread.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.check();
+        let mut this = ManuallyDrop::new(self);
+        unsafe { ManuallyDrop::take(&mut this.value) }
+    }
+
+    fn check(&self) {
+        if !self.is_owning_thread() {
+            panic!(
+                "value owned by thread {:?} accessed from thread {:?}",
+                self.thread,
+                thread::current().id(),
+            );
+        }
+    }
+}
+
+impl<T: ForeignType> Deref for ThreadBound<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.check();
+        &self.value
+    }
+}
+
+impl<T: ForeignType> DerefMut for ThreadBound<T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.check();
+        &mut self.value
+    }
+}
+
+impl<T: ForeignType> Drop for ThreadBound<T> {
+    fn drop(&mut self) {
+        if self.is_owning_thread() {
+            unsafe { ManuallyDrop::drop(&mut self.value) }
+        } else {
+            eprintln!(
+                "warning: leaking a value owned by thread {:?} which was dropped on thread {:?}",
+                self.thread,
+                thread::current().id(),
+            );
+        }
+    }
+}
+
+impl<T: ForeignType> fmt::Debug for ThreadBound<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ThreadBound")
+            .field("thread", &self.thread)
+            .finish()
+    }
+}
+
+/// A value of the foreign type `T` which is shared between threads behind a mutex.
+///
+/// `SyncForeign` is `Send` and `Sync` regardless of `T`. The value is only accessible through a
+/// `SyncForeignGuard` or a closure passed to `with`, so it is never accessed by more than one thread
+/// at a time.
+pub struct SyncForeign<T: ForeignType> {
+    value: Mutex<T>,
+}
+
+// Access to the value is serialized by the mutex, and constructing a `SyncForeign` from a value
+// which is not `Send` is unsafe.
+unsafe impl<T: ForeignType> Send for SyncForeign<T> {}
+unsafe impl<T: ForeignType> Sync for SyncForeign<T> {}
+
+impl<T: ForeignType> SyncForeign<T> {
+    /// Wraps a value which may be sent between threads.
+    #[inline]
+    pub fn new(value: T) -> SyncForeign<T>
+    where
+        T: Send,
+    {
+        unsafe { SyncForeign::new_unchecked(value) }
+    }
+
+    /// Wraps a value which is not `Send`.
+    ///
+    /// # Safety
+    ///
+    /// It must be safe to use and drop the value from any thread, as long as it is not accessed by
+    /// more than one thread at a time. In particular, the C object must not rely on thread-local
+    /// state.
+    #[inline]
+    pub unsafe fn new_unchecked(value: T) -> SyncForeign<T> {
+        SyncForeign {
+            value: Mutex::new(value),
+        }
+    }
+
+    /// Locks the mutex, blocking until it is available.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while holding the lock, since the C object may have been
+    /// left in an inconsistent state.
+    #[inline]
+    pub fn lock(&self) -> SyncForeignGuard<'_, T> {
+        SyncForeignGuard(self.value.lock().expect("SyncForeign mutex poisoned"))
+    }
+
+    /// Calls `f` with exclusive access to the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `lock`.
+    #[inline]
+    pub fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T::Ref) -> R,
+    {
+        f(&mut self.lock())
+    }
+
+    /// Returns a mutable reference to the value without locking.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `lock`.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T::Ref {
+        let value = self.value.get_mut().expect("SyncForeign mutex poisoned");
+        unsafe { T::Ref::from_ptr_mut(value.as_ptr()) }
+    }
+
+    /// Unwraps the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `lock`.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.value.into_inner().expect("SyncForeign mutex poisoned")
+    }
+}
+
+impl<T: ForeignType> fmt::Debug for SyncForeign<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("SyncForeign").finish()
+    }
+}
+
+/// A guard providing exclusive access to the value in a `SyncForeign`.
+///
+/// The lock is released when the guard is dropped.
+pub struct SyncForeignGuard<'a, T: ForeignType>(MutexGuard<'a, T>);
+
+impl<'a, T: ForeignType> Deref for SyncForeignGuard<'a, T> {
+    type Target = T::Ref;
+
+    #[inline]
+    fn deref(&self) -> &T::Ref {
+        unsafe { T::Ref::from_ptr(self.0.as_ptr()) }
+    }
+}
+
+impl<'a, T: ForeignType> DerefMut for SyncForeignGuard<'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T::Ref {
+        unsafe { T::Ref::from_ptr_mut(self.0.as_ptr()) }
+    }
+}
@@ -0,0 +1,89 @@
+//! Instrumentation used by the `trace` Cargo feature.
+//!
+//! When the feature is enabled, types generated by `foreign_type!` report an `Event` whenever an
+//! owned instance is created with `ForeignType::from_ptr`, cloned, dropped or closed. Events are
+//! passed to a global hook, which is typically used to forward them to a logging framework:
+//!
+//! ```ignore
+//! foreign_types::trace::set_hook(|event| log::trace!("{}", event));
+//! ```
+//!
+//! No events are reported until a hook has been set.
+
+use core::fmt;
+
+use crate::atomic_fn::AtomicFn;
+
+static HOOK: AtomicFn<fn(&Event)> = AtomicFn::new();
+
+/// The kind of an `Event`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// An owned instance was created from a raw pointer.
+    FromPtr,
+    /// An owned instance was cloned, producing the instance at `Event::new_ptr`.
+    Clone,
+    /// An owned instance was dropped, or closed with its `close` method.
+    Drop,
+    /// An owned instance of a type with `fn close` was dropped without being closed, and was freed
+    /// synchronously.
+    DropUnclosed,
+}
+
+/// An operation on an owned instance of a foreign type.
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    /// The name of the Rust type.
+    pub type_name: &'static str,
+    /// The kind of operation.
+    pub kind: EventKind,
+    /// The pointer to the instance.
+    pub ptr: *const (),
+    /// The pointer to the new instance for `EventKind::Clone` events, and null otherwise.
+    pub new_ptr: *const (),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            EventKind::FromPtr => write!(fmt, "{}::from_ptr({:p})", self.type_name, self.ptr),
+            EventKind::Clone => write!(
+                fmt,
+                "{}::clone({:p}) -> {:p}",
+                self.type_name, self.ptr, self.new_ptr
+            ),
+            EventKind::Drop => write!(fmt, "{}::drop({:p})", self.type_name, self.ptr),
+            EventKind::DropUnclosed => {
+                write!(
+                    fmt,
+                    "{}::drop({:p}) without close",
+                    self.type_name, self.ptr
+                )
+            }
+        }
+    }
+}
+
+/// Sets the function called with every event.
+pub fn set_hook(hook: fn(&Event)) {
+    HOOK.store(Some(hook));
+}
+
+/// Removes the hook, if any.
+pub fn clear_hook() {
+    HOOK.store(None);
+}
+
+#[doc(hidden)]
+pub fn emit<T>(type_name: &'static str, kind: EventKind, ptr: *mut T, new_ptr: *mut T) {
+    let hook = match HOOK.load() {
+        Some(hook) => hook,
+        None => return,
+    };
+    hook(&Event {
+        type_name,
+        kind,
+        ptr: ptr.cast::<()>(),
+        new_ptr: new_ptr.cast::<()>(),
+    });
+}
@@ -0,0 +1,114 @@
+//! Ownership tracking used by the `track-ownership` Cargo feature.
+//!
+//! Every owned pointer created by a type generated with `foreign_type!` is recorded in a global
+//! table along with the number of live owned wrappers referring to it. Wrapping a pointer which is
+//! already owned, freeing a pointer which is not owned, and borrowing a pointer which has been
+//! freed all panic with a diagnostic naming the offending type.
+//!
+//! Types with a `fn clone` may be reference counted, in which case several owned wrappers can
+//! legitimately share a pointer. The duplicate ownership check is skipped for those types.
+//!
+//! Addresses may be reused by the C allocator, so a pointer is removed from the freed set as soon
+//! as it is wrapped as owned again.
+
+use std::boxed::Box;
+use std::collections::{HashMap, HashSet};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Mutex, Once};
+
+use foreign_types_shared::ptr_addr;
+
+struct Table {
+    live: HashMap<usize, usize>,
+    freed: HashSet<usize>,
+}
+
+static TABLE: AtomicPtr<Mutex<Table>> = AtomicPtr::new(ptr::null_mut());
+static INIT: Once = Once::new();
+
+fn with_table<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Table) -> R,
+{
+    INIT.call_once(|| {
+        let table = Box::new(Mutex::new(Table {
+            live: HashMap::new(),
+            freed: HashSet::new(),
+        }));
+        TABLE.store(Box::into_raw(table), Ordering::Release);
+    });
+    // The table is initialized exactly once above and never freed.
+    let table = unsafe { &*TABLE.load(Ordering::Acquire) };
+    let mut guard = match table.lock() {
+        Ok(guard) => guard,
+        Err(e) => e.into_inner(),
+    };
+    f(&mut guard)
+}
+
+/// Records that `ptr` is now uniquely owned by a wrapper of type `name`.
+pub fn acquire<T>(ptr: *mut T, name: &str) {
+    let addr = ptr_addr(ptr);
+    let duplicate = with_table(|table| {
+        table.freed.remove(&addr);
+        let count = table.live.entry(addr).or_insert(0);
+        *count += 1;
+        *count > 1
+    });
+    if duplicate {
+        panic!("{} at {:p} is already owned by another wrapper", name, ptr);
+    }
+}
+
+/// Records that `ptr` has gained an additional owned wrapper of type `name`.
+///
+/// Unlike `acquire`, this tolerates the pointer already being owned.
+pub fn share<T>(ptr: *mut T, _name: &str) {
+    let addr = ptr_addr(ptr);
+    with_table(|table| {
+        table.freed.remove(&addr);
+        *table.live.entry(addr).or_insert(0) += 1;
+    });
+}
+
+/// Records that an owned wrapper of type `name` has released `ptr` without freeing it.
+pub fn forget<T>(ptr: *mut T, name: &str) {
+    if !release_inner(ptr_addr(ptr), false) {
+        panic!("{} at {:p} was released but is not owned", name, ptr);
+    }
+}
+
+/// Records that an owned wrapper of type `name` is about to free `ptr`.
+pub fn release<T>(ptr: *mut T, name: &str) {
+    if !release_inner(ptr_addr(ptr), true) {
+        panic!(
+            "{} at {:p} was freed but is not owned (double free?)",
+            name, ptr
+        );
+    }
+}
+
+fn release_inner(addr: usize, free: bool) -> bool {
+    with_table(|table| match table.live.get_mut(&addr) {
+        Some(count) => {
+            *count -= 1;
+            if *count == 0 {
+                table.live.remove(&addr);
+                if free {
+                    table.freed.insert(addr);
+                }
+            }
+            true
+        }
+        None => false,
+    })
+}
+
+/// Checks that `ptr` has not been freed before it is borrowed as a reference of type `name`.
+pub fn check<T>(ptr: *mut T, name: &str) {
+    let freed = with_table(|table| table.freed.contains(&(ptr_addr(ptr))));
+    if freed {
+        panic!("{} at {:p} was used after being freed", name, ptr);
+    }
+}
@@ -0,0 +1,44 @@
+use foreign_types::{foreign_type_decl, ForeignType, ForeignTypeRef};
+
+#[allow(clippy::upper_case_acronyms)]
+mod foo_sys {
+    pub enum FOO {}
+
+    pub unsafe extern "C" fn foo_drop(_: *mut FOO) {}
+    pub unsafe extern "C" fn foo_clone(ptr: *mut FOO) -> *mut FOO {
+        ptr
+    }
+}
+
+foreign_type_decl! {
+    /// A Foo.
+    pub unsafe type Foo: Sync + Send {
+        type CType = foo_sys::FOO;
+        type Ref = FooRef;
+        fn drop = foo_sys::foo_drop;
+        fn clone = foo_sys::foo_clone;
+    }
+
+    pub unsafe type FooNoClone {
+        type CType = foo_sys::FOO;
+        type Ref = FooNoCloneRef;
+        fn drop = foo_sys::foo_drop;
+    }
+
+    pub unsafe type FooClosure {
+        type CType = foo_sys::FOO;
+        type Ref = FooClosureRef;
+        fn drop = |p| foo_sys::foo_drop(p);
+        fn clone = |p| foo_sys::foo_clone(p);
+    }
+}
+
+#[test]
+fn round_trip() {
+    let ptr = core::ptr::NonNull::<foo_sys::FOO>::dangling().as_ptr();
+    let foo = unsafe { Foo::from_ptr(ptr) };
+    let clone = foo.clone();
+    let foo_ref: &FooRef = &clone;
+    assert_eq!(foo_ref.as_ptr(), ptr);
+    assert_eq!(foo.into_ptr(), ptr);
+}
@@ -0,0 +1,35 @@
+#![cfg(feature = "defmt")]
+
+use foreign_types::foreign_type;
+
+mod foo_sys {
+    #[allow(clippy::upper_case_acronyms)]
+    pub enum FOO {}
+
+    pub unsafe fn foo_drop(_: *mut FOO) {}
+}
+
+foreign_type! {
+    pub unsafe type Foo {
+        type CType = foo_sys::FOO;
+        fn drop = foo_sys::foo_drop;
+    }
+
+    pub unsafe type Bar<T> {
+        type CType = foo_sys::FOO;
+        type PhantomData = T;
+        fn drop = foo_sys::foo_drop;
+    }
+}
+
+// A `defmt::Formatter` can only be created by a global logger, so the impls are only checked to
+// exist.
+fn assert_format<T: defmt::Format + ?Sized>() {}
+
+#[test]
+fn format_impls() {
+    assert_format::<Foo>();
+    assert_format::<FooRef>();
+    assert_format::<Bar<u8>>();
+    assert_format::<BarRef<u8>>();
+}
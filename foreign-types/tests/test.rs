@@ -1,5 +1,8 @@
+#![cfg(feature = "macros")]
+
 use foreign_types::foreign_type;
 
+#[allow(clippy::upper_case_acronyms)]
 mod foo_sys {
     pub enum FOO {}
 
@@ -27,6 +30,14 @@ foreign_type! {
         fn drop = foo_sys::foo_drop;
     }
 
+    pub unsafe type MutableFoo<'a, T>: Sync + Send {
+        type CType = foo_sys::FOO;
+        type PhantomData = &'a T;
+        type Immutable = Foo<'a, T>;
+        fn drop = foo_sys::foo_drop;
+        fn clone = foo_sys::foo_clone;
+    }
+
     pub unsafe type FooClosure {
         type CType = foo_sys::FOO;
         fn drop = |p| foo_sys::foo_drop(p);
@@ -38,4 +49,2431 @@ foreign_type! {
         fn drop = |p| foo_sys::foo_drop_requiring_cast(p as _);
         fn clone = |p| foo_sys::foo_clone_requiring_cast(p as _) as _;
     }
+
+    pub unsafe type FooReordered<'a, T> {
+        fn clone = foo_sys::foo_clone;
+        impl Debug;
+        fn drop = foo_sys::foo_drop;
+        type PhantomData = &'a T;
+        type CType = foo_sys::FOO;
+    }
+}
+
+#[cfg(feature = "track-ownership")]
+mod track {
+    use foreign_types::{foreign_type, ForeignType, ForeignTypeRef};
+
+    #[allow(clippy::upper_case_acronyms)]
+    pub enum BAR {}
+
+    unsafe fn bar_free(_: *mut BAR) {}
+
+    foreign_type! {
+        unsafe type Bar {
+            type CType = BAR;
+            fn drop = bar_free;
+        }
+    }
+
+    fn dangling(addr: usize) -> *mut BAR {
+        addr as *mut BAR
+    }
+
+    #[test]
+    fn into_ptr_round_trip() {
+        unsafe {
+            let bar = Bar::from_ptr(dangling(0x1000));
+            let ptr = bar.into_ptr();
+            drop(Bar::from_ptr(ptr));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "already owned")]
+    fn double_owned() {
+        unsafe {
+            let a = Bar::from_ptr(dangling(0x2000));
+            let b = Bar::from_ptr(dangling(0x2000));
+            std::mem::forget((a, b));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "used after being freed")]
+    fn use_after_free() {
+        unsafe {
+            drop(Bar::from_ptr(dangling(0x3000)));
+            BarRef::from_ptr(dangling(0x3000));
+        }
+    }
+}
+
+#[cfg(feature = "testing")]
+mod testing {
+    use foreign_types::{assert_no_leaks, foreign_type, ForeignType};
+
+    #[allow(clippy::upper_case_acronyms)]
+    pub enum BAZ {}
+
+    unsafe fn baz_free(_: *mut BAZ) {}
+    unsafe fn baz_clone(ptr: *mut BAZ) -> *mut BAZ {
+        ptr
+    }
+
+    foreign_type! {
+        unsafe type Baz {
+            type CType = BAZ;
+            fn drop = baz_free;
+            fn clone = baz_clone;
+        }
+
+        unsafe type ConstBaz {
+            type CType = BAZ;
+            fn drop = baz_free;
+        }
+    }
+
+    fn dangling() -> *mut BAZ {
+        std::ptr::NonNull::dangling().as_ptr()
+    }
+
+    #[test]
+    fn counts_live_instances() {
+        assert_no_leaks!(Baz => {
+            let baz = unsafe { Baz::from_ptr(dangling()) };
+            let clone = baz.clone();
+            assert_eq!(Baz::live_count(), 2);
+            drop(baz);
+            let ptr = clone.into_ptr();
+            assert_eq!(Baz::live_count(), 0);
+            drop(unsafe { Baz::from_ptr(ptr) });
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "leaked 1 instance(s) of Baz")]
+    fn detects_leaks() {
+        assert_no_leaks!(Baz => {
+            std::mem::forget(unsafe { Baz::from_ptr(dangling()) });
+        });
+    }
+
+    #[test]
+    fn const_values_are_not_counted() {
+        assert_no_leaks!(ConstBaz => {
+            let baz = unsafe { ConstBaz::from_ptr_const(dangling()) };
+            assert_eq!(ConstBaz::live_count(), 0);
+            drop(baz);
+        });
+        assert_eq!(ConstBaz::live_count(), 0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "live count decremented below zero")]
+    fn detects_underflow() {
+        let counter = foreign_types::testing::LiveCounter::new();
+        counter.increment();
+        counter.decrement();
+        counter.decrement();
+    }
+}
+
+static CONST_FOO: Foo<'static, u8> =
+    unsafe { Foo::from_ptr_const(core::ptr::NonNull::dangling().as_ptr()) };
+static CONST_FOO_REF: &FooRef<'static, u8> =
+    unsafe { FooRef::from_ptr_const(core::ptr::NonNull::dangling().as_ptr()) };
+
+#[test]
+fn reordered_items() {
+    use foreign_types::ForeignType;
+
+    let ptr = core::ptr::NonNull::dangling().as_ptr();
+    let foo = unsafe { FooReordered::<u8>::from_ptr(ptr) };
+    assert_eq!(foo.clone().as_ptr(), ptr);
+    assert_eq!(format!("{:?}", foo), format!("FooReordered({:?})", ptr));
+}
+
+#[test]
+fn const_from_ptr() {
+    use foreign_types::ForeignTypeRef;
+
+    let ptr = core::ptr::NonNull::dangling().as_ptr();
+    assert_eq!(CONST_FOO.as_ptr_const(), ptr);
+    assert_eq!(CONST_FOO_REF.as_ptr(), ptr);
+}
+
+#[cfg(all(feature = "objc2", target_vendor = "apple"))]
+mod toll_free_bridged {
+    use foreign_types::{foreign_type, ForeignType, ForeignTypeRef};
+    use objc2::rc::Retained;
+    use objc2::runtime::NSObject;
+
+    foreign_type! {
+        pub unsafe type BridgedObject {
+            type CType = NSObject;
+            type TollFreeBridged = NSObject;
+            fn drop = |p| unsafe { drop(Retained::from_raw(p)) };
+        }
+    }
+
+    #[test]
+    fn toll_free_bridged() {
+        let object = NSObject::new();
+        let ptr = Retained::as_ptr(&object);
+        let bridged = BridgedObject::from(object);
+        assert!(std::ptr::eq(bridged.as_ptr(), ptr));
+        let object = Retained::<NSObject>::from(bridged);
+        let bridged_ref: &BridgedObjectRef = (&*object).into();
+        assert!(std::ptr::eq(bridged_ref.as_ptr(), ptr));
+        let ns: &NSObject = bridged_ref.as_ref();
+        assert!(std::ptr::eq(ns, ptr));
+    }
+}
+
+#[cfg(all(feature = "core-foundation", target_vendor = "apple"))]
+mod core_foundation_bridged {
+    use core_foundation::base::{CFRelease, CFRetain, TCFType};
+    use core_foundation::string::{__CFString, CFString};
+    use foreign_types::{foreign_type, ForeignType};
+
+    foreign_type! {
+        pub unsafe type CoreString {
+            type CType = __CFString;
+            type CoreFoundation = CFString;
+            fn drop = |p| unsafe { CFRelease(p.cast()) };
+            fn clone = |p| CFRetain(p.cast()) as _;
+        }
+    }
+
+    #[test]
+    fn core_foundation() {
+        let string = CFString::new("foo");
+        let ptr = string.as_concrete_TypeRef();
+        let foo = CoreString::from(string);
+        assert!(std::ptr::eq(foo.as_ptr(), ptr));
+        let string = CFString::from(&*foo);
+        assert_eq!(string.to_string(), "foo");
+        drop(string);
+        let string = CFString::from(foo);
+        let foo = CoreString::from(string);
+        assert!(std::ptr::eq(foo.as_ptr(), ptr));
+    }
+}
+
+foreign_type! {
+    pub unsafe type RefCountedFoo: Sync + Send {
+        type CType = foo_sys::FOO;
+        fn ref = foo_sys::foo_clone;
+        fn unref = foo_sys::foo_drop;
+    }
+}
+
+#[cfg(feature = "glib")]
+#[test]
+fn glib() {
+    use foreign_types::ForeignType;
+    use glib::translate::{FromGlibPtrFull, FromGlibPtrNone, ToGlibPtr};
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+    let ptr: <RefCountedFoo as glib::translate::GlibPtrDefault>::GlibType = ptr;
+    let foo = unsafe { RefCountedFoo::from_glib_full(ptr) };
+    assert_eq!(foo.to_glib_none().0, ptr);
+    assert_eq!(foo.to_glib_full(), ptr);
+    let other = unsafe { RefCountedFoo::from_glib_none(ptr) };
+    assert_eq!(other.as_ptr(), ptr);
+}
+
+#[cfg(feature = "pyo3")]
+foreign_type! {
+    pub unsafe type CapsuleFoo: Sync + Send {
+        type CType = foo_sys::FOO;
+        const PY_CAPSULE_NAME = "test.CapsuleFoo";
+        fn drop = counted_sys::foo_drop;
+    }
+
+    pub unsafe type OtherCapsuleFoo: Sync + Send {
+        type CType = foo_sys::FOO;
+        const PY_CAPSULE_NAME = "test.OtherCapsuleFoo";
+        fn drop = counted_sys::foo_drop;
+    }
+}
+
+#[cfg(feature = "pyo3")]
+#[test]
+fn py_capsule() {
+    use foreign_types::{ForeignType, ForeignTypeRef};
+    use pyo3::types::PyCapsuleMethods;
+
+    static STORAGE: [u8; 2] = [0; 2];
+    let ptr = &STORAGE[0] as *const u8 as *mut foo_sys::FOO;
+    let drops = || counted_sys::DROPS.with(|d| d.get());
+    pyo3::prepare_freethreaded_python();
+    pyo3::Python::with_gil(|py| {
+        let foo = unsafe { CapsuleFoo::from_ptr(ptr) };
+        let capsule = foo.into_py_capsule(py).unwrap();
+        assert_eq!(capsule.pointer(), ptr.cast());
+        let foo = unsafe { CapsuleFooRef::from_py_capsule(&capsule) }.unwrap();
+        assert_eq!(foo.as_ptr(), ptr);
+        assert!(unsafe { OtherCapsuleFooRef::from_py_capsule(&capsule) }.is_err());
+        assert!(unsafe { OtherCapsuleFoo::from_py_capsule(&capsule) }.is_err());
+
+        let foo = unsafe { CapsuleFoo::from_py_capsule(&capsule) }.unwrap();
+        assert_eq!(foo.as_ptr(), ptr);
+        assert!(unsafe { CapsuleFooRef::from_py_capsule(&capsule) }.is_err());
+        drop(capsule);
+        assert_eq!(drops(), 0);
+        drop(foo);
+        assert_eq!(drops(), 1);
+
+        let ptr = &STORAGE[1] as *const u8 as *mut foo_sys::FOO;
+        let capsule = unsafe { CapsuleFoo::from_ptr(ptr) }
+            .into_py_capsule(py)
+            .unwrap();
+        drop(capsule);
+        assert_eq!(drops(), 2);
+    });
+}
+
+foreign_type! {
+    pub unsafe type HandleFoo: Sync + Send {
+        type CType = core::ffi::c_void;
+        fn drop = |_| {};
+        impl AsHandle;
+    }
+}
+
+foreign_type! {
+    pub unsafe type DynamicFoo {
+        type CType = foo_sys::FOO;
+        fn drop = dyn;
+        fn clone = dyn;
+    }
+}
+
+#[test]
+fn dynamic() {
+    use foreign_types::ForeignType;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn dynamic_drop(_: *mut foo_sys::FOO) {
+        DROPS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    unsafe {
+        DynamicFoo::set_drop_fn(dynamic_drop);
+        DynamicFoo::set_clone_fn(foo_sys::foo_clone);
+    }
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+    let foo = unsafe { DynamicFoo::from_ptr(ptr) };
+    let clone = foo.clone();
+    assert_eq!(clone.as_ptr(), ptr);
+    drop(clone);
+    drop(foo);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+}
+
+foreign_type! {
+    pub unsafe type DtorFoo<'a, T> {
+        type CType = foo_sys::FOO;
+        type PhantomData = &'a T;
+        type Dtor = unsafe fn(*mut foo_sys::FOO);
+        fn drop = |p| foo_sys::foo_drop(p);
+        fn clone = foo_sys::foo_clone;
+    }
+}
+
+#[test]
+fn per_instance_dtor() {
+    use foreign_types::ForeignType;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe fn counting_drop(_: *mut foo_sys::FOO) {
+        DROPS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+    let foo = unsafe { DtorFoo::<u8>::from_ptr_with_dtor(ptr, counting_drop) };
+    let clone = foo.clone();
+    drop(foo);
+    drop(clone);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+
+    drop(unsafe { DtorFoo::<u8>::from_ptr(ptr) });
+    assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+}
+
+// A minimal stand-in for the traits of foreign-types 0.3.
+mod foreign_types_03 {
+    pub trait ForeignType: Sized {
+        type CType;
+        type Ref: ForeignTypeRef<CType = Self::CType>;
+
+        unsafe fn from_ptr(ptr: *mut Self::CType) -> Self;
+
+        fn as_ptr(&self) -> *mut Self::CType;
+    }
+
+    pub trait ForeignTypeRef: Sized {
+        type CType;
+
+        fn as_ptr(&self) -> *mut Self::CType {
+            self as *const _ as *mut _
+        }
+    }
+}
+
+foreign_type! {
+    pub unsafe type CompatFoo<'a, T> {
+        type CType = foo_sys::FOO;
+        type PhantomData = &'a T;
+        fn drop = foo_sys::foo_drop;
+        impl foreign_types_03::ForeignType;
+    }
+}
+
+#[test]
+fn compat_03() {
+    fn old_as_ptr<T: foreign_types_03::ForeignType>(t: &T) -> *mut T::CType {
+        t.as_ptr()
+    }
+
+    fn old_ref_as_ptr<T: foreign_types_03::ForeignTypeRef>(t: &T) -> *mut T::CType {
+        t.as_ptr()
+    }
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+    let foo = unsafe { <CompatFoo<u8> as foreign_types_03::ForeignType>::from_ptr(ptr) };
+    assert_eq!(old_as_ptr(&foo), ptr);
+    assert_eq!(old_ref_as_ptr(&*foo), ptr);
+    assert_eq!(foreign_types::ForeignType::as_ptr(&foo), ptr);
+}
+
+mod array_sys {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::foo_sys::FOO;
+
+    pub static ELEMENTS_FREED: AtomicUsize = AtomicUsize::new(0);
+    pub static ARRAYS_FREED: AtomicUsize = AtomicUsize::new(0);
+
+    pub unsafe extern "C" fn element_free(_: *mut FOO) {
+        ELEMENTS_FREED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub unsafe extern "C" fn array_free(ptr: *mut *mut FOO) {
+        drop(Box::from_raw(ptr as *mut [*mut FOO; 3]));
+        ARRAYS_FREED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+foreign_type! {
+    pub unsafe type ArrayFoo {
+        type CType = foo_sys::FOO;
+        fn drop = array_sys::element_free;
+        fn free_array = array_sys::array_free;
+    }
+}
+
+#[test]
+fn null_terminated() {
+    use foreign_types::array::NullTerminated;
+    use foreign_types::ForeignTypeRef;
+    use std::ptr;
+    use std::sync::atomic::Ordering;
+
+    static STORAGE: [u8; 2] = [0; 2];
+    let elements = [
+        &STORAGE[0] as *const u8 as *mut foo_sys::FOO,
+        &STORAGE[1] as *const u8 as *mut foo_sys::FOO,
+    ];
+    let new_array = || Box::into_raw(Box::new([elements[0], elements[1], ptr::null_mut()])).cast();
+
+    let borrowed =
+        unsafe { NullTerminated::<ArrayFoo>::from_ptr(new_array(), |p| array_sys::array_free(p)) };
+    assert_eq!(borrowed.len(), 2);
+    assert!(!borrowed.is_empty());
+    assert_eq!(borrowed.get(1).unwrap().as_ptr(), elements[1]);
+    assert!(borrowed.get(2).is_none());
+    let ptrs = borrowed.iter().map(|f| f.as_ptr()).collect::<Vec<_>>();
+    assert_eq!(ptrs, elements);
+    assert_eq!(borrowed.iter().next_back().unwrap().as_ptr(), elements[1]);
+    drop(borrowed);
+    assert_eq!(array_sys::ARRAYS_FREED.load(Ordering::SeqCst), 1);
+    assert_eq!(array_sys::ELEMENTS_FREED.load(Ordering::SeqCst), 0);
+
+    let owned = unsafe { ArrayFoo::from_null_terminated(new_array()) };
+    assert_eq!((&owned).into_iter().len(), 2);
+    drop(owned);
+    assert_eq!(array_sys::ARRAYS_FREED.load(Ordering::SeqCst), 2);
+    assert_eq!(array_sys::ELEMENTS_FREED.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(feature = "std")]
+mod vec {
+    use foreign_types::array::{into_c_array, vec_from_raw_parts};
+    use foreign_types::{foreign_type, ForeignType};
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::foo_sys::FOO;
+
+    static ELEMENTS_FREED: AtomicUsize = AtomicUsize::new(0);
+    static ARRAYS_FREED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe fn element_free(_: *mut FOO) {
+        ELEMENTS_FREED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    unsafe fn array_alloc(len: usize) -> *mut *mut FOO {
+        Box::into_raw(vec![ptr::null_mut::<FOO>(); len].into_boxed_slice()).cast()
+    }
+
+    unsafe fn array_free(ptr: *mut *mut FOO) {
+        drop(Box::from_raw(ptr as *mut [*mut FOO; 2]));
+        ARRAYS_FREED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    foreign_type! {
+        pub unsafe type VecFoo {
+            type CType = FOO;
+            fn drop = element_free;
+        }
+    }
+
+    #[test]
+    fn vec_from_raw_parts_into_c_array() {
+        static STORAGE: [u8; 2] = [0; 2];
+        let elements = [
+            &STORAGE[0] as *const u8 as *mut FOO,
+            &STORAGE[1] as *const u8 as *mut FOO,
+        ];
+        let values = elements
+            .iter()
+            .map(|&ptr| unsafe { VecFoo::from_ptr(ptr) })
+            .collect::<Vec<_>>();
+
+        let array = unsafe { into_c_array(values, array_alloc) };
+        assert_eq!(unsafe { *array.add(1) }, elements[1]);
+        assert_eq!(ELEMENTS_FREED.load(Ordering::SeqCst), 0);
+
+        let values = unsafe { vec_from_raw_parts::<VecFoo>(array, 2, array_free) };
+        assert_eq!(ARRAYS_FREED.load(Ordering::SeqCst), 1);
+        assert_eq!(values[0].as_ptr(), elements[0]);
+        drop(values);
+        assert_eq!(ELEMENTS_FREED.load(Ordering::SeqCst), 2);
+
+        let empty = unsafe { vec_from_raw_parts::<VecFoo>(ptr::null_mut(), 0, array_free) };
+        assert!(empty.is_empty());
+        assert_eq!(ARRAYS_FREED.load(Ordering::SeqCst), 1);
+    }
+}
+
+foreign_type! {
+    pub unsafe type FooPool {
+        type CType = foo_sys::FOO;
+        fn drop = foo_sys::foo_drop;
+    }
+
+    pub unsafe type PoolFoo<'pool> {
+        type CType = foo_sys::FOO;
+        type Pool = FooPool;
+    }
+}
+
+#[test]
+fn pool_allocated() {
+    use foreign_types::ForeignType;
+
+    static POOL: u8 = 0;
+    static STORAGE: u8 = 0;
+    let pool = unsafe { FooPool::from_ptr(&POOL as *const u8 as *mut foo_sys::FOO) };
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+    let foo = unsafe { PoolFoo::from_pool_ptr(&pool, ptr) };
+    assert_eq!(foo.as_ptr(), ptr);
+    drop(foo);
+    drop(pool);
+}
+
+#[cfg(feature = "std")]
+mod thread {
+    use foreign_types::thread::{SyncForeign, ThreadBound};
+    use foreign_types::{foreign_type, ForeignType};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::foo_sys::FOO;
+
+    static FREED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe fn bound_free(_: *mut FOO) {
+        FREED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    foreign_type! {
+        pub unsafe type BoundFoo {
+            type CType = FOO;
+            fn drop = bound_free;
+        }
+    }
+
+    fn new_bound(storage: &'static u8) -> ThreadBound<BoundFoo> {
+        ThreadBound::new(unsafe { BoundFoo::from_ptr(storage as *const u8 as *mut FOO) })
+    }
+
+    #[test]
+    fn thread_bound() {
+        static STORAGE: [u8; 2] = [0; 2];
+
+        let bound = new_bound(&STORAGE[0]);
+        assert!(bound.is_owning_thread());
+        assert_eq!(bound.as_ptr(), &STORAGE[0] as *const u8 as *mut FOO);
+
+        let bound = thread::spawn(move || {
+            assert!(bound.get().is_none());
+            assert!(thread::current().id() != bound.thread_id());
+            bound
+        })
+        .join()
+        .unwrap();
+        drop(bound.into_inner());
+        assert_eq!(FREED.load(Ordering::SeqCst), 1);
+
+        let bound = new_bound(&STORAGE[1]);
+        let panicked = thread::spawn(move || {
+            let _ = bound.as_ptr();
+        })
+        .join()
+        .is_err();
+        assert!(panicked);
+        assert_eq!(FREED.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn sync_foreign() {
+        use foreign_types::ForeignTypeRef;
+
+        static STORAGE: u8 = 0;
+        let ptr = &STORAGE as *const u8 as *mut FOO;
+        let shared = Arc::new(unsafe { SyncForeign::new_unchecked(BoundFoo::from_ptr(ptr)) });
+
+        let threads = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || shared.with(|foo| foo.as_ptr() as usize))
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), ptr as usize);
+        }
+
+        assert_eq!(shared.lock().as_ptr(), ptr);
+        let mut shared = Arc::try_unwrap(shared).unwrap();
+        assert_eq!(shared.get_mut().as_ptr(), ptr);
+        let foo = shared.into_inner();
+        assert_eq!(foo.as_ptr(), ptr);
+        // Dropping `foo` would race with the counter checked by `thread_bound`.
+        std::mem::forget(foo);
+    }
+}
+
+#[cfg(feature = "std")]
+mod queue {
+    use foreign_types::queue::DropQueue;
+    use foreign_types::{foreign_type, ForeignType};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    use crate::foo_sys::FOO;
+
+    static QUEUE: DropQueue = DropQueue::new();
+    static FREED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe fn queued_free(_: *mut FOO) {
+        FREED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    foreign_type! {
+        pub unsafe type QueuedFoo: Send {
+            type CType = FOO;
+            static DROP_QUEUE = QUEUE;
+            fn drop = queued_free;
+        }
+    }
+
+    #[test]
+    fn drop_queue() {
+        static STORAGE: [u8; 2] = [0; 2];
+
+        assert!(QUEUE.is_empty());
+        let foos = STORAGE
+            .iter()
+            .map(|s| unsafe { QueuedFoo::from_ptr(s as *const u8 as *mut FOO) })
+            .collect::<Vec<_>>();
+        thread::spawn(move || drop(foos)).join().unwrap();
+        assert!(!QUEUE.is_empty());
+        assert_eq!(FREED.load(Ordering::SeqCst), 0);
+
+        assert_eq!(QUEUE.drain(), 2);
+        assert!(QUEUE.is_empty());
+        assert_eq!(FREED.load(Ordering::SeqCst), 2);
+        assert_eq!(QUEUE.drain(), 0);
+    }
+
+    #[test]
+    fn drain_continues_after_panic() {
+        static RUN: AtomicUsize = AtomicUsize::new(0);
+
+        unsafe fn count(_: *mut ()) {
+            RUN.fetch_add(1, Ordering::SeqCst);
+        }
+
+        unsafe fn panic(_: *mut ()) {
+            panic!("destructor panicked");
+        }
+
+        let queue = DropQueue::new();
+        unsafe {
+            queue.push(std::ptr::null_mut(), count);
+            queue.push(std::ptr::null_mut(), panic);
+            queue.push(std::ptr::null_mut(), count);
+        }
+
+        let result = std::panic::catch_unwind(|| queue.drain());
+        assert!(result.is_err());
+        assert_eq!(RUN.load(Ordering::SeqCst), 2);
+        assert!(queue.is_empty());
+    }
+}
+
+#[cfg(feature = "trace")]
+mod trace {
+    use foreign_types::trace::{self, Event, EventKind};
+    use foreign_types::{foreign_type, ForeignType};
+    use std::cell::RefCell;
+
+    use crate::foo_sys::FOO;
+
+    unsafe fn traced_free(_: *mut FOO) {}
+    unsafe fn traced_clone(_: *mut FOO) -> *mut FOO {
+        &CLONE_STORAGE as *const u8 as *mut FOO
+    }
+
+    static STORAGE: u8 = 0;
+    static CLONE_STORAGE: u8 = 0;
+
+    thread_local! {
+        static EVENTS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    foreign_type! {
+        pub unsafe type TracedFoo {
+            type CType = FOO;
+            fn drop = traced_free;
+            fn clone = traced_clone;
+        }
+
+        pub unsafe type TracedClosingFoo {
+            type CType = FOO;
+            fn drop = traced_free;
+            fn close = traced_close;
+        }
+    }
+
+    async unsafe fn traced_close(_: *mut FOO) {}
+
+    fn hook(event: &Event) {
+        if event.type_name.starts_with("Traced") {
+            assert_eq!(event.kind == EventKind::Clone, !event.new_ptr.is_null());
+            EVENTS.with(|events| events.borrow_mut().push(event.to_string()));
+        }
+    }
+
+    #[test]
+    fn trace_events() {
+        trace::set_hook(hook);
+        let foo = unsafe { TracedFoo::from_ptr(&STORAGE as *const u8 as *mut FOO) };
+        let clone = foo.clone();
+        drop(foo);
+        drop(clone);
+        trace::clear_hook();
+
+        let ptr = format!("{:p}", &STORAGE);
+        let clone_ptr = format!("{:p}", &CLONE_STORAGE);
+        assert_eq!(
+            EVENTS.with(|events| events.take()),
+            [
+                format!("TracedFoo::from_ptr({})", ptr),
+                format!("TracedFoo::clone({}) -> {}", ptr, clone_ptr),
+                format!("TracedFoo::from_ptr({})", clone_ptr),
+                format!("TracedFoo::drop({})", ptr),
+                format!("TracedFoo::drop({})", clone_ptr),
+            ]
+        );
+
+        // The hook is global, so this shares a test with the events above.
+        trace::set_hook(hook);
+        let foo = unsafe { TracedClosingFoo::from_ptr(&STORAGE as *const u8 as *mut FOO) };
+        drop(foo);
+        trace::clear_hook();
+
+        assert_eq!(
+            EVENTS.with(|events| events.take()),
+            [
+                format!("TracedClosingFoo::from_ptr({})", ptr),
+                format!("TracedClosingFoo::drop({}) without close", ptr),
+            ]
+        );
+    }
+}
+
+mod getter_sys {
+    #[allow(clippy::upper_case_acronyms)]
+    pub struct BAR {
+        pub foo: u8,
+    }
+
+    pub unsafe fn bar_get_foo(bar: *mut BAR) -> *mut crate::foo_sys::FOO {
+        &mut (*bar).foo as *mut u8 as *mut crate::foo_sys::FOO
+    }
+}
+
+foreign_type! {
+    pub unsafe type GetterFoo {
+        type CType = foo_sys::FOO;
+        fn drop = foo_sys::foo_drop;
+    }
+
+    pub unsafe type GetterBar {
+        type CType = getter_sys::BAR;
+        fn drop = |_| {};
+        fn get foo: GetterFooRef = getter_sys::bar_get_foo;
+        /// Returns the Foo again.
+        fn get foo_again: GetterFooRef = |p| getter_sys::bar_get_foo(p);
+        fn get missing: GetterFooRef = |_| std::ptr::null_mut::<foo_sys::FOO>();
+    }
+}
+
+#[test]
+fn getters() {
+    use foreign_types::ForeignTypeRef;
+
+    let mut bar = getter_sys::BAR { foo: 0 };
+    let foo_ptr = &mut bar.foo as *mut u8 as *mut foo_sys::FOO;
+    let bar = unsafe { GetterBarRef::from_ptr_mut(&mut bar) };
+    assert_eq!(bar.foo().as_ptr(), foo_ptr);
+    assert_eq!(bar.foo_mut().as_ptr(), foo_ptr);
+    assert_eq!(bar.foo_again().as_ptr(), foo_ptr);
+    assert_eq!(bar.foo_again_mut().as_ptr(), foo_ptr);
+}
+
+#[test]
+#[should_panic(expected = "`missing` returned a null pointer")]
+fn null_getter() {
+    use foreign_types::ForeignTypeRef;
+
+    let mut bar = getter_sys::BAR { foo: 0 };
+    let bar = unsafe { GetterBarRef::from_ptr_mut(&mut bar) };
+    bar.missing();
+}
+
+#[test]
+fn maybe_owned() {
+    use foreign_types::maybe_owned::MaybeOwned;
+    use foreign_types::ForeignTypeRef;
+
+    static STORAGE: [u8; 2] = [0; 2];
+    let owned_ptr = &STORAGE[0] as *const u8 as *mut foo_sys::FOO;
+    let borrowed_ptr = &STORAGE[1] as *const u8 as *mut foo_sys::FOO;
+
+    let owned = unsafe { MaybeOwned::<Foo<u8>>::from_ptr(owned_ptr, true) };
+    assert!(owned.is_owned());
+    assert_eq!(owned.as_ptr(), owned_ptr);
+    assert_eq!(owned.clone().as_ptr(), owned_ptr);
+
+    let borrowed = unsafe { MaybeOwned::<Foo<u8>>::from_ptr(borrowed_ptr, false) };
+    assert!(borrowed.is_borrowed());
+    assert_eq!(borrowed.as_ptr(), borrowed_ptr);
+
+    #[cfg(feature = "std")]
+    {
+        use foreign_types::ForeignType;
+
+        let owned = owned.into_owned();
+        assert_eq!(owned.as_ptr(), owned_ptr);
+        let from_borrowed = borrowed.into_owned();
+        assert_eq!(from_borrowed.as_ptr(), borrowed_ptr);
+        std::mem::forget(from_borrowed);
+    }
+}
+
+#[test]
+fn nullable() {
+    use foreign_types::{ForeignType, ForeignTypeRef};
+    use std::ptr;
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+
+    assert!(unsafe { Foo::<u8>::from_ptr_opt(ptr::null_mut()) }.is_none());
+    let foo = unsafe { Foo::<u8>::from_ptr_opt(ptr) }.unwrap();
+    assert_eq!(foo.as_ptr(), ptr);
+
+    assert!(unsafe { FooRef::<u8>::from_ptr_opt(ptr::null_mut()) }.is_none());
+    assert!(unsafe { FooRef::<u8>::from_ptr_mut_opt(ptr::null_mut()) }.is_none());
+    let foo_ref = unsafe { FooRef::<u8>::from_ptr_opt(ptr) };
+    assert_eq!(FooRef::opt_as_ptr(foo_ref), ptr);
+    assert!(FooRef::<u8>::opt_as_ptr(None).is_null());
+}
+
+mod error_sys {
+    pub unsafe fn last_error() -> i32 {
+        42
+    }
+}
+
+foreign_type! {
+    pub unsafe type ErrorFoo {
+        type CType = foo_sys::FOO;
+        fn drop = foo_sys::foo_drop;
+        fn last_error: i32 = error_sys::last_error;
+    }
+}
+
+#[test]
+fn last_error() {
+    use foreign_types::ForeignType;
+    use std::ptr;
+
+    let err = unsafe { ErrorFoo::from_ptr_checked(ptr::null_mut()) }
+        .err()
+        .unwrap();
+    assert_eq!(err, ErrorFooError(42));
+    assert_eq!(err.code(), 42);
+    assert_eq!(err.to_string(), "error creating ErrorFoo: 42");
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+    let foo = unsafe { ErrorFoo::from_ptr_checked(ptr) }.unwrap();
+    assert_eq!(foo.as_ptr(), ptr);
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn try_init() {
+    use foreign_types::init::try_init;
+    use foreign_types::ForeignType;
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+
+    let foo = unsafe {
+        try_init::<Foo<u8>, _>(|out| {
+            *out = ptr;
+            0
+        })
+    }
+    .unwrap();
+    assert_eq!(foo.as_ptr(), ptr);
+
+    let err = unsafe { try_init::<Foo<u8>, _>(|_| -3) }.err().unwrap();
+    assert_eq!(err, -3);
+}
+
+mod parse_sys {
+    use crate::foo_sys::FOO;
+
+    static STORAGE: u8 = 0;
+
+    pub unsafe fn foo_parse(data: *const u8, len: usize) -> *mut FOO {
+        if len > 0 && *data == b'F' {
+            &STORAGE as *const u8 as *mut FOO
+        } else {
+            std::ptr::null_mut()
+        }
+    }
+}
+
+foreign_type! {
+    pub unsafe type ParsedFoo {
+        type CType = foo_sys::FOO;
+        fn drop = foo_sys::foo_drop;
+        fn from_bytes = parse_sys::foo_parse;
+    }
+}
+
+#[test]
+fn from_bytes() {
+    assert!(ParsedFoo::from_bytes(b"Foo").is_some());
+    assert!(ParsedFoo::from_bytes(b"Bar").is_none());
+    assert!(ParsedFoo::from_bytes(b"").is_none());
+}
+
+#[test]
+#[cfg(feature = "arbitrary")]
+fn arbitrary() {
+    use arbitrary::{Arbitrary, Error, Unstructured};
+
+    assert!(ParsedFoo::arbitrary_take_rest(Unstructured::new(b"Foo")).is_ok());
+    assert!(matches!(
+        ParsedFoo::arbitrary_take_rest(Unstructured::new(b"Bar")),
+        Err(Error::IncorrectFormat)
+    ));
+    assert!(matches!(
+        ParsedFoo::arbitrary(&mut Unstructured::new(b"")),
+        Err(Error::IncorrectFormat)
+    ));
+}
+
+mod static_sys {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    pub static LOADS: AtomicUsize = AtomicUsize::new(0);
+    static STORAGE: u8 = 0;
+
+    pub fn default_foo() -> *mut crate::foo_sys::FOO {
+        LOADS.fetch_add(1, Ordering::SeqCst);
+        &STORAGE as *const u8 as *mut crate::foo_sys::FOO
+    }
+
+    pub fn null_foo() -> *mut crate::foo_sys::FOO {
+        std::ptr::null_mut()
+    }
+}
+
+foreign_type! {
+    pub unsafe type StaticFoo {
+        type CType = foo_sys::FOO;
+        fn drop = foo_sys::foo_drop;
+    }
+
+    /// The default StaticFoo.
+    pub static ref DEFAULT_FOO: StaticFooRef = static_sys::default_foo();
+
+    static ref NULL_FOO: StaticFooRef = static_sys::null_foo();
+}
+
+#[test]
+fn static_ref() {
+    use foreign_types::ForeignTypeRef;
+    use std::sync::atomic::Ordering;
+
+    let ptr = DEFAULT_FOO.as_ptr();
+    assert_eq!(DEFAULT_FOO.as_ptr(), ptr);
+    assert_eq!(static_sys::LOADS.load(Ordering::SeqCst), 1);
+
+    let foo: &'static StaticFooRef = &DEFAULT_FOO;
+    assert_eq!(foo.as_ptr(), ptr);
+}
+
+#[test]
+#[should_panic(expected = "`NULL_FOO` is null")]
+fn static_ref_null() {
+    let _ = &*NULL_FOO;
+}
+
+#[cfg(feature = "std")]
+mod io {
+    use foreign_types::{foreign_type, ForeignType};
+    use std::io::{Read, Write};
+    use std::os::raw::{c_int, c_void};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::foo_sys;
+
+    unsafe fn foo_read(_: *mut foo_sys::FOO, buf: *mut c_void, len: c_int) -> c_int {
+        std::ptr::write_bytes(buf as *mut u8, b'x', len as usize);
+        len
+    }
+
+    unsafe fn foo_write(_: *mut foo_sys::FOO, buf: *const c_void, len: c_int) -> c_int {
+        if len > 0 && *(buf as *const u8) == b'!' {
+            -1
+        } else {
+            len
+        }
+    }
+
+    static FLUSHES: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe fn foo_flush(_: *mut foo_sys::FOO) -> c_int {
+        FLUSHES.fetch_add(1, Ordering::SeqCst);
+        0
+    }
+
+    unsafe fn foo_read_overlong(_: *mut foo_sys::FOO, _: *mut c_void, len: c_int) -> c_int {
+        len + 1
+    }
+
+    foreign_type! {
+        pub unsafe type IoFoo {
+            type CType = foo_sys::FOO;
+            fn drop = foo_sys::foo_drop;
+            fn read = foo_read;
+            fn write = foo_write;
+            fn flush = foo_flush;
+        }
+
+        pub unsafe type OverlongFoo {
+            type CType = foo_sys::FOO;
+            fn drop = foo_sys::foo_drop;
+            fn read = foo_read_overlong;
+        }
+
+        pub unsafe type IoErrorFoo {
+            type CType = foo_sys::FOO;
+            fn drop = foo_sys::foo_drop;
+            fn last_error: i32 = crate::error_sys::last_error;
+            fn write = foo_write;
+        }
+    }
+
+    static STORAGE: u8 = 0;
+
+    #[test]
+    fn read_write() {
+        let mut foo = unsafe { IoFoo::from_ptr(&STORAGE as *const u8 as *mut _) };
+
+        let mut buf = [0; 4];
+        assert_eq!(foo.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"xxxx");
+
+        assert_eq!(foo.write(b"abc").unwrap(), 3);
+        foo.flush().unwrap();
+        assert_eq!(FLUSHES.load(Ordering::SeqCst), 1);
+
+        let err = foo.write(b"!").unwrap_err();
+        assert_eq!(err.to_string(), "IoFoo I/O failed");
+    }
+
+    #[test]
+    fn write_last_error() {
+        let mut foo = unsafe { IoErrorFoo::from_ptr(&STORAGE as *const u8 as *mut _) };
+
+        let err = foo.write(b"!").unwrap_err();
+        assert_eq!(err.to_string(), "error creating IoErrorFoo: 42");
+    }
+
+    #[test]
+    fn read_overlong() {
+        let mut foo = unsafe { OverlongFoo::from_ptr(&STORAGE as *const u8 as *mut _) };
+
+        let err = foo.read(&mut [0; 4]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
+
+mod error_object_sys {
+    use std::os::raw::c_char;
+
+    use crate::foo_sys::FOO;
+
+    pub static UNKNOWN: u8 = 0;
+
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub unsafe fn foo_message(ptr: *mut FOO) -> *const c_char {
+        if std::ptr::eq(ptr as *const u8, &UNKNOWN) {
+            std::ptr::null()
+        } else {
+            b"out of cheese\0".as_ptr() as *const c_char
+        }
+    }
+
+    pub unsafe fn foo_code(_: *mut FOO) -> i32 {
+        7
+    }
+}
+
+foreign_type! {
+    pub unsafe type ErrorObject {
+        type CType = foo_sys::FOO;
+        fn drop = foo_sys::foo_drop;
+        fn message = error_object_sys::foo_message;
+        fn code: i32 = error_object_sys::foo_code;
+    }
+}
+
+#[test]
+fn error_object() {
+    use foreign_types::ForeignType;
+
+    static STORAGE: u8 = 0;
+    let error = unsafe { ErrorObject::from_ptr(&STORAGE as *const u8 as *mut _) };
+    assert_eq!(error.code(), 7);
+
+    #[cfg(feature = "std")]
+    {
+        assert_eq!(error.to_string(), "out of cheese");
+        assert_eq!(
+            format!("{:?}", error),
+            "ErrorObject { code: 7, message: out of cheese }"
+        );
+
+        let boxed: Box<dyn std::error::Error> = Box::new(error);
+        assert_eq!(boxed.to_string(), "out of cheese");
+
+        let ptr = &error_object_sys::UNKNOWN as *const u8 as *mut _;
+        let error = unsafe { ErrorObject::from_ptr(ptr) };
+        assert_eq!(error.to_string(), "unknown error");
+    }
+}
+
+#[cfg(feature = "std")]
+mod from_str {
+    use foreign_types::foreign_type;
+    use std::ffi::CStr;
+    use std::os::raw::c_char;
+
+    use crate::foo_sys;
+
+    static STORAGE: u8 = 0;
+
+    unsafe fn foo_parse_str(s: *const c_char) -> *mut foo_sys::FOO {
+        if CStr::from_ptr(s).to_bytes() == b"foo" {
+            &STORAGE as *const u8 as *mut foo_sys::FOO
+        } else {
+            std::ptr::null_mut()
+        }
+    }
+
+    foreign_type! {
+        pub unsafe type ParsedFoo {
+            type CType = foo_sys::FOO;
+            fn drop = foo_sys::foo_drop;
+            fn from_str = foo_parse_str;
+        }
+    }
+
+    #[test]
+    fn from_str() {
+        use foreign_types::ForeignType;
+
+        let foo: ParsedFoo = "foo".parse().unwrap();
+        assert_eq!(foo.as_ptr() as *const u8, &STORAGE as *const u8);
+
+        let err = "bar".parse::<ParsedFoo>().err().unwrap();
+        assert_eq!(err.to_string(), "invalid ParsedFoo syntax");
+
+        let err = "f\0o".parse::<ParsedFoo>().err().unwrap();
+        assert_eq!(err.to_string(), "string contains an interior nul byte");
+        assert_ne!(err, "bar".parse::<ParsedFoo>().err().unwrap());
+    }
+}
+
+mod cmp_sys {
+    use std::os::raw::c_int;
+
+    use crate::foo_sys::FOO;
+
+    pub unsafe fn foo_cmp(a: *mut FOO, b: *mut FOO) -> c_int {
+        *(a as *const u8) as c_int - *(b as *const u8) as c_int
+    }
+}
+
+foreign_type! {
+    pub unsafe type OrdFoo {
+        type CType = foo_sys::FOO;
+        fn drop = foo_sys::foo_drop;
+        fn cmp = cmp_sys::foo_cmp;
+    }
+}
+
+#[test]
+fn cmp() {
+    use foreign_types::ForeignType;
+
+    static VALUES: [u8; 4] = [3, 1, 2, 1];
+    let mut foos = VALUES
+        .iter()
+        .map(|v| unsafe { OrdFoo::from_ptr(v as *const u8 as *mut _) })
+        .collect::<Vec<_>>();
+    foos.sort();
+
+    let sorted = foos
+        .iter()
+        .map(|foo| unsafe { *(foo.as_ptr() as *const u8) })
+        .collect::<Vec<_>>();
+    assert_eq!(sorted, [1, 1, 2, 3]);
+    assert!(foos[0] == foos[1]);
+    assert!(*foos[2] > *foos[1]);
+}
+
+mod counted_sys {
+    use std::cell::Cell;
+
+    use crate::foo_sys::FOO;
+
+    thread_local! {
+        pub static DROPS: Cell<usize> = const { Cell::new(0) };
+        pub static CLONES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    pub unsafe fn foo_drop(_: *mut FOO) {
+        DROPS.with(|d| d.set(d.get() + 1));
+    }
+
+    pub unsafe fn foo_clone(ptr: *mut FOO) -> *mut FOO {
+        CLONES.with(|c| c.set(c.get() + 1));
+        ptr
+    }
+}
+
+foreign_type! {
+    pub unsafe type CountedFoo {
+        type CType = foo_sys::FOO;
+        fn drop = counted_sys::foo_drop;
+        fn clone = counted_sys::foo_clone;
+    }
+}
+
+#[test]
+fn drop_clone_consts() {
+    use foreign_types::ForeignType;
+
+    // Duplicates and frees a raw pointer without knowing the concrete type.
+    unsafe fn dup_and_free<T: ForeignType>(ptr: *mut T::CType) -> bool {
+        match T::CLONE {
+            Some(clone) => {
+                (T::DROP)(clone(ptr));
+                true
+            }
+            None => false,
+        }
+    }
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+
+    assert!(unsafe { dup_and_free::<CountedFoo>(ptr) });
+    assert_eq!(counted_sys::CLONES.with(|c| c.get()), 1);
+    assert_eq!(counted_sys::DROPS.with(|d| d.get()), 1);
+
+    assert!(!unsafe { dup_and_free::<FooNoClone>(ptr) });
+}
+
+#[test]
+fn thread_safe_marker_traits() {
+    use foreign_types::{SendForeignType, SyncForeignType};
+
+    fn assert_send<T: Send + ?Sized>() {}
+    fn assert_sync<T: Sync + ?Sized>() {}
+
+    fn send<T: SendForeignType>() {
+        assert_send::<T::Ref>();
+    }
+
+    fn thread_safe<T: SendForeignType + SyncForeignType>() {
+        assert_send::<T::Ref>();
+        assert_sync::<T::Ref>();
+    }
+
+    send::<Foo<'static, u8>>();
+    thread_safe::<Foo<'static, u8>>();
+}
+
+#[test]
+fn cast() {
+    use foreign_types::{ForeignType, ForeignTypeRef};
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+
+    let mut foo = unsafe { FooNoClone::from_ptr(ptr) };
+    let foo_ref: &FooRef<'static, u8> = unsafe { foo.cast_ref() };
+    assert_eq!(foo_ref.as_ptr(), ptr);
+    let foo_mut: &mut FooRef<'static, u8> = unsafe { foo.cast_mut() };
+    assert_eq!(foo_mut.as_ptr(), ptr);
+
+    let foo: FooClosure = unsafe { foo.cast() };
+    assert_eq!(foo.as_ptr(), ptr);
+}
+
+mod setter_sys {
+    use crate::foo_sys::FOO;
+
+    #[allow(clippy::upper_case_acronyms)]
+    pub struct BAR {
+        pub owned: *mut FOO,
+        pub copied: *mut FOO,
+    }
+
+    pub unsafe fn bar_set0_foo(bar: *mut BAR, value: *mut FOO) {
+        (*bar).owned = value;
+    }
+
+    pub unsafe fn bar_set1_foo(bar: *mut BAR, value: *mut FOO) -> i32 {
+        (*bar).copied = value;
+        1
+    }
+}
+
+foreign_type! {
+    pub unsafe type SetterBar {
+        type CType = setter_sys::BAR;
+        fn drop = |_| {};
+        fn set owned(takes): CountedFoo = setter_sys::bar_set0_foo;
+        fn get copied: CountedFooRef = |p: *mut setter_sys::BAR| (*p).copied;
+        /// Sets the copied Foo.
+        fn set copied(copies): CountedFooRef -> i32 = setter_sys::bar_set1_foo;
+    }
+}
+
+#[test]
+fn setters() {
+    use foreign_types::{ForeignType, ForeignTypeRef};
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+
+    // A static, since `track-ownership` rejects stack addresses freed by earlier tests.
+    static mut BAR: setter_sys::BAR = setter_sys::BAR {
+        owned: std::ptr::null_mut(),
+        copied: std::ptr::null_mut(),
+    };
+    // Taking the address of a `static mut` is only safe on newer compilers.
+    #[allow(unused_unsafe)]
+    let bar = unsafe { std::ptr::addr_of_mut!(BAR) };
+    let bar_ref = unsafe { SetterBarRef::from_ptr_mut(bar) };
+
+    let foo = unsafe { CountedFoo::from_ptr(ptr) };
+    assert_eq!(bar_ref.set_copied(&foo), 1);
+    bar_ref.set_owned(foo);
+    assert_eq!(bar_ref.copied().as_ptr(), ptr);
+    assert_eq!(counted_sys::DROPS.with(|d| d.get()), 0);
+
+    assert_eq!(unsafe { (*bar).owned }, ptr);
+}
+
+mod shared_sys {
+    use std::cell::Cell;
+
+    use crate::foo_sys::FOO;
+
+    thread_local! {
+        pub static REFS: Cell<usize> = const { Cell::new(1) };
+    }
+
+    pub unsafe fn foo_ref(ptr: *mut FOO) -> *mut FOO {
+        REFS.with(|r| r.set(r.get() + 1));
+        ptr
+    }
+
+    pub unsafe fn foo_unref(_: *mut FOO) {
+        REFS.with(|r| r.set(r.get() - 1));
+    }
+}
+
+foreign_type! {
+    pub unsafe type SharedFoo {
+        type CType = foo_sys::FOO;
+        fn drop = shared_sys::foo_unref;
+    }
+
+    pub unsafe type AutoSharedFoo {
+        type CType = foo_sys::FOO;
+        fn ref = shared_sys::foo_ref;
+        fn unref = shared_sys::foo_unref;
+    }
+}
+
+unsafe impl foreign_types::RefCounted for SharedFoo {
+    unsafe fn retain(ptr: *mut foo_sys::FOO) {
+        shared_sys::foo_ref(ptr);
+    }
+
+    unsafe fn is_unique(_: *mut foo_sys::FOO) -> bool {
+        shared_sys::REFS.with(|r| r.get()) == 1
+    }
+}
+
+#[test]
+fn shared() {
+    use foreign_types::{ForeignType, ForeignTypeRef, Shared};
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+    let refs = || shared_sys::REFS.with(|r| r.get());
+
+    let shared = Shared::new(unsafe { SharedFoo::from_ptr(ptr) });
+    let other = shared.clone();
+    assert_eq!(refs(), 2);
+    assert_eq!(Shared::as_ptr(&other), ptr);
+    assert_eq!(other.as_ptr(), ptr);
+
+    let shared = Shared::try_unwrap(shared).err().unwrap();
+    drop(other);
+    assert_eq!(refs(), 1);
+    let foo = Shared::try_unwrap(shared).ok().unwrap();
+    assert_eq!(foo.as_ptr(), ptr);
+    drop(foo);
+    assert_eq!(refs(), 0);
+
+    shared_sys::REFS.with(|r| r.set(1));
+    let shared = Shared::from(unsafe { AutoSharedFoo::from_ptr(ptr) });
+    let other = shared.clone();
+    assert_eq!(refs(), 2);
+    drop((shared, other));
+    assert_eq!(refs(), 0);
+}
+
+mod shallow_sys {
+    use std::cell::Cell;
+
+    use crate::foo_sys::FOO;
+
+    thread_local! {
+        pub static SHALLOW: Cell<usize> = const { Cell::new(0) };
+        pub static DEEP: Cell<usize> = const { Cell::new(0) };
+    }
+
+    pub unsafe extern "C" fn foo_free(_: *mut FOO) {
+        SHALLOW.with(|s| s.set(s.get() + 1));
+    }
+
+    pub unsafe extern "C" fn foo_pop_free(_: *mut FOO) {
+        DEEP.with(|d| d.set(d.get() + 1));
+    }
+}
+
+foreign_type! {
+    pub unsafe type ShallowFoo {
+        type CType = foo_sys::FOO;
+        fn drop = shallow_sys::foo_pop_free;
+        fn drop_shallow = shallow_sys::foo_free;
+        fn clone = foo_sys::foo_clone;
+    }
+
+    pub unsafe type ShallowClosureFoo {
+        type CType = foo_sys::FOO;
+        fn drop = |p| shallow_sys::foo_pop_free(p);
+        fn drop_shallow = |p| shallow_sys::foo_free(p);
+    }
+}
+
+#[test]
+fn shallow_drop() {
+    use foreign_types::ForeignType;
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+    let counts = || {
+        (
+            shallow_sys::SHALLOW.with(|s| s.get()),
+            shallow_sys::DEEP.with(|d| d.get()),
+        )
+    };
+
+    drop(unsafe { ShallowFoo::from_ptr(ptr) });
+    assert_eq!(counts(), (0, 1));
+    drop(unsafe { ShallowFoo::from_ptr_deep(ptr) });
+    assert_eq!(counts(), (0, 2));
+
+    let shallow = unsafe { ShallowFoo::from_ptr_shallow(ptr) };
+    let clone = shallow.clone();
+    drop((shallow, clone));
+    assert_eq!(counts(), (2, 2));
+
+    drop(unsafe { ShallowClosureFoo::from_ptr_shallow(ptr) });
+    drop(unsafe { ShallowClosureFoo::from_ptr(ptr) });
+    assert_eq!(counts(), (3, 3));
+}
+
+#[allow(clippy::upper_case_acronyms, non_camel_case_types)]
+pub enum FOO_const {}
+
+foreign_type! {
+    pub unsafe type ConstFoo {
+        type CType = foo_sys::FOO;
+        type CTypeConst = FOO_const;
+        fn drop = foo_sys::foo_drop;
+    }
+
+    pub unsafe type ConstBorrowFoo<'a> {
+        type CType = foo_sys::FOO;
+        type CTypeConst = FOO_const;
+        type PhantomData = &'a ();
+        fn drop = foo_sys::foo_drop;
+    }
+}
+
+#[test]
+fn ctype_const() {
+    use foreign_types::ForeignTypeRef;
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *const FOO_const;
+
+    let foo = unsafe { ConstFooRef::from_const_ptr(ptr) };
+    assert_eq!(foo.as_const_ptr(), ptr);
+    assert_eq!(foo.as_ptr() as *const FOO_const, ptr);
+
+    let foo = unsafe { ConstBorrowFooRef::<'static>::from_const_ptr(ptr) };
+    assert_eq!(foo.as_const_ptr(), ptr);
+}
+
+foreign_type! {
+    pub unsafe type UnusedFoo {
+        type CType = foo_sys::FOO;
+        const MUST_USE = false;
+        fn drop = foo_sys::foo_drop;
+    }
+}
+
+#[test]
+#[deny(unused_must_use)]
+fn must_use_opt_out() {
+    use foreign_types::ForeignType;
+
+    static STORAGE: u8 = 0;
+    unsafe { UnusedFoo::from_ptr(&STORAGE as *const u8 as *mut _) };
+}
+
+#[test]
+fn any_foreign_type() {
+    use foreign_types::{AnyForeignType, ForeignType};
+    use std::ffi::c_void;
+
+    static STORAGE: [u8; 2] = [0; 2];
+    let ptrs = [
+        &STORAGE[0] as *const u8 as *mut foo_sys::FOO,
+        &STORAGE[1] as *const u8 as *mut foo_sys::FOO,
+    ];
+
+    let values: Vec<Box<dyn AnyForeignType>> = unsafe {
+        vec![
+            Box::new(<Foo<'static, u8>>::from_ptr(ptrs[0])),
+            Box::new(FooNoClone::from_ptr(ptrs[1])),
+        ]
+    };
+    assert_eq!(values[0].as_any_ptr(), ptrs[0] as *mut c_void);
+    assert_eq!(values[1].as_any_ptr(), ptrs[1] as *mut c_void);
+    assert!(values[0].type_name().contains("Foo<"));
+    assert!(values[1].type_name().ends_with("FooNoClone"));
+}
+
+foreign_type! {
+    pub mod ossl;
+    const STRIP_PREFIX = "Ossl";
+    const REF_SUFFIX = "Borrowed";
+
+    pub unsafe type OsslFoo {
+        type CType = foo_sys::FOO;
+        fn drop = foo_sys::foo_drop;
+        fn clone = foo_sys::foo_clone;
+    }
+
+    pub unsafe type OsslBar {
+        type CType = getter_sys::BAR;
+        fn drop = |_| {};
+        fn get foo: FooBorrowed = getter_sys::bar_get_foo;
+    }
+
+    pub static ref OSSL_DEFAULT: FooBorrowed = {
+        static STORAGE: u8 = 0;
+        &STORAGE as *const u8 as *mut foo_sys::FOO
+    };
+}
+
+#[test]
+fn module_and_naming() {
+    use foreign_types::{ForeignType, ForeignTypeRef};
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+
+    let foo = unsafe { ossl::Foo::from_ptr(ptr) };
+    let borrowed: &ossl::FooBorrowed = &foo;
+    assert_eq!(borrowed.as_ptr(), ptr);
+    assert_eq!(foo.clone().as_ptr(), ptr);
+
+    let mut bar = getter_sys::BAR { foo: 0 };
+    let foo_ptr = &mut bar.foo as *mut u8 as *mut foo_sys::FOO;
+    let bar = unsafe { ossl::BarBorrowed::from_ptr_mut(&mut bar) };
+    assert_eq!(bar.foo().as_ptr(), foo_ptr);
+
+    assert!(!ossl::OSSL_DEFAULT.as_ptr().is_null());
+}
+
+mod map_sys {
+    use crate::counted_sys;
+    use crate::foo_sys::FOO;
+
+    #[allow(clippy::upper_case_acronyms)]
+    pub struct DICT {
+        pub owning: bool,
+        pub entries: Vec<(*mut FOO, *mut FOO)>,
+    }
+
+    pub unsafe fn dict_new(owning: bool) -> *mut DICT {
+        Box::into_raw(Box::new(DICT {
+            owning,
+            entries: vec![],
+        }))
+    }
+
+    pub unsafe fn dict_free(dict: *mut DICT) {
+        let dict = Box::from_raw(dict);
+        if dict.owning {
+            for (key, value) in dict.entries {
+                counted_sys::foo_drop(key);
+                counted_sys::foo_drop(value);
+            }
+        }
+    }
+
+    pub unsafe fn dict_insert(dict: *mut DICT, key: *mut FOO, value: *mut FOO) {
+        match (*dict).entries.iter_mut().find(|e| e.0 == key) {
+            Some(entry) => entry.1 = value,
+            None => (*dict).entries.push((key, value)),
+        }
+    }
+
+    pub unsafe fn dict_lookup(dict: *mut DICT, key: *mut FOO) -> *mut FOO {
+        match (*dict).entries.iter().find(|e| e.0 == key) {
+            Some(entry) => entry.1,
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    pub unsafe fn dict_size(dict: *mut DICT) -> u32 {
+        (*dict).entries.len() as u32
+    }
+
+    pub unsafe fn dict_foreach(dict: *mut DICT, f: &mut dyn FnMut(*mut FOO, *mut FOO)) {
+        for &(key, value) in &(*dict).entries {
+            f(key, value);
+        }
+    }
+}
+
+foreign_type! {
+    /// A map owning its entries.
+    pub unsafe type map OwningMap<CountedFoo => CountedFoo> {
+        type CType = map_sys::DICT;
+        fn new = || map_sys::dict_new(true);
+        fn drop = map_sys::dict_free;
+        fn insert(takes) = map_sys::dict_insert;
+        fn get = map_sys::dict_lookup;
+        fn len = map_sys::dict_size;
+        fn for_each = map_sys::dict_foreach;
+    }
+
+    pub unsafe type map CopyingMap<CountedFoo => CountedFoo> {
+        type CType = map_sys::DICT;
+        fn new = || map_sys::dict_new(false);
+        fn drop = map_sys::dict_free;
+        fn insert(copies) = map_sys::dict_insert;
+        fn get = map_sys::dict_lookup;
+        fn len = map_sys::dict_size;
+        fn for_each = map_sys::dict_foreach;
+    }
+
+    // The items of a map may be given in any order.
+    pub unsafe type map NegativeLenMap<CountedFoo => CountedFoo> {
+        fn len = |_| -1i32;
+        fn get = map_sys::dict_lookup;
+        fn insert(copies) = map_sys::dict_insert;
+        type CType = map_sys::DICT;
+        fn for_each = map_sys::dict_foreach;
+        fn drop = map_sys::dict_free;
+        fn new = || map_sys::dict_new(false);
+    }
+}
+
+#[test]
+fn map() {
+    use foreign_types::{ForeignType, ForeignTypeRef};
+
+    static STORAGE: [u8; 3] = [0; 3];
+    let key = &STORAGE[0] as *const u8 as *mut foo_sys::FOO;
+    let value = &STORAGE[1] as *const u8 as *mut foo_sys::FOO;
+    let missing = &STORAGE[2] as *const u8 as *mut foo_sys::FOO;
+    let drops = || counted_sys::DROPS.with(|d| d.get());
+
+    let mut map = OwningMap::new();
+    assert!(map.is_empty());
+    unsafe { map.insert(CountedFoo::from_ptr(key), CountedFoo::from_ptr(value)) };
+    assert_eq!(drops(), 0);
+    assert_eq!(map.len(), 1);
+
+    let key_ref = unsafe { CountedFooRef::from_ptr(key) };
+    assert_eq!(map.get(key_ref).unwrap().as_ptr(), value);
+    assert!(!map.contains_key(unsafe { CountedFooRef::from_ptr(missing) }));
+
+    #[cfg(feature = "std")]
+    {
+        let entries = map
+            .iter()
+            .map(|(k, v)| (k.as_ptr(), v.as_ptr()))
+            .collect::<Vec<_>>();
+        assert_eq!(entries, [(key, value)]);
+    }
+
+    drop(map);
+    assert_eq!(drops(), 2);
+
+    let mut map = CopyingMap::default();
+    unsafe { map.insert(CountedFoo::from_ptr(key), CountedFoo::from_ptr(value)) };
+    assert_eq!(drops(), 4);
+    assert_eq!(map.len(), 1);
+    drop(map);
+    assert_eq!(drops(), 4);
+
+    let map = NegativeLenMap::new();
+    assert_eq!(map.len(), 0);
+    assert!(map.is_empty());
+}
+
+foreign_type! {
+    /// A Foo which is never owned.
+    pub unsafe type ref BorrowedFoo: Sync + Send {
+        type CType = foo_sys::FOO;
+    }
+
+    pub static ref BORROWED_FOO: BorrowedFoo = {
+        static STORAGE: u8 = 0;
+        &STORAGE as *const u8 as *mut foo_sys::FOO
+    };
+}
+
+#[test]
+fn ref_only() {
+    use foreign_types::ForeignTypeRef;
+
+    fn assert_thread_safe<T: Send + Sync + ?Sized>() {}
+    assert_thread_safe::<BorrowedFoo>();
+
+    let ptr = BORROWED_FOO.as_ptr();
+    let foo = unsafe { BorrowedFoo::from_ptr(ptr) };
+    assert_eq!(foo.as_ptr(), ptr);
+
+    let foo = unsafe { BorrowedFoo::from_ptr_const(ptr) };
+    assert_eq!(foo.as_ptr(), ptr);
+}
+
+mod close_sys {
+    use std::cell::Cell;
+
+    use crate::foo_sys::FOO;
+
+    thread_local! {
+        pub static CLOSES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    pub async unsafe fn foo_close(_: *mut FOO) {
+        CLOSES.with(|c| c.set(c.get() + 1));
+    }
+}
+
+foreign_type! {
+    pub unsafe type ClosingFoo {
+        type CType = foo_sys::FOO;
+        fn drop = counted_sys::foo_drop;
+        fn close = close_sys::foo_close;
+    }
+}
+
+#[test]
+fn close() {
+    use foreign_types::ForeignType;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::ptr;
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    fn poll_once<F: Future>(future: F) -> Poll<F::Output> {
+        unsafe fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(ptr::null(), &VTABLE)
+        }
+        unsafe fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+        let waker = unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) };
+        let mut future = Box::pin(future);
+        Pin::as_mut(&mut future).poll(&mut Context::from_waker(&waker))
+    }
+
+    static STORAGE: [u8; 2] = [0; 2];
+    let closes = || close_sys::CLOSES.with(|c| c.get());
+    let drops = || counted_sys::DROPS.with(|d| d.get());
+
+    let foo = unsafe { ClosingFoo::from_ptr(&STORAGE[0] as *const u8 as *mut foo_sys::FOO) };
+    assert!(poll_once(foo.close()).is_ready());
+    assert_eq!(closes(), 1);
+    assert_eq!(drops(), 0);
+
+    let foo = unsafe { ClosingFoo::from_ptr(&STORAGE[1] as *const u8 as *mut foo_sys::FOO) };
+    drop(foo);
+    assert_eq!(closes(), 1);
+    assert_eq!(drops(), 1);
+}
+
+#[cfg(all(feature = "std", debug_assertions))]
+#[test]
+fn drop_unclosed_warns() {
+    use foreign_types::ForeignType;
+    use std::env;
+    use std::process::Command;
+
+    const CHILD: &str = "FOREIGN_TYPES_DROP_UNCLOSED_CHILD";
+
+    if env::var_os(CHILD).is_some() {
+        static STORAGE: u8 = 0;
+        let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+        drop(unsafe { ClosingFoo::from_ptr(ptr) });
+        return;
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["drop_unclosed_warns", "--exact", "--nocapture"])
+        .env(CHILD, "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: `ClosingFoo` was dropped without being closed"),
+        "{}",
+        stderr
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn abort_on_panic() {
+    assert_eq!(foreign_types::panic::abort_on_panic("test", || 1), 1);
+}
+
+#[cfg(feature = "abort-on-panic")]
+mod abort_on_panic {
+    use foreign_types::{foreign_type, ForeignType};
+    use std::env;
+    use std::process::Command;
+
+    use crate::foo_sys;
+
+    foreign_type! {
+        pub unsafe type PanickingFoo {
+            type CType = foo_sys::FOO;
+            fn drop = |_| panic!("drop failed");
+        }
+    }
+
+    #[test]
+    fn drop_aborts() {
+        const CHILD: &str = "FOREIGN_TYPES_ABORT_ON_PANIC_CHILD";
+
+        if env::var_os(CHILD).is_some() {
+            static STORAGE: u8 = 0;
+            let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+            drop(unsafe { PanickingFoo::from_ptr(ptr) });
+            return;
+        }
+
+        let output = Command::new(env::current_exe().unwrap())
+            .args(["abort_on_panic::drop_aborts", "--exact", "--nocapture"])
+            .env(CHILD, "1")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("fatal: panic in PanickingFoo::drop: drop failed"),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[cfg(feature = "mock")]
+mod mock {
+    use foreign_types::mock::{self, MOCK};
+    use foreign_types::{foreign_type, ForeignType, ForeignTypeRef};
+
+    foreign_type! {
+        pub unsafe type MockFoo {
+            type CType = MOCK;
+            fn drop = mock::mock_free;
+            fn clone = mock::mock_dup;
+            fn get child: MockFooRef = mock::mock_get0_child;
+            fn set child(copies): MockFooRef = mock::mock_set1_child;
+        }
+    }
+
+    impl MockFooRef {
+        fn value(&self) -> i32 {
+            unsafe { mock::mock_get_value(self.as_ptr()) }
+        }
+
+        fn set_value(&mut self, value: i32) {
+            unsafe { mock::mock_set_value(self.as_ptr(), value) }
+        }
+    }
+
+    #[test]
+    fn accessors() {
+        let mut foo = unsafe { MockFoo::from_ptr(mock::mock_new()) };
+        let mut child = unsafe { MockFoo::from_ptr(mock::mock_new()) };
+        child.set_value(2);
+        foo.set_child(&child);
+        child.set_value(3);
+        assert_eq!(foo.value(), 0);
+        assert_eq!(foo.child().value(), 2);
+        assert_eq!(mock::live(), 3);
+
+        drop(foo);
+        drop(child);
+        assert_eq!(mock::live(), 0);
+        assert_eq!(mock::allocated(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "mock_get_value called with a freed MOCK")]
+    fn use_after_free() {
+        let foo = unsafe { MockFoo::from_ptr(mock::mock_new()) };
+        let ptr = foo.as_ptr();
+        drop(foo);
+        unsafe { mock::mock_get_value(ptr) };
+    }
+}
+
+mod shim_sys {
+    use std::cell::Cell;
+
+    #[allow(clippy::upper_case_acronyms)]
+    pub struct FOO {
+        pub freed_with: Cell<i32>,
+        pub copy: *mut FOO,
+    }
+
+    pub unsafe fn foo_free_with_flags(flags: i32, ptr: *mut FOO) {
+        (*ptr).freed_with.set(flags);
+    }
+}
+
+foreign_type! {
+    pub unsafe type ShimFoo {
+        type CType = shim_sys::FOO;
+        fn drop = |p| unsafe { shim_sys::foo_free_with_flags(1, p) };
+        fn clone = |p| (*p).copy;
+    }
+}
+
+#[test]
+fn closure_shims() {
+    use foreign_types::ForeignType;
+    use std::cell::Cell;
+    use std::ptr;
+
+    let mut copy = shim_sys::FOO {
+        freed_with: Cell::new(0),
+        copy: ptr::null_mut(),
+    };
+    let mut original = shim_sys::FOO {
+        freed_with: Cell::new(0),
+        copy: &mut copy,
+    };
+
+    let foo = unsafe { ShimFoo::from_ptr(&mut original) };
+    let foo_copy = foo.clone();
+    assert_eq!(foo_copy.as_ptr(), &mut copy as *mut _);
+    drop(foo);
+    drop(foo_copy);
+    assert_eq!(original.freed_with.get(), 1);
+    assert_eq!(copy.freed_with.get(), 1);
+}
+
+#[test]
+fn swap_and_replace() {
+    use foreign_types::ForeignType;
+
+    static STORAGE: [u8; 3] = [0; 3];
+    let ptrs = [0, 1, 2].map(|i| &STORAGE[i] as *const u8 as *mut foo_sys::FOO);
+    let drops = || counted_sys::DROPS.with(|d| d.get());
+
+    let mut a = unsafe { CountedFoo::from_ptr(ptrs[0]) };
+    let mut b = unsafe { CountedFoo::from_ptr(ptrs[1]) };
+    a.swap(&mut b);
+    assert_eq!(a.as_ptr(), ptrs[1]);
+    assert_eq!(b.as_ptr(), ptrs[0]);
+
+    let old = a.replace(b);
+    assert_eq!(old.as_ptr(), ptrs[1]);
+    assert_eq!(a.as_ptr(), ptrs[0]);
+    assert_eq!(drops(), 0);
+
+    let older = unsafe { a.replace_ptr(ptrs[2]) };
+    assert_eq!(older.as_ptr(), ptrs[0]);
+    assert_eq!(a.as_ptr(), ptrs[2]);
+    assert_eq!(drops(), 0);
+
+    drop((a, old, older));
+    assert_eq!(drops(), 3);
+}
+
+mod stack_sys {
+    use std::cell::Cell;
+
+    use crate::foo_sys::FOO;
+
+    thread_local! {
+        pub static FREED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[allow(clippy::upper_case_acronyms)]
+    pub struct STACK {
+        pub items: [*mut FOO; 3],
+        pub len: usize,
+    }
+
+    pub unsafe fn stack_free(stack: *mut STACK) {
+        let stack = &*stack;
+        for &item in &stack.items[..stack.len] {
+            crate::counted_sys::foo_drop(item);
+        }
+        FREED.with(|f| f.set(f.get() + 1));
+    }
+
+    pub unsafe fn stack_pop(stack: *mut STACK) -> *mut FOO {
+        if (*stack).len == 0 {
+            return std::ptr::null_mut();
+        }
+        (*stack).len -= 1;
+        (*stack).items[(*stack).len]
+    }
+}
+
+foreign_type! {
+    pub unsafe type FooStack {
+        type CType = stack_sys::STACK;
+        fn drop = stack_sys::stack_free;
+        fn pop: CountedFoo = stack_sys::stack_pop;
+    }
+}
+
+#[test]
+fn pop() {
+    use foreign_types::ForeignType;
+
+    static STORAGE: [u8; 3] = [0; 3];
+    let items = [0, 1, 2].map(|i| &STORAGE[i] as *const u8 as *mut foo_sys::FOO);
+    let drops = || counted_sys::DROPS.with(|d| d.get());
+    let freed = || stack_sys::FREED.with(|f| f.get());
+
+    let mut stack = stack_sys::STACK { items, len: 3 };
+    let popped = unsafe { FooStack::from_ptr(&mut stack) }
+        .into_iter()
+        .map(|foo| foo.as_ptr())
+        .collect::<Vec<_>>();
+    assert_eq!(popped, [items[2], items[1], items[0]]);
+    assert_eq!(drops(), 3);
+    assert_eq!(freed(), 1);
+
+    let mut stack = stack_sys::STACK { items, len: 3 };
+    let mut iter = unsafe { FooStack::from_ptr(&mut stack) }.into_iter();
+    assert_eq!(iter.next().unwrap().as_ptr(), items[2]);
+    drop(iter);
+    assert_eq!(drops(), 6);
+    assert_eq!(freed(), 2);
+}
+
+#[test]
+fn from_ptr_of() {
+    use foreign_types::ForeignTypeRef;
+
+    let mut bar = getter_sys::BAR { foo: 0 };
+    let ptr = &mut bar.foo as *mut u8 as *mut foo_sys::FOO;
+
+    let foo = unsafe { FooRef::<u8>::from_ptr_of(&bar, ptr) };
+    assert_eq!(foo.as_ptr(), ptr);
+    assert!(unsafe { FooRef::<u8>::from_ptr_opt_of(&bar, std::ptr::null_mut()) }.is_none());
+
+    let foo = unsafe { FooRef::<u8>::from_ptr_mut_of(&mut bar, ptr) };
+    assert_eq!(foo.as_ptr(), ptr);
+}
+
+foreign_type! {
+    pub unsafe type AutoFoo<T>: auto {
+        type CType = foo_sys::FOO;
+        type PhantomData = T;
+        fn drop = foo_sys::foo_drop;
+    }
+
+    pub unsafe type ref AutoBorrowedFoo: auto {
+        type CType = foo_sys::FOO;
+    }
+}
+
+#[test]
+fn auto_marker_traits() {
+    fn assert_send_sync<T: Send + Sync + ?Sized>() {}
+
+    assert_send_sync::<AutoFoo<u8>>();
+    assert_send_sync::<AutoFooRef<u8>>();
+    assert_send_sync::<AutoBorrowedFoo>();
+}
+
+foreign_type! {
+    pub unsafe type DebugFoo {
+        type CType = foo_sys::FOO;
+        fn drop = foo_sys::foo_drop;
+        impl Debug;
+    }
+}
+
+#[test]
+fn debug_pointer() {
+    use foreign_types::ForeignType;
+
+    #[derive(Debug)]
+    struct Holder {
+        #[allow(dead_code)]
+        foo: DebugFoo,
+    }
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+
+    let foo = unsafe { DebugFoo::from_ptr(ptr) };
+    let expected = format!("DebugFoo({:?})", ptr);
+    assert_eq!(format!("{:?}", foo), expected);
+    assert_eq!(format!("{:?}", &*foo), expected);
+    assert_eq!(
+        format!("{:?}", Holder { foo }),
+        format!("Holder {{ foo: {} }}", expected)
+    );
+}
+
+#[test]
+fn raw_conversions() {
+    use foreign_types::raw::{NullPtrError, OwnedPtr};
+    use std::convert::TryFrom;
+    use std::ptr::NonNull;
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+
+    let foo = FooNoClone::try_from(unsafe { OwnedPtr::new(ptr) }).unwrap();
+    assert_eq!(NonNull::from(foo).as_ptr(), ptr);
+
+    let null = unsafe { OwnedPtr::new(std::ptr::null_mut()) };
+    assert!(null.is_null());
+    assert_eq!(FooNoClone::try_from(null).err(), Some(NullPtrError));
+}
+
+mod buf_sys {
+    use std::os::raw::{c_int, c_uchar};
+
+    #[allow(clippy::upper_case_acronyms)]
+    pub struct BUF {
+        pub data: [c_uchar; 4],
+        pub len: c_int,
+    }
+
+    pub unsafe fn buf_length(buf: *mut BUF) -> c_int {
+        (*buf).len
+    }
+
+    pub unsafe fn buf_data(buf: *mut BUF) -> *mut c_uchar {
+        if (*buf).len == 0 {
+            std::ptr::null_mut()
+        } else {
+            (*buf).data.as_mut_ptr()
+        }
+    }
+}
+
+foreign_type! {
+    pub unsafe type Buf {
+        type CType = buf_sys::BUF;
+        fn drop = |_| {};
+        fn len = buf_sys::buf_length;
+        fn data = buf_sys::buf_data;
+    }
+}
+
+#[test]
+fn bytes() {
+    use foreign_types::ForeignType;
+
+    let mut raw = buf_sys::BUF {
+        data: *b"abcd",
+        len: 3,
+    };
+    let mut buf = unsafe { Buf::from_ptr(&mut raw) };
+    assert_eq!(buf.as_bytes(), b"abc");
+    buf.as_bytes_mut()[0] = b'x';
+    assert_eq!(buf.as_bytes(), b"xbc");
+    drop(buf);
+    assert_eq!(raw.data, *b"xbcd");
+
+    let mut raw = buf_sys::BUF {
+        data: [0; 4],
+        len: 0,
+    };
+    let mut buf = unsafe { Buf::from_ptr(&mut raw) };
+    assert!(buf.as_bytes().is_empty());
+    assert!(buf.as_bytes_mut().is_empty());
+
+    let mut raw = buf_sys::BUF {
+        data: *b"abcd",
+        len: -1,
+    };
+    let mut buf = unsafe { Buf::from_ptr(&mut raw) };
+    assert!(buf.as_bytes().is_empty());
+    assert!(buf.as_bytes_mut().is_empty());
+}
+
+#[cfg(feature = "std")]
+mod batch_sys {
+    use std::cell::RefCell;
+    use std::os::raw::c_int;
+
+    use crate::foo_sys::FOO;
+
+    thread_local! {
+        pub static BATCHES: RefCell<Vec<Vec<*mut FOO>>> = const { RefCell::new(Vec::new()) };
+    }
+
+    pub unsafe fn foo_free_all(foos: *mut *mut FOO, n: c_int) {
+        let batch = std::slice::from_raw_parts(foos, n as usize).to_vec();
+        BATCHES.with(|b| b.borrow_mut().push(batch));
+    }
+}
+
+#[cfg(feature = "std")]
+foreign_type! {
+    pub unsafe type BatchFoo {
+        type CType = foo_sys::FOO;
+        fn drop = counted_sys::foo_drop;
+        fn drop_many = batch_sys::foo_free_all;
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn drop_many() {
+    use foreign_types::ForeignType;
+
+    static STORAGE: [u8; 3] = [0; 3];
+    let ptrs = [0, 1, 2].map(|i| &STORAGE[i] as *const u8 as *mut foo_sys::FOO);
+    let batches = || batch_sys::BATCHES.with(|b| b.borrow().clone());
+
+    BatchFoo::drop_many(vec![]);
+    assert!(batches().is_empty());
+
+    let foos = ptrs
+        .iter()
+        .map(|&ptr| unsafe { BatchFoo::from_ptr(ptr) })
+        .collect();
+    BatchFoo::drop_many(foos);
+    assert_eq!(batches(), [ptrs.to_vec()]);
+    assert_eq!(counted_sys::DROPS.with(|d| d.get()), 0);
+}
+
+foreign_type! {
+    pub unsafe type UnwindFoo: Sync + Send + UnwindSafe + RefUnwindSafe {
+        type CType = foo_sys::FOO;
+        fn drop = foo_sys::foo_drop;
+    }
+
+    pub unsafe type NotUnwindFoo<T>: auto + !UnwindSafe + !RefUnwindSafe {
+        type CType = foo_sys::FOO;
+        type PhantomData = T;
+        fn drop = foo_sys::foo_drop;
+    }
+}
+
+#[test]
+fn unwind_safety() {
+    use std::panic::{RefUnwindSafe, UnwindSafe};
+
+    fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe + ?Sized>() {}
+    fn assert_send_sync<T: Send + Sync + ?Sized>() {}
+
+    assert_unwind_safe::<UnwindFoo>();
+    assert_unwind_safe::<UnwindFooRef>();
+    assert_unwind_safe::<&UnwindFooRef>();
+    assert_send_sync::<NotUnwindFoo<u8>>();
+    assert_send_sync::<NotUnwindFooRef<u8>>();
+}
+
+mod edit_sys {
+    use std::cell::RefCell;
+
+    use crate::foo_sys::FOO;
+
+    thread_local! {
+        pub static CALLS: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(call: &'static str) {
+        CALLS.with(|c| c.borrow_mut().push(call));
+    }
+
+    pub unsafe fn foo_begin(_: *mut FOO) {
+        record("begin");
+    }
+
+    pub unsafe fn foo_commit(_: *mut FOO) {
+        record("commit");
+    }
+
+    pub unsafe fn foo_abort(_: *mut FOO) {
+        record("abort");
+    }
+
+    pub fn take() -> Vec<&'static str> {
+        CALLS.with(|c| c.take())
+    }
+}
+
+foreign_type! {
+    pub unsafe type EditFoo<T> {
+        type CType = foo_sys::FOO;
+        type PhantomData = T;
+        fn drop = foo_sys::foo_drop;
+        fn begin_edit = edit_sys::foo_begin;
+        fn commit_edit = edit_sys::foo_commit;
+        fn rollback_edit = edit_sys::foo_abort;
+    }
+
+    pub unsafe type CommitFoo {
+        type CType = foo_sys::FOO;
+        fn drop = foo_sys::foo_drop;
+        fn begin_edit = edit_sys::foo_begin;
+        fn commit_edit = edit_sys::foo_commit;
+    }
+}
+
+#[test]
+fn edit_guard() {
+    use foreign_types::{ForeignType, ForeignTypeRef};
+
+    static STORAGE: u8 = 0;
+    let ptr = &STORAGE as *const u8 as *mut foo_sys::FOO;
+
+    let mut foo = unsafe { EditFoo::<u8>::from_ptr(ptr) };
+    let edit: EditFooEditGuard<'_, u8> = foo.edit();
+    assert_eq!(edit.as_ptr(), ptr);
+    edit.finish();
+    assert_eq!(edit_sys::take(), ["begin", "commit"]);
+
+    drop(foo.edit());
+    assert_eq!(edit_sys::take(), ["begin", "abort"]);
+    drop(foo);
+
+    let mut foo = unsafe { CommitFoo::from_ptr(ptr) };
+    drop(foo.edit());
+    assert_eq!(edit_sys::take(), ["begin", "commit"]);
 }